@@ -21,7 +21,10 @@ use futures::{sink::SinkExt, stream::StreamExt};
 use std::{net::SocketAddr, sync::Arc};
 use tokio::sync::broadcast;
 
-use crate::serv::{client_handler::client_call_handler, server_mngr::server_mngr_handler};
+use crate::serv::{
+    client_handler::client_call_handler,
+    server_mngr::{server_mngr_handler, DuplicateClientPolicy, SERVER_MNGR},
+};
 
 #[tokio::main]
 async fn main() {
@@ -39,6 +42,31 @@ async fn main() {
         .filter_level(log::LevelFilter::Debug)
         .init();
 
+    // 同一client_id重复连接的处理策略: reject | kick_old | multi_device
+    if let Ok(policy) = std::env::var("DUPLICATE_CLIENT_POLICY") {
+        match policy.parse::<DuplicateClientPolicy>() {
+            Ok(policy) => SERVER_MNGR.lock().await.set_duplicate_policy(policy),
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // 客户端之间呼叫的振铃超时（秒）
+    if let Ok(secs) = std::env::var("CALL_RING_TIMEOUT_SECS") {
+        match secs.parse::<u64>() {
+            Ok(secs) => SERVER_MNGR
+                .lock()
+                .await
+                .set_ring_timeout(std::time::Duration::from_secs(secs)),
+            Err(e) => {
+                error!("invalid CALL_RING_TIMEOUT_SECS {}: {}", secs, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
     let (sender, _) = broadcast::channel(16);
     let app_state = Arc::new(AppState { sender });
 
@@ -47,6 +75,7 @@ async fn main() {
         .route("/ws/server", get(server_mngr_handler)) // Server WebSocket endpoint
         .route("/server_mngr", get(serv::server_mngr::server_mngr_handler))
         .route("/call", post(serv::msg_pass::caller_handler))
+        .route("/presence", get(serv::presence::presence_handler))
         .with_state(app_state);
 
     info!("Starting server on port 9527");
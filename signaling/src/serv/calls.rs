@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+// 客户端之间呼叫的结束原因
+pub const REASON_OFFLINE: &str = "offline";
+pub const REASON_BUSY: &str = "busy";
+pub const REASON_INVALID_CALLEE: &str = "invalid_callee";
+pub const REASON_TIMEOUT: &str = "timeout";
+pub const REASON_ANSWERED_ELSEWHERE: &str = "answered_elsewhere";
+pub const REASON_PEER_DISCONNECTED: &str = "peer_disconnected";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallState {
+    Ringing, // 已邀请，等待被叫接听
+    Active,  // 已接听，双方可交换 Offer/Answer/IceCandidate
+}
+
+// 一次客户端之间的呼叫
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub call_id: String,
+    pub caller: String,                 // 主叫 client_id
+    pub caller_session: String,         // 发起呼叫的会话
+    pub callee: String,                 // 被叫 client_id
+    pub callee_session: Option<String>, // 接听的会话，接听前为空
+    pub state: CallState,
+}
+
+impl Call {
+    pub fn involves_client(&self, client_id: &str) -> bool {
+        self.caller == client_id || self.callee == client_id
+    }
+
+    pub fn involves_session(&self, session_id: &str) -> bool {
+        self.caller_session == session_id || self.callee_session.as_deref() == Some(session_id)
+    }
+
+    // 已接听的呼叫中，session 对端的 (client_id, session_id)
+    pub fn peer_of(&self, session_id: &str) -> Option<(&str, &str)> {
+        let callee_session = self.callee_session.as_deref()?;
+        if self.caller_session == session_id {
+            Some((&self.callee, callee_session))
+        } else if callee_session == session_id {
+            Some((&self.caller, &self.caller_session))
+        } else {
+            None
+        }
+    }
+}
+
+// call_id -> Call
+#[derive(Default)]
+pub struct CallRegistry {
+    calls: HashMap<String, Call>,
+}
+
+impl CallRegistry {
+    pub fn insert(&mut self, call: Call) {
+        self.calls.insert(call.call_id.clone(), call);
+    }
+
+    pub fn get(&self, call_id: &str) -> Option<&Call> {
+        self.calls.get(call_id)
+    }
+
+    pub fn get_mut(&mut self, call_id: &str) -> Option<&mut Call> {
+        self.calls.get_mut(call_id)
+    }
+
+    pub fn remove(&mut self, call_id: &str) -> Option<Call> {
+        self.calls.remove(call_id)
+    }
+
+    // 客户端是否已在呼叫中（振铃或通话）
+    pub fn is_busy(&self, client_id: &str) -> bool {
+        self.calls.values().any(|call| call.involves_client(client_id))
+    }
+
+    pub fn calls_of_session(&self, session_id: &str) -> Vec<String> {
+        self.calls
+            .values()
+            .filter(|call| call.involves_session(session_id))
+            .map(|call| call.call_id.clone())
+            .collect()
+    }
+
+    // 会话与 to 之间已接听呼叫的对端会话
+    pub fn peer_session(&self, session_id: &str, to: &str) -> Option<String> {
+        self.calls
+            .values()
+            .filter(|call| call.state == CallState::Active)
+            .filter_map(|call| call.peer_of(session_id))
+            .find(|(peer, _)| *peer == to)
+            .map(|(_, session)| session.to_string())
+    }
+}
@@ -4,7 +4,7 @@ use axum::{
     response::IntoResponse,
 };
 use futures::{SinkExt, StreamExt};
-use msgs::SignalingMessage;
+use msgs::{SignalingMessage, ERR_DUPLICATE_CLIENT};
 use serde::{de, Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::mpsc;
@@ -64,43 +64,61 @@ async fn handle_client_ws(socket: WebSocket, state: Arc<AppState>) {
         warn!("Failed to receive message from client");
         return;
     };
-    let (cli_id, server_id) = if let Ok(client_msg) = serde_json::from_str::<SignalingMessage>(&msg)
+    let (cli_id, session) = if let Ok(client_msg) = serde_json::from_str::<SignalingMessage>(&msg)
     {
         match client_msg {
-            SignalingMessage::ClientConnect { client_id } => {
+            SignalingMessage::ClientConnect { client_id, .. } => {
                 info!("New client registered with ID: {}", &client_id);
                 let mut server_mngr = SERVER_MNGR.lock().await;
-                server_mngr
+                let session = match server_mngr
                     .register_client(&client_id, msg_tx.clone())
+                    .await
+                {
+                    Ok(session) => session,
+                    Err(e) => {
+                        warn!("Refused client {}: {}", client_id, e);
+                        let response = SignalingMessage::Error {
+                            code: ERR_DUPLICATE_CLIENT,
+                            message: e.to_string(),
+                            corr_id: None,
+                        };
+                        let _ = sender
+                            .send(Message::Text(serde_json::to_string(&response).unwrap()))
+                            .await;
+                        return;
+                    }
+                };
+                let server_id = server_mngr
+                    .assign_server_to_session(&session.session_id)
                     .await;
-                if let Some(server_id) = server_mngr.assign_server_to_client(&client_id).await {
-                    debug!(
-                        "Found available server {} for client {}",
-                        server_id, client_id
-                    );
-
-                    info!(
-                        "Successfully assigned server {} to client {}",
-                        server_id, client_id
-                    );
-                    let response = SignalingMessage::ClientConnected {
-                        client_id: client_id.clone(),
-                        server_id: server_id.clone(),
-                    };
-                    debug!("Sending server assignment response: {:?}", response);
-                    let _ = msg_tx.send(serde_json::to_string(&response).unwrap()).await;
-
+                match &server_id {
+                    Some(server_id) => info!(
+                        "Successfully assigned server {} to client {} (session {})",
+                        server_id, client_id, session.session_id
+                    ),
+                    // 客户端之间的呼叫不需要bot，没有RTC服务器时仍保持连接
+                    None => warn!(
+                        "No available server found for client {}, only peer calls are possible",
+                        client_id
+                    ),
+                }
+                let response = SignalingMessage::ClientConnected {
+                    client_id: client_id.clone(),
+                    server_id: server_id.clone().unwrap_or_default(),
+                    corr_id: server_mngr.corr_id_of(&session.session_id),
+                };
+                debug!("Sending server assignment response: {:?}", response);
+                let _ = msg_tx.send(serde_json::to_string(&response).unwrap()).await;
+
+                if server_id.is_some() {
                     server_mngr
-                        .forward_to_server_by_client(
-                            &client_id.clone(),
+                        .forward_to_server_by_session(
+                            &session.session_id,
                             serde_json::from_str::<SignalingMessage>(&msg).unwrap(),
                         )
                         .await;
-                    (client_id.clone(), server_id.clone())
-                } else {
-                    warn!("No available server found for client {}", client_id);
-                    return;
                 }
+                (client_id.clone(), session)
             }
             _ => {
                 warn!("Invalid message type from client");
@@ -111,8 +129,12 @@ async fn handle_client_ws(socket: WebSocket, state: Arc<AppState>) {
         warn!("Failed to parse message from client for the first frame");
         return;
     };
+    // 会话表中持有发送端即可，会话被移除时发送任务随之结束
+    drop(msg_tx);
 
     let cli_id_copy = cli_id.clone();
+    let session_id = session.session_id.clone();
+    let mut kicked_rx = session.kicked_rx;
 
     let mut receive_task = tokio::spawn(async move {
         debug!("Starting WebSocket receive task for client");
@@ -121,13 +143,50 @@ async fn handle_client_ws(socket: WebSocket, state: Arc<AppState>) {
             if let Ok(msg) = serde_json::from_str::<SignalingMessage>(&text) {
                 let mut server_mngr = SERVER_MNGR.lock().await;
 
+                // 已接听的客户端呼叫，信令直接转发给对端客户端
+                let peer_session = match &msg {
+                    SignalingMessage::Offer { to, .. }
+                    | SignalingMessage::Answer { to, .. }
+                    | SignalingMessage::IceCandidate { to, .. } => {
+                        server_mngr.call_peer_session(&session.session_id, to)
+                    }
+                    _ => None,
+                };
+                if let Some(peer_session) = peer_session {
+                    server_mngr.forward_to_session(&peer_session, text).await;
+                    continue;
+                }
+
                 match msg {
+                    SignalingMessage::CallInvite { .. }
+                    | SignalingMessage::CallAccept { .. }
+                    | SignalingMessage::CallReject { .. }
+                    | SignalingMessage::CallCancel { .. }
+                    | SignalingMessage::CallHangup { .. } => {
+                        if let Some(call_id) = server_mngr
+                            .handle_call_message(&session.session_id, msg)
+                            .await
+                        {
+                            let ring_timeout = server_mngr.ring_timeout();
+                            tokio::spawn(async move {
+                                tokio::time::sleep(ring_timeout).await;
+                                SERVER_MNGR.lock().await.expire_call(&call_id).await;
+                            });
+                        }
+                    }
+                    SignalingMessage::PresenceSet { .. }
+                    | SignalingMessage::PresenceSubscribe { .. }
+                    | SignalingMessage::PresenceUnsubscribe { .. } => {
+                        server_mngr
+                            .handle_presence_message(&session.session_id, msg)
+                            .await;
+                    }
                     _ => {
                         let to_pass = text.clone();
-                        info!("passing through the message from client {:?}", to_pass);
+                        info!("passing through the message from client {} {:?}", cli_id, to_pass);
                         let msg = serde_json::from_str::<SignalingMessage>(&to_pass).unwrap();
                         server_mngr
-                            .forward_to_server_by_client(&cli_id.clone(), msg)
+                            .forward_to_server_by_session(&session.session_id, msg)
                             .await;
                         //let _ = msg_tx.send(to_pass).await;
                     }
@@ -147,15 +206,21 @@ async fn handle_client_ws(socket: WebSocket, state: Arc<AppState>) {
         }
     });
 
-    // Wait for either task to finish
+    // Wait for either task to finish, or for a newer connection to replace this one
     tokio::select! {
         _ = (&mut receive_task) => send_task.abort(),
         _ = (&mut send_task) => receive_task.abort(),
+        Ok(()) = &mut kicked_rx => {
+            info!("Session {} of client {} replaced, closing", session_id, cli_id_copy);
+            receive_task.abort();
+            // 会话已被移除，发送任务把踢出通知发完后自行结束
+            let _ = send_task.await;
+        }
     };
 
     // Clean up when the connection is closed
     let mut server_mngr = SERVER_MNGR.lock().await;
-    info!("Cleaning up connection for client {}", cli_id_copy);
-    server_mngr.remove_client(&cli_id_copy).await;
+    info!("Cleaning up connection for client {} (session {})", cli_id_copy, session_id);
+    server_mngr.remove_session(&session_id).await;
     debug!("Client {} removed from server manager", cli_id_copy);
 }
@@ -1,9 +1,11 @@
+pub mod calls;
 pub mod client_handler;
 pub mod events;
 pub mod server_mngr;
 pub mod msg_pass;
 pub mod rtc_server;
 pub mod msgs;
+pub mod presence;
 
 use events::*;
 
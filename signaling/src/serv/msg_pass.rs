@@ -12,16 +12,27 @@ pub async fn caller_handler(
 
     let client_id = xid::new().to_string();
 
-    let (client_tx, client_rx) = mpsc::channel(100);
+    // HTTP 呼叫没有长连接，不接收消息；会话在 bot 结束时由 release_bot 移除
+    let (client_tx, _) = mpsc::channel(100);
 
-    server_mngr.register_client(&client_id, client_tx.clone()).await;
+    // client_id 为新生成的xid，不会与已有客户端冲突
+    let session = match server_mngr.register_client(&client_id, client_tx.clone()).await {
+        Ok(session) => session,
+        Err(e) => {
+            return Json(RoomAssignResponse {
+                success: false,
+                server_id: None,
+                error: Some(e.to_string()),
+            })
+        }
+    };
 
-    match server_mngr.assign_server_to_client(&client_id).await {
+    match server_mngr.assign_server_to_session(&session.session_id).await {
         Some(server_id) => {
             // 通知选中的RTC服务器
-            server_mngr.forward_to_server(&server_id, SignalingMessage::ClientConnected {
+            server_mngr.forward_to_server(&server_id, SignalingMessage::ClientConnect {
                 client_id: client_id,
-                server_id: server_id.clone(),
+                corr_id: server_mngr.corr_id_of(&session.session_id),
             }).await;
             Json(RoomAssignResponse {
                 success: true,
@@ -29,10 +40,13 @@ pub async fn caller_handler(
                 error: None,
             })
         }
-        None => Json(RoomAssignResponse {
-            success: false,
-            server_id: None,
-            error: Some("No available server".to_string()),
-        }),
+        None => {
+            server_mngr.remove_session(&session.session_id).await;
+            Json(RoomAssignResponse {
+                success: false,
+                server_id: None,
+                error: Some("No available server".to_string()),
+            })
+        }
     }
 }
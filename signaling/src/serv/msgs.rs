@@ -9,6 +9,27 @@ pub struct CallRequest {
 
 use std::fmt::Debug;
 
+// SignalingMessage::Error 错误码
+pub const ERR_DUPLICATE_CLIENT: i32 = 4009;   // 同一client_id已在线，新连接被拒绝
+pub const ERR_SESSION_REPLACED: i32 = 4010;   // 旧连接被同一client_id的新连接替换
+pub const ERR_SERVER_FULL: i32 = 5003;        // RTC服务器已达并发上限，且没有其他可用服务器
+pub const ERR_BOT_CREATE_FAILED: i32 = 5004;  // RTC服务器创建 bot 失败
+pub const ERR_INVALID_SDP: i32 = 4011;        // 客户端的 SDP 无法解析或被 bot 拒绝
+pub const ERR_INVALID_CANDIDATE: i32 = 4012;  // 客户端的 ICE 候选无法解析或被 bot 拒绝
+pub const ERR_UNEXPECTED_STATE: i32 = 4013;   // 信令消息与 bot 当前协商状态不符，已忽略
+pub const ERR_UNKNOWN_PROMPT: i32 = 4014;     // play_prompt 命令的提示音名称未在 bot 配置中登记
+pub const ERR_NEGOTIATION_FAILED: i32 = 5005; // bot 协商失败
+pub const ERR_RTC_INTERNAL: i32 = 5006;       // bot 内部错误
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceStatus {
+    Online,
+    Busy,
+    Away,
+    Offline,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "payload", rename_all = "snake_case")]
 pub enum SignalingMessage {
@@ -16,17 +37,131 @@ pub enum SignalingMessage {
     ServerRegister { server_id: String },
     ServerRegistered { server_id: String },
     ServerDisconnect { server_id: String },
+    // RTC服务器上报当前 bot 数量与上限
+    // avg/max_answer_ms 为收到 Offer 到发出 Answer 的耗时统计
+    ServerLoad {
+        server_id: String,
+        active_bots: u32,
+        max_bots: u32,
+        #[serde(default)]
+        avg_answer_ms: u32,
+        #[serde(default)]
+        max_answer_ms: u32,
+    },
     
     // 客户端管理
-    ClientConnect { client_id: String },
-    ClientConnected { client_id: String, server_id: String },
-    ClientDisconnect { client_id: String },
-    
+    // corr_id 为客户端分配到服务器时生成的关联ID，随所有转发的消息携带，用于串联两端日志
+    ClientConnect {
+        client_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+    ClientConnected {
+        client_id: String,
+        server_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+    ClientDisconnect {
+        client_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+
     // WebRTC 信令
-    Offer { from: String, to: String, sdp: String },
-    Answer { from: String, to: String, sdp: String },
-    IceCandidate { from: String, to: String, candidate: String },
+    Offer {
+        from: String,
+        to: String,
+        sdp: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+    Answer {
+        from: String,
+        to: String,
+        sdp: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+    IceCandidate {
+        from: String,
+        to: String,
+        candidate: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+
+    // 客户端之间的呼叫控制，call_id 由信令服务器在 CallInvite 时分配
+    CallInvite { #[serde(default)] call_id: String, from: String, to: String },
+    CallRinging { call_id: String, from: String, to: String },
+    CallAccept { call_id: String, from: String, to: String },
+    CallReject { call_id: String, from: String, to: String, reason: String },
+    CallCancel { call_id: String, from: String, to: String },
+    CallHangup { call_id: String, from: String, to: String },
+    CallEnded { call_id: String, reason: String },
+
+    // 在线状态
+    PresenceSet { status: PresenceStatus },
+    PresenceSubscribe { client_ids: Vec<String> },
+    PresenceUnsubscribe { client_ids: Vec<String> },
+    PresenceUpdate { client_id: String, status: PresenceStatus },
     
+    // 客户端对 bot 的控制命令，command 内容由 vox_server 解析，信令服务器原样转发
+    BotCommand {
+        from: String,
+        to: String,
+        command: serde_json::Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+
+    // bot 会话结束，reason 见 vox_server BotEndReason
+    BotEnded {
+        client_id: String,
+        reason: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+
     // 错误处理
-    Error { code: i32, message: String }
+    Error {
+        code: i32,
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+}
+
+impl SignalingMessage {
+    pub fn corr_id(&self) -> Option<&str> {
+        match self {
+            SignalingMessage::ClientConnect { corr_id, .. }
+            | SignalingMessage::ClientConnected { corr_id, .. }
+            | SignalingMessage::ClientDisconnect { corr_id, .. }
+            | SignalingMessage::Offer { corr_id, .. }
+            | SignalingMessage::Answer { corr_id, .. }
+            | SignalingMessage::IceCandidate { corr_id, .. }
+            | SignalingMessage::BotCommand { corr_id, .. }
+            | SignalingMessage::BotEnded { corr_id, .. }
+            | SignalingMessage::Error { corr_id, .. } => corr_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    // 为可携带关联ID的消息设置 corr_id，其他消息原样返回
+    pub fn with_corr_id(mut self, id: Option<String>) -> Self {
+        match &mut self {
+            SignalingMessage::ClientConnect { corr_id, .. }
+            | SignalingMessage::ClientConnected { corr_id, .. }
+            | SignalingMessage::ClientDisconnect { corr_id, .. }
+            | SignalingMessage::Offer { corr_id, .. }
+            | SignalingMessage::Answer { corr_id, .. }
+            | SignalingMessage::IceCandidate { corr_id, .. }
+            | SignalingMessage::BotCommand { corr_id, .. }
+            | SignalingMessage::BotEnded { corr_id, .. }
+            | SignalingMessage::Error { corr_id, .. } => *corr_id = id,
+            _ => {}
+        }
+        self
+    }
 }
\ No newline at end of file
@@ -0,0 +1,62 @@
+use axum::extract::Query;
+use serde::{Deserialize, Serialize};
+
+use super::msgs::PresenceStatus;
+use super::server_mngr::SERVER_MNGR;
+use super::*;
+
+#[derive(Debug, Deserialize)]
+pub struct PresenceQuery {
+    // 逗号分隔的client_id，为空时返回所有在线客户端
+    pub ids: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClientPresence {
+    pub client_id: String,
+    pub status: PresenceStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServerPresence {
+    pub server_id: String,
+    pub connected_users: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresenceResponse {
+    pub clients: Vec<ClientPresence>,
+    pub servers: Vec<ServerPresence>,
+}
+
+// GET /presence?ids=a,b
+pub async fn presence_handler(Query(query): Query<PresenceQuery>) -> impl IntoResponse {
+    let server_mngr = SERVER_MNGR.lock().await;
+
+    let client_ids = match query.ids {
+        Some(ids) => ids
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+            .collect(),
+        None => server_mngr.online_clients(),
+    };
+    let clients = client_ids
+        .into_iter()
+        .map(|client_id| ClientPresence {
+            status: server_mngr.presence_of(&client_id),
+            client_id,
+        })
+        .collect();
+    let servers = server_mngr
+        .server_loads()
+        .into_iter()
+        .map(|(server_id, connected_users)| ServerPresence {
+            server_id,
+            connected_users,
+        })
+        .collect();
+
+    Json(PresenceResponse { clients, servers })
+}
@@ -6,6 +6,7 @@ use log::{debug, error};
 use serde::Deserialize;
 use tokio::sync::{mpsc::Receiver, Mutex};
 
+use crate::serv::msgs::ERR_SERVER_FULL;
 use crate::serv::{server_mngr::SERVER_MNGR, ServerEvent};
 
 use super::*;
@@ -78,21 +79,57 @@ impl RtcServer {
 
     pub async fn handle_message(&mut self, msg: SignalingMessage) {
         let msg_str = serde_json::to_string(&msg).unwrap();
+        let corr_id = msg.corr_id().unwrap_or("-").to_string();
         match msg {
-            SignalingMessage::Answer { from, to, sdp } => {
-                self.forward_to_client(&to, msg_str.clone())
+            // bot 发起的重协商/ICE restart 也会发送 Offer
+            SignalingMessage::Offer { to, .. } | SignalingMessage::Answer { to, .. } => {
+                self.forward_to_client(&corr_id, &to, msg_str.clone())
                     .await;
             }
             SignalingMessage::IceCandidate {
                 from,
                 to,
                 candidate,
+                ..
             } => {
-                self.forward_to_client(&to, msg_str.clone())
+                self.forward_to_client(&corr_id, &to, msg_str.clone())
                     .await;
             }
-            SignalingMessage::Error { code, message } => {
-                error!("rtc server handle message error: {}", message);
+            SignalingMessage::BotEnded {
+                ref client_id,
+                ref reason,
+                ..
+            } => {
+                info!("[{}] bot of client {} ended: {}", corr_id, client_id, reason);
+                self.forward_to_client(&corr_id, client_id, msg_str.clone())
+                    .await;
+                let mut server_mngr = SERVER_MNGR.lock().await;
+                server_mngr
+                    .release_bot(&self.server_id, client_id, msg.corr_id())
+                    .await;
+            }
+            SignalingMessage::ServerLoad {
+                active_bots,
+                max_bots,
+                avg_answer_ms,
+                max_answer_ms,
+                ..
+            } => {
+                let mut server_mngr = SERVER_MNGR.lock().await;
+                server_mngr.update_server_load(&self.server_id, active_bots, max_bots);
+                server_mngr.update_answer_latency(&self.server_id, avg_answer_ms, max_answer_ms);
+            }
+            SignalingMessage::Error { code, message, .. } => {
+                error!("[{}] rtc server handle message error: {}", corr_id, message);
+                let mut server_mngr = SERVER_MNGR.lock().await;
+                if code == ERR_SERVER_FULL {
+                    server_mngr
+                        .handle_server_full(&self.server_id, &corr_id, message)
+                        .await;
+                } else if let Some(session_id) = server_mngr.session_by_corr_id(&corr_id) {
+                    // 其他错误转给对应的客户端会话
+                    server_mngr.forward_to_session(&session_id, msg_str).await;
+                }
             }
             _ => {
                 warn!("unsupported msg");
@@ -100,13 +137,13 @@ impl RtcServer {
         }
     }
 
-    pub async fn forward_to_client(&mut self, client_id: &str, msg: String) {
+    pub async fn forward_to_client(&mut self, corr_id: &str, client_id: &str, msg: String) {
         let server_mngr = SERVER_MNGR.lock().await;
-        let result = server_mngr.forward_to_client(client_id, msg).await;
+        let result = server_mngr.forward_from_bot(corr_id, client_id, msg).await;
         if result {
-            info!("forward to client success");
+            info!("[{}] forward to client {} success", corr_id, client_id);
         } else {
-            error!("forward to client failed");
+            error!("[{}] forward to client {} failed", corr_id, client_id);
         }
     }
 
@@ -118,12 +155,9 @@ impl RtcServer {
         }
     }
 
+    // 节点由 server_mngr 按连接标识在重连宽限期后移除，这里不动全局状态
     async fn cleanup(&mut self) {
         // Clear managed rooms
         self.managed_rooms.clear();
-
-        // Remove the server from the global manager
-        let mut server_mngr = SERVER_MNGR.lock().await;
-        server_mngr.remove_server(&self.server_id).await;
     }
 }
@@ -1,31 +1,128 @@
 use lazy_static::lazy_static;
-use std::collections::HashMap;
-use tokio::sync::{mpsc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, Mutex};
 use xid;
 
 use super::*;
-use crate::serv::msgs::SignalingMessage;
+use crate::serv::calls::*;
+use crate::serv::msgs::{PresenceStatus, SignalingMessage, ERR_SERVER_FULL, ERR_SESSION_REPLACED};
+
+const DEFAULT_RING_TIMEOUT: Duration = Duration::from_secs(30);
+// 服务器断线后保留节点的时间，期间重连沿用原有会话
+pub const SERVER_RECONNECT_GRACE: Duration = Duration::from_secs(10);
 
 lazy_static! {
     pub static ref SERVER_MNGR: Mutex<ServerMngr> = Mutex::new(ServerMngr::new());
 }
 
+// 同一client_id出现第二个连接时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateClientPolicy {
+    Reject,      // 拒绝新连接，保留旧连接
+    #[default]
+    KickOld,     // 踢掉旧连接，保留新连接
+    MultiDevice, // 允许多设备同时在线，以session_id区分
+}
+
+impl FromStr for DuplicateClientPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "reject" => Ok(Self::Reject),
+            "kick_old" => Ok(Self::KickOld),
+            "multi_device" => Ok(Self::MultiDevice),
+            other => Err(format!("unknown duplicate client policy: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RegisterError {
+    DuplicateClient(String),
+}
+
+impl std::fmt::Display for RegisterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RegisterError::DuplicateClient(client_id) => {
+                write!(f, "client {} is already connected", client_id)
+            }
+        }
+    }
+}
+
+// 注册成功后返回给连接处理方的会话句柄
+pub struct ClientSession {
+    pub session_id: String,
+    pub kicked_rx: oneshot::Receiver<()>, // 被新连接踢掉时触发
+}
+
 // 服务器节点信息
 pub struct ServerNode {
     pub sig_tx: mpsc::Sender<SignalingMessage>,  // 发送消息到服务器的channel
     pub connected_users: u32,                    // 当前连接的用户数
-    pub client_ids: Vec<String>,                 // 该服务器管理的客户端ID列表
+    pub session_ids: Vec<String>,                // 该服务器管理的客户端会话ID列表
+    pub active_bots: u32,                        // 服务器上报的 bot 数量
+    pub max_bots: Option<u32>,                   // 服务器上报的上限，未上报时不限制
+    pub avg_answer_ms: u32,                      // 服务器上报的平均应答耗时
+    pub max_answer_ms: u32,
+    pub conn_id: u64,                            // 当前注册的连接，旧连接的清理据此忽略
+    pending: Vec<SignalingMessage>,              // 断线期间未送达的消息，重连后补发
 }
 
-// 客户端信息
+impl ServerNode {
+    pub fn new(sig_tx: mpsc::Sender<SignalingMessage>) -> Self {
+        Self {
+            sig_tx,
+            connected_users: 0,
+            session_ids: Vec::new(),
+            active_bots: 0,
+            max_bots: None,
+            avg_answer_ms: 0,
+            max_answer_ms: 0,
+            conn_id: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    // 连接已断开、等待重连
+    pub fn is_disconnected(&self) -> bool {
+        self.sig_tx.is_closed()
+    }
+
+    // 上报存在延迟，取本地分配数与上报数中较大者
+    pub fn load(&self) -> u32 {
+        self.connected_users.max(self.active_bots)
+    }
+
+    pub fn has_capacity(&self) -> bool {
+        self.max_bots.map_or(true, |max| self.load() < max)
+    }
+}
+
+// 客户端信息（每个连接一条）
 pub struct ClientInfo {
+    pub client_id: String,
     pub client_tx: mpsc::Sender<String>,         // 发送消息到客户端的channel
     pub server_id: Option<String>,               // 分配的服务器ID
+    pub corr_id: Option<String>,                 // 分配服务器时生成的关联ID
+    kick_tx: Option<oneshot::Sender<()>>,
 }
 
 pub struct ServerMngr {
-    server_nodes: HashMap<String, ServerNode>,    // server_id -> ServerNode
-    client_info: HashMap<String, ClientInfo>,     // client_id -> ClientInfo
+    server_nodes: HashMap<String, ServerNode>,      // server_id -> ServerNode
+    client_info: HashMap<String, ClientInfo>,       // session_id -> ClientInfo
+    client_sessions: HashMap<String, Vec<String>>,  // client_id -> session_ids
+    duplicate_policy: DuplicateClientPolicy,
+    calls: CallRegistry,                            // 客户端之间的呼叫
+    ring_timeout: Duration,                         // 振铃超时
+    presence: HashMap<String, PresenceStatus>,      // client_id -> 客户端自行设置的状态
+    presence_watchers: HashMap<String, HashSet<String>>, // 被关注的client_id -> 订阅者session_ids
+    presence_published: HashMap<String, PresenceStatus>, // 已推送给订阅者的状态
+    next_conn_id: u64,
 }
 
 impl ServerMngr {
@@ -33,47 +130,209 @@ impl ServerMngr {
         Self {
             server_nodes: HashMap::new(),
             client_info: HashMap::new(),
+            client_sessions: HashMap::new(),
+            duplicate_policy: DuplicateClientPolicy::default(),
+            calls: CallRegistry::default(),
+            ring_timeout: DEFAULT_RING_TIMEOUT,
+            presence: HashMap::new(),
+            presence_watchers: HashMap::new(),
+            presence_published: HashMap::new(),
+            next_conn_id: 0,
         }
     }
 
-    // 注册新的服务器节点
-    pub async fn register_server(&mut self, server_id: String, sig_tx: mpsc::Sender<SignalingMessage>) {
-        self.server_nodes.insert(server_id, ServerNode {
-            sig_tx,
-            connected_users: 0,
-            client_ids: Vec::new(),
-        });
+    pub fn set_ring_timeout(&mut self, timeout: Duration) {
+        self.ring_timeout = timeout;
+    }
+
+    pub fn ring_timeout(&self) -> Duration {
+        self.ring_timeout
+    }
+
+    pub fn set_duplicate_policy(&mut self, policy: DuplicateClientPolicy) {
+        info!("duplicate client policy set to {:?}", policy);
+        self.duplicate_policy = policy;
+    }
+
+    // 注册服务器节点，返回本次连接的标识。同一服务器重连时保留会话与负载，只换发送通道，
+    // 并补发断线期间积压的消息
+    pub async fn register_server(&mut self, server_id: String, sig_tx: mpsc::Sender<SignalingMessage>) -> u64 {
+        self.next_conn_id += 1;
+        let conn_id = self.next_conn_id;
+        let Some(node) = self.server_nodes.get_mut(&server_id) else {
+            let mut node = ServerNode::new(sig_tx);
+            node.conn_id = conn_id;
+            self.server_nodes.insert(server_id, node);
+            return conn_id;
+        };
+        info!(
+            "server {} reconnected with {} sessions, {} pending messages",
+            server_id,
+            node.session_ids.len(),
+            node.pending.len()
+        );
+        node.sig_tx = sig_tx;
+        node.conn_id = conn_id;
+        for msg in std::mem::take(&mut node.pending) {
+            if let Err(e) = node.sig_tx.send(msg).await {
+                error!("Failed to forward pending message to server: {}", e);
+            }
+        }
+        conn_id
     }
 
-    // 注册新的客户端
-    pub async fn register_client(&mut self, client_id: &str, client_tx: mpsc::Sender<String>) {
-        self.client_info.insert(client_id.to_string(), ClientInfo {
+    // 注册新的客户端连接，按重复连接策略处理已存在的同名客户端
+    pub async fn register_client(
+        &mut self,
+        client_id: &str,
+        client_tx: mpsc::Sender<String>,
+    ) -> Result<ClientSession, RegisterError> {
+        let existing = self.sessions_of(client_id);
+        if !existing.is_empty() {
+            match self.duplicate_policy {
+                DuplicateClientPolicy::Reject => {
+                    warn!("reject duplicate connection for client {}", client_id);
+                    return Err(RegisterError::DuplicateClient(client_id.to_string()));
+                }
+                DuplicateClientPolicy::KickOld => {}
+                DuplicateClientPolicy::MultiDevice => {
+                    debug!("client {} connects another device", client_id);
+                }
+            }
+        }
+
+        let session_id = xid::new().to_string();
+        let (kick_tx, kicked_rx) = oneshot::channel();
+        self.client_info.insert(session_id.clone(), ClientInfo {
+            client_id: client_id.to_string(),
             client_tx,
             server_id: None,
+            corr_id: None,
+            kick_tx: Some(kick_tx),
         });
+        self.client_sessions
+            .entry(client_id.to_string())
+            .or_default()
+            .push(session_id.clone());
+
+        // 先登记新会话再踢旧会话，订阅者不会看到短暂的离线
+        if self.duplicate_policy == DuplicateClientPolicy::KickOld {
+            for old_session in existing {
+                info!("kick old session {} of client {}", old_session, client_id);
+                self.kick_session(&old_session).await;
+            }
+        }
+        self.publish_presence_changes().await;
+
+        Ok(ClientSession { session_id, kicked_rx })
     }
 
-    // 为客户端分配服务器（负载均衡）
-    pub async fn assign_server_to_client(&mut self, client_id: &str) -> Option<String> {
-        // 找到负载最小的服务器
+    // 为客户端会话分配服务器（负载均衡）
+    pub async fn assign_server_to_session(&mut self, session_id: &str) -> Option<String> {
+        if !self.client_info.contains_key(session_id) {
+            return None;
+        }
+
+        // 在未满的服务器中找到负载最小的
         let selected_server = self.server_nodes.iter_mut()
-            .min_by_key(|(_, node)| node.connected_users)
+            .filter(|(_, node)| node.has_capacity() && !node.is_disconnected())
+            .min_by_key(|(_, node)| node.load())
             .map(|(id, node)| {
                 node.connected_users += 1;
-                node.client_ids.push(client_id.to_string());
+                node.session_ids.push(session_id.to_string());
                 id.clone()
             });
 
-        // 更新客户端信息
+        // 更新客户端信息，并为本次分配生成关联ID
         if let Some(server_id) = &selected_server {
-            if let Some(client) = self.client_info.get_mut(client_id) {
+            if let Some(client) = self.client_info.get_mut(session_id) {
+                let corr_id = xid::new().to_string();
+                info!(
+                    "[{}] client {} session {} assigned to server {}",
+                    corr_id, client.client_id, session_id, server_id
+                );
                 client.server_id = Some(server_id.clone());
+                client.corr_id = Some(corr_id);
             }
         }
 
         selected_server
     }
 
+    // 更新服务器上报的负载
+    pub fn update_server_load(&mut self, server_id: &str, active_bots: u32, max_bots: u32) {
+        if let Some(node) = self.server_nodes.get_mut(server_id) {
+            debug!("server {} load {}/{}", server_id, active_bots, max_bots);
+            node.active_bots = active_bots;
+            node.max_bots = Some(max_bots);
+        }
+    }
+
+    pub fn update_answer_latency(&mut self, server_id: &str, avg_ms: u32, max_ms: u32) {
+        if let Some(node) = self.server_nodes.get_mut(server_id) {
+            debug!("server {} answer latency avg {} ms, max {} ms", server_id, avg_ms, max_ms);
+            node.avg_answer_ms = avg_ms;
+            node.max_answer_ms = max_ms;
+        }
+    }
+
+    pub fn session_by_corr_id(&self, corr_id: &str) -> Option<String> {
+        self.client_info
+            .iter()
+            .find(|(_, client)| client.corr_id.as_deref() == Some(corr_id))
+            .map(|(session_id, _)| session_id.clone())
+    }
+
+    // 服务器已满拒绝了分配给它的会话：改派到其他服务器，没有可用服务器时通知客户端
+    pub async fn handle_server_full(&mut self, server_id: &str, corr_id: &str, message: String) {
+        let Some(session_id) = self.session_by_corr_id(corr_id) else {
+            warn!("[{}] server full for unknown session", corr_id);
+            return;
+        };
+        if let Some(node) = self.server_nodes.get_mut(server_id) {
+            node.session_ids.retain(|id| id != &session_id);
+            node.connected_users = node.connected_users.saturating_sub(1);
+            // 服务器已明确拒绝，在下一次上报前视为已满
+            let full_at = node.load();
+            node.max_bots = Some(node.max_bots.map_or(full_at, |max| max.min(full_at)));
+        }
+        let Some(client) = self.client_info.get_mut(&session_id) else {
+            return;
+        };
+        client.server_id = None;
+        client.corr_id = None;
+        let client_id = client.client_id.clone();
+
+        match self.assign_server_to_session(&session_id).await {
+            Some(new_server) => {
+                let corr_id = self.corr_id_of(&session_id);
+                info!(
+                    "[{}] server {} full, client {} moved to {}",
+                    corr_id.as_deref().unwrap_or("-"), server_id, client_id, new_server
+                );
+                self.forward_to_server(&new_server, SignalingMessage::ClientConnect {
+                    client_id: client_id.clone(),
+                    corr_id: corr_id.clone(),
+                }).await;
+                let connected = SignalingMessage::ClientConnected {
+                    client_id,
+                    server_id: new_server,
+                    corr_id,
+                };
+                self.send_to_session(&session_id, &connected).await;
+            }
+            None => {
+                warn!("[{}] server {} full and no other server available", corr_id, server_id);
+                let error = SignalingMessage::Error {
+                    code: ERR_SERVER_FULL,
+                    message,
+                    corr_id: Some(corr_id.to_string()),
+                };
+                self.send_to_session(&session_id, &error).await;
+            }
+        }
+    }
+
     // 转发消息到服务器
     pub async fn forward_to_server(&self, server_id: &str, msg: SignalingMessage) -> bool {
         if let Some(server) = self.server_nodes.get(server_id) {
@@ -89,9 +348,26 @@ impl ServerMngr {
         }
     }
 
-    // 转发消息到客户端
+    // 转发消息到客户端的所有在线会话
     pub async fn forward_to_client(&self, client_id: &str, msg: String) -> bool {
-        if let Some(client) = self.client_info.get(client_id) {
+        let mut delivered = false;
+        for session_id in self.sessions_of(client_id) {
+            delivered |= self.forward_to_session(&session_id, msg.clone()).await;
+        }
+        delivered
+    }
+
+    // 转发bot发出的消息：按关联ID找到对应会话，找不到时（旧版服务器不带关联ID）发给客户端的所有会话
+    pub async fn forward_from_bot(&self, corr_id: &str, client_id: &str, msg: String) -> bool {
+        match self.session_by_corr_id(corr_id) {
+            Some(session_id) => self.forward_to_session(&session_id, msg).await,
+            None => self.forward_to_client(client_id, msg).await,
+        }
+    }
+
+    // 转发消息到指定会话
+    pub async fn forward_to_session(&self, session_id: &str, msg: String) -> bool {
+        if let Some(client) = self.client_info.get(session_id) {
             match client.client_tx.send(msg).await {
                 Ok(_) => true,
                 Err(e) => {
@@ -104,45 +380,484 @@ impl ServerMngr {
         }
     }
 
-    // 通过client_id转发消息到对应的server
-    pub async fn forward_to_server_by_client(&self, client_id: &str, msg: SignalingMessage) -> bool {
-        if let Some(client) = self.client_info.get(client_id) {
+    // 通过session_id转发消息到对应的server，消息带上该会话的关联ID
+    pub async fn forward_to_server_by_session(&self, session_id: &str, msg: SignalingMessage) -> bool {
+        if let Some(client) = self.client_info.get(session_id) {
             if let Some(server_id) = &client.server_id {
+                let msg = msg.with_corr_id(client.corr_id.clone());
+                debug!(
+                    "[{}] forward to server {}: {:?}",
+                    client.corr_id.as_deref().unwrap_or("-"),
+                    server_id,
+                    msg
+                );
                 return self.forward_to_server(server_id, msg).await;
             }
         }
         false
     }
 
-    // 移除客户端
-    pub async fn remove_client(&mut self, client_id: &str) {
-        if let Some(client) = self.client_info.remove(client_id) {
-            if let Some(server_id) = client.server_id {
-                if let Some(server) = self.server_nodes.get_mut(&server_id) {
-                    server.connected_users = server.connected_users.saturating_sub(1);
-                    server.client_ids.retain(|id| id != client_id);
+    // 会话当前的关联ID
+    pub fn corr_id_of(&self, session_id: &str) -> Option<String> {
+        self.client_info.get(session_id)?.corr_id.clone()
+    }
+
+    // 移除客户端会话，只影响该会话本身
+    pub async fn remove_session(&mut self, session_id: &str) {
+        if !self.client_info.contains_key(session_id) {
+            debug!("session {} already removed", session_id);
+            return;
+        }
+        self.end_calls_of_session(session_id).await;
+        let Some(client) = self.client_info.remove(session_id) else {
+            return;
+        };
+
+        if let Some(sessions) = self.client_sessions.get_mut(&client.client_id) {
+            sessions.retain(|id| id != session_id);
+            if sessions.is_empty() {
+                self.client_sessions.remove(&client.client_id);
+                self.presence.remove(&client.client_id);
+            }
+        }
+        // 断开的会话不再关注任何人
+        for watchers in self.presence_watchers.values_mut() {
+            watchers.remove(session_id);
+        }
+        self.presence_watchers.retain(|_, watchers| !watchers.is_empty());
+        self.presence_published
+            .retain(|client_id, _| self.presence_watchers.contains_key(client_id));
+
+        if let Some(server_id) = client.server_id {
+            if let Some(server) = self.server_nodes.get_mut(&server_id) {
+                server.connected_users = server.connected_users.saturating_sub(1);
+                server.session_ids.retain(|id| id != session_id);
+            }
+
+            // 每个会话独占一个bot（服务器以关联ID区分），通知服务器释放该会话的bot；
+            // 服务器断线等待重连时先积压，重连后补发
+            let msg = SignalingMessage::ClientDisconnect {
+                client_id: client.client_id.clone(),
+                corr_id: client.corr_id.clone(),
+            };
+            match self.server_nodes.get_mut(&server_id) {
+                Some(server) if server.is_disconnected() => server.pending.push(msg),
+                _ => {
+                    self.forward_to_server(&server_id, msg).await;
                 }
             }
         }
+        self.publish_presence_changes().await;
+    }
+
+    // 服务器上的bot已结束：解除会话与服务器的绑定并释放负载，会话本身保留；
+    // 没有长连接的会话（如 HTTP 呼叫）随 bot 一起移除
+    pub async fn release_bot(&mut self, server_id: &str, client_id: &str, corr_id: Option<&str>) {
+        let released: Vec<String> = self
+            .sessions_of(client_id)
+            .into_iter()
+            .filter(|id| {
+                let client = &self.client_info[id];
+                client.server_id.as_deref() == Some(server_id)
+                    && corr_id.map_or(true, |corr_id| client.corr_id.as_deref() == Some(corr_id))
+            })
+            .collect();
+        for session_id in released {
+            if let Some(client) = self.client_info.get_mut(&session_id) {
+                client.server_id = None;
+                client.corr_id = None;
+            }
+            if let Some(server) = self.server_nodes.get_mut(server_id) {
+                server.connected_users = server.connected_users.saturating_sub(1);
+                server.session_ids.retain(|id| id != &session_id);
+            }
+            debug!("session {} released from server {}", session_id, server_id);
+            if self.client_info[&session_id].client_tx.is_closed() {
+                self.remove_session(&session_id).await;
+            }
+        }
     }
 
-    // 移除服务器
-    pub async fn remove_server(&mut self, server_id: &str) {
-        let mut client_id_to_remove = None;
+    // 移除服务器，只移除由 conn_id 这次连接注册的节点，已被新连接接管时忽略
+    pub async fn remove_server(&mut self, server_id: &str, conn_id: u64) {
+        if self.server_nodes.get(server_id).map(|node| node.conn_id) != Some(conn_id) {
+            debug!("server {} already re-registered, skip cleanup", server_id);
+            return;
+        }
         if let Some(server) = self.server_nodes.remove(server_id) {
             // 清理该服务器关联的所有客户端
-            for client_id in server.client_ids {
-                if let Some(client) = self.client_info.get_mut(&client_id) {
+            for session_id in server.session_ids {
+                if let Some(client) = self.client_info.get_mut(&session_id) {
                     client.server_id = None;
-                    client_id_to_remove = Some(client_id.clone());
                 }
+                debug!("remove client session: {}", session_id);
+                self.remove_session(&session_id).await;
+            }
+        }
+    }
+
+    // 处理客户端之间的呼叫控制消息；新呼叫开始振铃时返回 call_id，由调用方启动振铃超时
+    pub async fn handle_call_message(&mut self, session_id: &str, msg: SignalingMessage) -> Option<String> {
+        let client_id = self.client_info.get(session_id)?.client_id.clone();
+        let ringing = match msg {
+            SignalingMessage::CallInvite { to, .. } => {
+                self.invite_call(session_id, &client_id, &to).await
+            }
+            SignalingMessage::CallAccept { call_id, .. } => {
+                self.accept_call(session_id, &client_id, &call_id).await;
+                None
+            }
+            SignalingMessage::CallReject { call_id, reason, .. } => {
+                self.reject_call(session_id, &client_id, &call_id, reason).await;
+                None
+            }
+            SignalingMessage::CallCancel { call_id, .. } => {
+                self.cancel_call(session_id, &call_id).await;
+                None
+            }
+            SignalingMessage::CallHangup { call_id, .. } => {
+                self.hangup_call(session_id, &client_id, &call_id).await;
+                None
+            }
+            other => {
+                warn!("not a call control message: {:?}", other);
+                None
+            }
+        };
+        self.publish_presence_changes().await;
+        ringing
+    }
+
+    // 已接听呼叫中，会话发往 to 的 Offer/Answer/IceCandidate 应转发到的对端会话
+    pub fn call_peer_session(&self, session_id: &str, to: &str) -> Option<String> {
+        self.calls.peer_session(session_id, to)
+    }
+
+    // 振铃超时：仍未接听的呼叫通知双方结束
+    pub async fn expire_call(&mut self, call_id: &str) {
+        if self.calls.get(call_id).map(|call| call.state) != Some(CallState::Ringing) {
+            return;
+        }
+        if let Some(call) = self.calls.remove(call_id) {
+            info!("call {} ringing timeout", call_id);
+            let ended = SignalingMessage::CallEnded {
+                call_id: call.call_id.clone(),
+                reason: REASON_TIMEOUT.to_string(),
+            };
+            self.send_to_session(&call.caller_session, &ended).await;
+            self.notify_callee_sessions(&call, None, &ended).await;
+        }
+        self.publish_presence_changes().await;
+    }
+
+    async fn invite_call(&mut self, session_id: &str, caller: &str, callee: &str) -> Option<String> {
+        let call_id = xid::new().to_string();
+        let refused = if callee == caller {
+            Some(REASON_INVALID_CALLEE)
+        } else if self.session_count(callee) == 0 {
+            Some(REASON_OFFLINE)
+        } else if self.calls.is_busy(callee) || self.calls.is_busy(caller) {
+            Some(REASON_BUSY)
+        } else {
+            None
+        };
+        if let Some(reason) = refused {
+            info!("call from {} to {} refused: {}", caller, callee, reason);
+            let reject = SignalingMessage::CallReject {
+                call_id,
+                from: callee.to_string(),
+                to: caller.to_string(),
+                reason: reason.to_string(),
+            };
+            self.send_to_session(session_id, &reject).await;
+            return None;
+        }
+
+        let call = Call {
+            call_id: call_id.clone(),
+            caller: caller.to_string(),
+            caller_session: session_id.to_string(),
+            callee: callee.to_string(),
+            callee_session: None,
+            state: CallState::Ringing,
+        };
+        info!("call {} from {} to {} ringing", call_id, caller, callee);
+        let invite = SignalingMessage::CallInvite {
+            call_id: call_id.clone(),
+            from: caller.to_string(),
+            to: callee.to_string(),
+        };
+        self.notify_callee_sessions(&call, None, &invite).await;
+        let ringing = SignalingMessage::CallRinging {
+            call_id: call_id.clone(),
+            from: caller.to_string(),
+            to: callee.to_string(),
+        };
+        self.send_to_session(session_id, &ringing).await;
+        self.calls.insert(call);
+        Some(call_id)
+    }
+
+    async fn accept_call(&mut self, session_id: &str, client_id: &str, call_id: &str) {
+        let Some(call) = self.calls.get_mut(call_id) else {
+            warn!("accept for unknown call {}", call_id);
+            return;
+        };
+        if call.callee != client_id || call.state != CallState::Ringing {
+            warn!("client {} cannot accept call {} in state {:?}", client_id, call_id, call.state);
+            return;
+        }
+        call.callee_session = Some(session_id.to_string());
+        call.state = CallState::Active;
+        let call = call.clone();
+
+        info!("call {} accepted by {}", call_id, client_id);
+        let accept = SignalingMessage::CallAccept {
+            call_id: call.call_id.clone(),
+            from: call.callee.clone(),
+            to: call.caller.clone(),
+        };
+        self.send_to_session(&call.caller_session, &accept).await;
+        let ended = SignalingMessage::CallEnded {
+            call_id: call.call_id.clone(),
+            reason: REASON_ANSWERED_ELSEWHERE.to_string(),
+        };
+        self.notify_callee_sessions(&call, Some(session_id), &ended).await;
+    }
+
+    async fn reject_call(&mut self, session_id: &str, client_id: &str, call_id: &str, reason: String) {
+        match self.calls.get(call_id) {
+            Some(call) if call.callee == client_id && call.state == CallState::Ringing => {}
+            _ => {
+                warn!("client {} cannot reject call {}", client_id, call_id);
+                return;
+            }
+        }
+        let Some(call) = self.calls.remove(call_id) else {
+            return;
+        };
+
+        info!("call {} rejected by {}: {}", call_id, client_id, reason);
+        let reject = SignalingMessage::CallReject {
+            call_id: call.call_id.clone(),
+            from: call.callee.clone(),
+            to: call.caller.clone(),
+            reason,
+        };
+        self.send_to_session(&call.caller_session, &reject).await;
+        let cancel = SignalingMessage::CallCancel {
+            call_id: call.call_id.clone(),
+            from: call.caller.clone(),
+            to: call.callee.clone(),
+        };
+        self.notify_callee_sessions(&call, Some(session_id), &cancel).await;
+    }
+
+    async fn cancel_call(&mut self, session_id: &str, call_id: &str) {
+        match self.calls.get(call_id) {
+            Some(call) if call.caller_session == session_id && call.state == CallState::Ringing => {}
+            _ => {
+                warn!("session {} cannot cancel call {}", session_id, call_id);
+                return;
+            }
+        }
+        let Some(call) = self.calls.remove(call_id) else {
+            return;
+        };
+
+        info!("call {} cancelled by {}", call_id, call.caller);
+        let cancel = SignalingMessage::CallCancel {
+            call_id: call.call_id.clone(),
+            from: call.caller.clone(),
+            to: call.callee.clone(),
+        };
+        self.notify_callee_sessions(&call, None, &cancel).await;
+    }
+
+    async fn hangup_call(&mut self, session_id: &str, client_id: &str, call_id: &str) {
+        let Some(call) = self.calls.get(call_id) else {
+            warn!("hangup for unknown call {}", call_id);
+            return;
+        };
+        // 振铃中挂断等同于主叫取消或被叫拒接
+        if call.state == CallState::Ringing {
+            if call.caller_session == session_id {
+                self.cancel_call(session_id, call_id).await;
+            } else {
+                self.reject_call(session_id, client_id, call_id, "declined".to_string())
+                    .await;
+            }
+            return;
+        }
+        let Some((peer, peer_session)) = call
+            .peer_of(session_id)
+            .map(|(peer, session)| (peer.to_string(), session.to_string()))
+        else {
+            warn!("session {} is not part of call {}", session_id, call_id);
+            return;
+        };
+        self.calls.remove(call_id);
+
+        info!("call {} hung up by {}", call_id, client_id);
+        let hangup = SignalingMessage::CallHangup {
+            call_id: call_id.to_string(),
+            from: client_id.to_string(),
+            to: peer,
+        };
+        self.send_to_session(&peer_session, &hangup).await;
+    }
+
+    // 会话断开时结束其参与的所有呼叫
+    async fn end_calls_of_session(&mut self, session_id: &str) {
+        for call_id in self.calls.calls_of_session(session_id) {
+            let Some(call) = self.calls.remove(&call_id) else {
+                continue;
+            };
+            info!("call {} ended, session {} disconnected", call_id, session_id);
+            let ended = SignalingMessage::CallEnded {
+                call_id: call_id.clone(),
+                reason: REASON_PEER_DISCONNECTED.to_string(),
+            };
+            match call.peer_of(session_id) {
+                Some((_, peer_session)) => {
+                    self.send_to_session(peer_session, &ended).await;
+                }
+                // 振铃中主叫断开
+                None => self.notify_callee_sessions(&call, None, &ended).await,
             }
         }
-        if let Some(client_id) = client_id_to_remove {
-            debug!("remove client: {}", client_id);
-            self.remove_client(&client_id).await;
+    }
+
+    async fn notify_callee_sessions(&self, call: &Call, except: Option<&str>, msg: &SignalingMessage) {
+        for session_id in self.sessions_of(&call.callee) {
+            if except != Some(session_id.as_str()) {
+                self.send_to_session(&session_id, msg).await;
+            }
         }
     }
+
+    async fn send_to_session(&self, session_id: &str, msg: &SignalingMessage) -> bool {
+        self.forward_to_session(session_id, serde_json::to_string(msg).unwrap())
+            .await
+    }
+
+    // 客户端当前的在线状态：无会话为离线，自行设置的忙碌/离开优先，通话中为忙碌
+    pub fn presence_of(&self, client_id: &str) -> PresenceStatus {
+        if self.session_count(client_id) == 0 {
+            return PresenceStatus::Offline;
+        }
+        match self.presence.get(client_id) {
+            Some(status) => *status,
+            None if self.calls.is_busy(client_id) => PresenceStatus::Busy,
+            None => PresenceStatus::Online,
+        }
+    }
+
+    // 所有在线的client_id
+    pub fn online_clients(&self) -> Vec<String> {
+        self.client_sessions.keys().cloned().collect()
+    }
+
+    // 各RTC服务器及其当前用户数
+    pub fn server_loads(&self) -> Vec<(String, u32)> {
+        self.server_nodes
+            .iter()
+            .map(|(server_id, node)| (server_id.clone(), node.connected_users))
+            .collect()
+    }
+
+    // 处理客户端的在线状态设置与订阅
+    pub async fn handle_presence_message(&mut self, session_id: &str, msg: SignalingMessage) {
+        let Some(client_id) = self.client_info.get(session_id).map(|c| c.client_id.clone()) else {
+            return;
+        };
+        match msg {
+            SignalingMessage::PresenceSet { status } => match status {
+                PresenceStatus::Online => {
+                    self.presence.remove(&client_id);
+                }
+                PresenceStatus::Busy | PresenceStatus::Away => {
+                    self.presence.insert(client_id, status);
+                }
+                PresenceStatus::Offline => {
+                    warn!("client {} cannot set itself offline", client_id);
+                }
+            },
+            SignalingMessage::PresenceSubscribe { client_ids } => {
+                for watched in client_ids {
+                    let status = self.presence_of(&watched);
+                    self.presence_watchers
+                        .entry(watched.clone())
+                        .or_default()
+                        .insert(session_id.to_string());
+                    self.presence_published.insert(watched.clone(), status);
+                    let update = SignalingMessage::PresenceUpdate {
+                        client_id: watched,
+                        status,
+                    };
+                    self.send_to_session(session_id, &update).await;
+                }
+            }
+            SignalingMessage::PresenceUnsubscribe { client_ids } => {
+                for watched in client_ids {
+                    if let Some(watchers) = self.presence_watchers.get_mut(&watched) {
+                        watchers.remove(session_id);
+                        if watchers.is_empty() {
+                            self.presence_watchers.remove(&watched);
+                            self.presence_published.remove(&watched);
+                        }
+                    }
+                }
+            }
+            other => warn!("not a presence message: {:?}", other),
+        }
+        self.publish_presence_changes().await;
+    }
+
+    // 把发生变化的在线状态推送给订阅者
+    async fn publish_presence_changes(&mut self) {
+        let watched: Vec<String> = self.presence_watchers.keys().cloned().collect();
+        for client_id in watched {
+            let status = self.presence_of(&client_id);
+            if self.presence_published.get(&client_id) == Some(&status) {
+                continue;
+            }
+            debug!("presence of {} changed to {:?}", client_id, status);
+            self.presence_published.insert(client_id.clone(), status);
+            let update = SignalingMessage::PresenceUpdate {
+                client_id: client_id.clone(),
+                status,
+            };
+            for session_id in self.presence_watchers[&client_id].clone() {
+                self.send_to_session(&session_id, &update).await;
+            }
+        }
+    }
+
+    // 当前在线的客户端会话数
+    pub fn session_count(&self, client_id: &str) -> usize {
+        self.client_sessions.get(client_id).map_or(0, |s| s.len())
+    }
+
+    fn sessions_of(&self, client_id: &str) -> Vec<String> {
+        self.client_sessions.get(client_id).cloned().unwrap_or_default()
+    }
+
+    // 踢掉旧会话：通知客户端，关闭其连接，并释放其服务器资源
+    async fn kick_session(&mut self, session_id: &str) {
+        if let Some(client) = self.client_info.get_mut(session_id) {
+            let notice = SignalingMessage::Error {
+                code: ERR_SESSION_REPLACED,
+                message: "session replaced by a new connection".to_string(),
+                corr_id: client.corr_id.clone(),
+            };
+            let _ = client.client_tx.try_send(serde_json::to_string(&notice).unwrap());
+            if let Some(kick_tx) = client.kick_tx.take() {
+                let _ = kick_tx.send(());
+            }
+        }
+        self.remove_session(session_id).await;
+    }
 }
 
 pub async fn server_mngr_handler(
@@ -155,43 +870,42 @@ pub async fn server_mngr_handler(
 pub async fn server_mngr(mut socket: WebSocket, state: Arc<AppState>) {
     debug!("Server mngr connected");
 
-    let mut rtc_server = None;
-
     let (sig_tx, sig_rx) = mpsc::channel::<SignalingMessage>(100);
-    if let Some(msg) = assert_msg::<SignalingMessage>(&mut socket, "ServerRegistered").await {
-        match msg {
-            SignalingMessage::ServerRegister { server_id } => {
-                rtc_server = Some(RtcServer::new(server_id, socket, sig_rx));
-            }
-            _ => {
-                error!("Unexpected message type, expected ServerRegistered");
-                return;
-            }
+    let server_id = match assert_msg::<SignalingMessage>(&mut socket, "ServerRegistered").await {
+        Some(SignalingMessage::ServerRegister { server_id }) => server_id,
+        Some(_) => {
+            error!("Unexpected message type, expected ServerRegistered");
+            return;
         }
-    } else {
-        error!("Failed to receive ServerRegistered message");
-        return;
-    }
-
-    if let Some(rtc_server) = rtc_server {
-        let server_id = rtc_server.server_id.clone();
-        {
-            let mut server_mngr = SERVER_MNGR.lock().await;
-            server_mngr
-                .register_server(rtc_server.server_id.clone(), sig_tx)
-                .await;
+        None => {
+            error!("Failed to receive ServerRegistered message");
+            return;
         }
+    };
 
-        debug!("Server mngr registered server: {:?}", &server_id);
-        rtc_server.process().await;
-        debug!("one rtc server process exited, server_id: {:?}", server_id);
+    let conn_id = {
+        let mut server_mngr = SERVER_MNGR.lock().await;
+        server_mngr
+            .register_server(server_id.clone(), sig_tx.clone())
+            .await
+    };
+    let rtc_server = RtcServer::new(server_id.clone(), socket, sig_rx);
+    // 确认注册，服务器收到后上报当前负载
+    let _ = sig_tx
+        .send(SignalingMessage::ServerRegistered { server_id: server_id.clone() })
+        .await;
 
-        {
-            let mut server_mngr = SERVER_MNGR.lock().await;
-            server_mngr.remove_server(&server_id).await;
-        }
-        debug!("Server cleaned up: {:?}", server_id);
+    debug!("Server mngr registered server: {:?}, conn {}", &server_id, conn_id);
+    rtc_server.process().await;
+    debug!("one rtc server process exited, server_id: {:?}", server_id);
+
+    // 给服务器留出重连时间，期间重连的新连接接管节点，这里的清理随之跳过
+    tokio::time::sleep(SERVER_RECONNECT_GRACE).await;
+    {
+        let mut server_mngr = SERVER_MNGR.lock().await;
+        server_mngr.remove_server(&server_id, conn_id).await;
     }
+    debug!("Server cleaned up: {:?}", server_id);
 }
 
 async fn assert_msg<T>(socket: &mut WebSocket, expected_type: &str) -> Option<T> 
@@ -213,4 +927,606 @@ where
         error!("Failed to receive {} message", expected_type);
         None
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mngr_with(policy: DuplicateClientPolicy) -> (ServerMngr, mpsc::Receiver<SignalingMessage>) {
+        let mut mngr = ServerMngr::new();
+        mngr.set_duplicate_policy(policy);
+        let (sig_tx, sig_rx) = mpsc::channel(16);
+        mngr.server_nodes.insert("server_1".to_string(), ServerNode::new(sig_tx));
+        (mngr, sig_rx)
+    }
+
+    fn users(mngr: &ServerMngr) -> u32 {
+        mngr.server_nodes["server_1"].connected_users
+    }
+
+    #[tokio::test]
+    async fn test_reject_keeps_existing_session() {
+        let (mut mngr, _sig_rx) = mngr_with(DuplicateClientPolicy::Reject);
+        let (tx1, _rx1) = mpsc::channel(4);
+        let (tx2, _rx2) = mpsc::channel(4);
+
+        let first = mngr.register_client("alice", tx1).await.unwrap();
+        mngr.assign_server_to_session(&first.session_id).await.unwrap();
+
+        let second = mngr.register_client("alice", tx2).await;
+        assert_eq!(
+            second.err(),
+            Some(RegisterError::DuplicateClient("alice".to_string()))
+        );
+        assert_eq!(mngr.session_count("alice"), 1);
+        assert_eq!(users(&mngr), 1);
+        assert!(mngr.forward_to_client("alice", "hi".to_string()).await);
+    }
+
+    #[tokio::test]
+    async fn test_kick_old_then_late_cleanup_of_old_session() {
+        let (mut mngr, mut sig_rx) = mngr_with(DuplicateClientPolicy::KickOld);
+        let (tx1, mut rx1) = mpsc::channel(4);
+        let (tx2, mut rx2) = mpsc::channel(4);
+
+        let old = mngr.register_client("alice", tx1).await.unwrap();
+        mngr.assign_server_to_session(&old.session_id).await.unwrap();
+
+        let new = mngr.register_client("alice", tx2).await.unwrap();
+        mngr.assign_server_to_session(&new.session_id).await.unwrap();
+
+        // 旧连接收到替换通知并被关闭，服务器收到旧bot的释放通知
+        assert!(old.kicked_rx.await.is_ok());
+        let notice: SignalingMessage = serde_json::from_str(&rx1.recv().await.unwrap()).unwrap();
+        assert!(matches!(notice, SignalingMessage::Error { code: ERR_SESSION_REPLACED, .. }));
+        assert!(matches!(
+            sig_rx.try_recv(),
+            Ok(SignalingMessage::ClientDisconnect { ref client_id, .. }) if client_id == "alice"
+        ));
+        assert_eq!(users(&mngr), 1);
+
+        // 旧连接随后才执行清理，不能影响新连接
+        mngr.remove_session(&old.session_id).await;
+        assert_eq!(mngr.session_count("alice"), 1);
+        assert_eq!(users(&mngr), 1);
+        assert!(sig_rx.try_recv().is_err());
+        assert!(mngr.forward_to_client("alice", "hi".to_string()).await);
+        assert_eq!(rx2.recv().await.unwrap(), "hi");
+
+        mngr.remove_session(&new.session_id).await;
+        assert_eq!(mngr.session_count("alice"), 0);
+        assert_eq!(users(&mngr), 0);
+    }
+
+    #[tokio::test]
+    async fn test_multi_device_sessions_are_independent() {
+        let (mut mngr, mut sig_rx) = mngr_with(DuplicateClientPolicy::MultiDevice);
+        let (tx1, mut rx1) = mpsc::channel(4);
+        let (tx2, mut rx2) = mpsc::channel(4);
+
+        let phone = mngr.register_client("alice", tx1).await.unwrap();
+        let laptop = mngr.register_client("alice", tx2).await.unwrap();
+        assert_ne!(phone.session_id, laptop.session_id);
+        mngr.assign_server_to_session(&phone.session_id).await.unwrap();
+        mngr.assign_server_to_session(&laptop.session_id).await.unwrap();
+        assert_eq!(users(&mngr), 2);
+
+        assert!(mngr.forward_to_client("alice", "ring".to_string()).await);
+        assert_eq!(rx1.recv().await.unwrap(), "ring");
+        assert_eq!(rx2.recv().await.unwrap(), "ring");
+
+        // 每台设备有自己的bot，bot的消息只发给对应设备
+        let phone_corr = mngr.corr_id_of(&phone.session_id).unwrap();
+        let laptop_corr = mngr.corr_id_of(&laptop.session_id).unwrap();
+        assert_ne!(phone_corr, laptop_corr);
+        assert!(mngr.forward_from_bot(&laptop_corr, "alice", "answer".to_string()).await);
+        assert_eq!(rx2.try_recv().unwrap(), "answer");
+        assert!(rx1.try_recv().is_err());
+
+        // 一台设备离线只释放它自己的bot
+        mngr.remove_session(&phone.session_id).await;
+        assert_eq!(mngr.session_count("alice"), 1);
+        assert_eq!(users(&mngr), 1);
+        match sig_rx.try_recv() {
+            Ok(SignalingMessage::ClientDisconnect { corr_id, .. }) => assert_eq!(corr_id, Some(phone_corr)),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        mngr.remove_session(&laptop.session_id).await;
+        assert_eq!(users(&mngr), 0);
+        match sig_rx.try_recv() {
+            Ok(SignalingMessage::ClientDisconnect { corr_id, .. }) => assert_eq!(corr_id, Some(laptop_corr)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_connects_keep_counts_consistent() {
+        let (mngr, mut sig_rx) = mngr_with(DuplicateClientPolicy::MultiDevice);
+        let mngr = Arc::new(Mutex::new(mngr));
+
+        // 同一client_id的多个连接并发注册、分配与断开
+        let mut tasks = Vec::new();
+        for i in 0..16 {
+            let mngr = mngr.clone();
+            tasks.push(tokio::spawn(async move {
+                let (tx, _rx) = mpsc::channel(4);
+                let session = mngr.lock().await.register_client("alice", tx).await.unwrap();
+                tokio::task::yield_now().await;
+                mngr.lock().await.assign_server_to_session(&session.session_id).await.unwrap();
+                tokio::task::yield_now().await;
+                if i % 2 == 0 {
+                    mngr.lock().await.remove_session(&session.session_id).await;
+                }
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let mngr = mngr.lock().await;
+        assert_eq!(mngr.session_count("alice"), 8);
+        assert_eq!(users(&mngr), 8);
+        assert_eq!(mngr.server_nodes["server_1"].session_ids.len(), 8);
+        let mut released = HashSet::new();
+        while let Ok(msg) = sig_rx.try_recv() {
+            if let SignalingMessage::ClientDisconnect { corr_id, .. } = msg {
+                assert!(released.insert(corr_id.unwrap()));
+            }
+        }
+        assert_eq!(released.len(), 8);
+        for session_id in mngr.sessions_of("alice") {
+            assert!(!released.contains(&mngr.corr_id_of(&session_id).unwrap()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remove_server_releases_all_sessions() {
+        let (mut mngr, _sig_rx) = mngr_with(DuplicateClientPolicy::MultiDevice);
+        let (tx1, _rx1) = mpsc::channel(4);
+        let (tx2, _rx2) = mpsc::channel(4);
+
+        let a = mngr.register_client("alice", tx1).await.unwrap();
+        let b = mngr.register_client("bob", tx2).await.unwrap();
+        mngr.assign_server_to_session(&a.session_id).await.unwrap();
+        mngr.assign_server_to_session(&b.session_id).await.unwrap();
+
+        mngr.remove_server("server_1", 0).await;
+        assert_eq!(mngr.session_count("alice"), 0);
+        assert_eq!(mngr.session_count("bob"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_reregister_keeps_sessions_and_ignores_old_cleanup() {
+        let mut mngr = ServerMngr::new();
+        let (old_tx, old_rx) = mpsc::channel(16);
+        let old_conn = mngr.register_server("server_1".to_string(), old_tx).await;
+        let (tx, _rx) = mpsc::channel(4);
+        let alice = mngr.register_client("alice", tx).await.unwrap().session_id;
+        mngr.assign_server_to_session(&alice).await.unwrap();
+        let (tx, _rx) = mpsc::channel(4);
+        let bob = mngr.register_client("bob", tx).await.unwrap().session_id;
+        mngr.assign_server_to_session(&bob).await.unwrap();
+        let bob_corr_id = mngr.corr_id_of(&bob);
+
+        // 旧连接断开：不再分配新会话，离开的会话积压到重连后通知
+        drop(old_rx);
+        let (tx, _rx) = mpsc::channel(4);
+        let carol = mngr.register_client("carol", tx).await.unwrap().session_id;
+        assert_eq!(mngr.assign_server_to_session(&carol).await, None);
+        mngr.remove_session(&bob).await;
+
+        // 新连接注册时旧连接尚未清理，沿用原节点的会话与负载
+        let (new_tx, mut new_rx) = mpsc::channel(16);
+        let new_conn = mngr.register_server("server_1".to_string(), new_tx).await;
+        assert_ne!(new_conn, old_conn);
+        assert_eq!(users(&mngr), 1);
+        match new_rx.try_recv().unwrap() {
+            SignalingMessage::ClientDisconnect { client_id, corr_id } => {
+                assert_eq!(client_id, "bob");
+                assert_eq!(corr_id, bob_corr_id);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // 旧连接的清理不影响新连接注册的节点
+        mngr.remove_server("server_1", old_conn).await;
+        assert_eq!(users(&mngr), 1);
+        assert_eq!(mngr.session_count("alice"), 1);
+        mngr.remove_session(&alice).await;
+        assert!(matches!(
+            new_rx.try_recv().unwrap(),
+            SignalingMessage::ClientDisconnect { .. }
+        ));
+        assert_eq!(users(&mngr), 0);
+
+        mngr.remove_server("server_1", new_conn).await;
+        assert!(mngr.server_nodes.is_empty());
+    }
+
+    fn recv_msg(rx: &mut mpsc::Receiver<String>) -> SignalingMessage {
+        serde_json::from_str(&rx.try_recv().expect("expected a message")).unwrap()
+    }
+
+    async fn connect(mngr: &mut ServerMngr, client_id: &str) -> (String, mpsc::Receiver<String>) {
+        let (tx, rx) = mpsc::channel(16);
+        let session = mngr.register_client(client_id, tx).await.unwrap();
+        (session.session_id, rx)
+    }
+
+    fn invite(to: &str) -> SignalingMessage {
+        SignalingMessage::CallInvite {
+            call_id: String::new(),
+            from: String::new(),
+            to: to.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_invite_accept_and_route() {
+        let (mut mngr, _sig_rx) = mngr_with(DuplicateClientPolicy::MultiDevice);
+        let (alice, mut alice_rx) = connect(&mut mngr, "alice").await;
+        let (bob_phone, mut phone_rx) = connect(&mut mngr, "bob").await;
+        let (bob_laptop, mut laptop_rx) = connect(&mut mngr, "bob").await;
+
+        let call_id = mngr.handle_call_message(&alice, invite("bob")).await.unwrap();
+        assert!(matches!(recv_msg(&mut alice_rx), SignalingMessage::CallRinging { .. }));
+        assert!(matches!(
+            recv_msg(&mut phone_rx),
+            SignalingMessage::CallInvite { call_id: ref id, .. } if id == &call_id
+        ));
+        assert!(matches!(recv_msg(&mut laptop_rx), SignalingMessage::CallInvite { .. }));
+        // 振铃中不转发媒体信令
+        assert_eq!(mngr.call_peer_session(&alice, "bob"), None);
+
+        let accept = SignalingMessage::CallAccept {
+            call_id: call_id.clone(),
+            from: "bob".to_string(),
+            to: "alice".to_string(),
+        };
+        mngr.handle_call_message(&bob_laptop, accept).await;
+        assert!(matches!(recv_msg(&mut alice_rx), SignalingMessage::CallAccept { .. }));
+        assert!(matches!(
+            recv_msg(&mut phone_rx),
+            SignalingMessage::CallEnded { ref reason, .. } if reason == REASON_ANSWERED_ELSEWHERE
+        ));
+
+        assert_eq!(mngr.call_peer_session(&alice, "bob"), Some(bob_laptop.clone()));
+        assert_eq!(mngr.call_peer_session(&bob_laptop, "alice"), Some(alice.clone()));
+        assert_eq!(mngr.call_peer_session(&bob_phone, "alice"), None);
+        // 到期的振铃定时器不影响已接听的呼叫
+        mngr.expire_call(&call_id).await;
+        assert!(mngr.call_peer_session(&alice, "bob").is_some());
+
+        let hangup = SignalingMessage::CallHangup {
+            call_id: call_id.clone(),
+            from: "alice".to_string(),
+            to: "bob".to_string(),
+        };
+        mngr.handle_call_message(&alice, hangup).await;
+        assert!(matches!(recv_msg(&mut laptop_rx), SignalingMessage::CallHangup { .. }));
+        assert_eq!(mngr.call_peer_session(&alice, "bob"), None);
+    }
+
+    #[tokio::test]
+    async fn test_call_refused_offline_busy_and_self() {
+        let (mut mngr, _sig_rx) = mngr_with(DuplicateClientPolicy::KickOld);
+        let (alice, mut alice_rx) = connect(&mut mngr, "alice").await;
+        let (_bob, _bob_rx) = connect(&mut mngr, "bob").await;
+        let (carol, mut carol_rx) = connect(&mut mngr, "carol").await;
+
+        assert!(mngr.handle_call_message(&alice, invite("dave")).await.is_none());
+        assert!(matches!(
+            recv_msg(&mut alice_rx),
+            SignalingMessage::CallReject { ref reason, .. } if reason == REASON_OFFLINE
+        ));
+        assert!(mngr.handle_call_message(&alice, invite("alice")).await.is_none());
+        assert!(matches!(
+            recv_msg(&mut alice_rx),
+            SignalingMessage::CallReject { ref reason, .. } if reason == REASON_INVALID_CALLEE
+        ));
+
+        assert!(mngr.handle_call_message(&alice, invite("bob")).await.is_some());
+        assert!(mngr.handle_call_message(&carol, invite("bob")).await.is_none());
+        assert!(matches!(
+            recv_msg(&mut carol_rx),
+            SignalingMessage::CallReject { ref reason, .. } if reason == REASON_BUSY
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_call_reject_cancel_and_timeout() {
+        let (mut mngr, _sig_rx) = mngr_with(DuplicateClientPolicy::KickOld);
+        let (alice, mut alice_rx) = connect(&mut mngr, "alice").await;
+        let (bob, mut bob_rx) = connect(&mut mngr, "bob").await;
+
+        let call_id = mngr.handle_call_message(&alice, invite("bob")).await.unwrap();
+        let reject = SignalingMessage::CallReject {
+            call_id: call_id.clone(),
+            from: "bob".to_string(),
+            to: "alice".to_string(),
+            reason: "declined".to_string(),
+        };
+        mngr.handle_call_message(&bob, reject).await;
+        recv_msg(&mut alice_rx);
+        assert!(matches!(recv_msg(&mut alice_rx), SignalingMessage::CallReject { .. }));
+        recv_msg(&mut bob_rx);
+
+        let call_id = mngr.handle_call_message(&alice, invite("bob")).await.unwrap();
+        let cancel = SignalingMessage::CallCancel {
+            call_id,
+            from: "alice".to_string(),
+            to: "bob".to_string(),
+        };
+        // 只有主叫能取消
+        mngr.handle_call_message(&bob, cancel.clone()).await;
+        recv_msg(&mut bob_rx);
+        assert!(bob_rx.try_recv().is_err());
+        mngr.handle_call_message(&alice, cancel).await;
+        assert!(matches!(recv_msg(&mut bob_rx), SignalingMessage::CallCancel { .. }));
+        recv_msg(&mut alice_rx);
+
+        let call_id = mngr.handle_call_message(&alice, invite("bob")).await.unwrap();
+        recv_msg(&mut alice_rx);
+        recv_msg(&mut bob_rx);
+        mngr.expire_call(&call_id).await;
+        assert!(matches!(
+            recv_msg(&mut alice_rx),
+            SignalingMessage::CallEnded { ref reason, .. } if reason == REASON_TIMEOUT
+        ));
+        assert!(matches!(recv_msg(&mut bob_rx), SignalingMessage::CallEnded { .. }));
+        // 超时后可以再次呼叫
+        assert!(mngr.handle_call_message(&alice, invite("bob")).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_call_ends_when_peer_disconnects() {
+        let (mut mngr, _sig_rx) = mngr_with(DuplicateClientPolicy::KickOld);
+        let (alice, _alice_rx) = connect(&mut mngr, "alice").await;
+        let (bob, mut bob_rx) = connect(&mut mngr, "bob").await;
+
+        let call_id = mngr.handle_call_message(&alice, invite("bob")).await.unwrap();
+        let accept = SignalingMessage::CallAccept {
+            call_id,
+            from: "bob".to_string(),
+            to: "alice".to_string(),
+        };
+        mngr.handle_call_message(&bob, accept).await;
+        recv_msg(&mut bob_rx);
+
+        mngr.remove_session(&alice).await;
+        assert!(matches!(
+            recv_msg(&mut bob_rx),
+            SignalingMessage::CallEnded { ref reason, .. } if reason == REASON_PEER_DISCONNECTED
+        ));
+        assert_eq!(mngr.call_peer_session(&bob, "alice"), None);
+    }
+
+    fn presence_update(rx: &mut mpsc::Receiver<String>) -> (String, PresenceStatus) {
+        match recv_msg(rx) {
+            SignalingMessage::PresenceUpdate { client_id, status } => (client_id, status),
+            other => panic!("expected presence update, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_presence_subscription_follows_sessions_and_calls() {
+        let (mut mngr, _sig_rx) = mngr_with(DuplicateClientPolicy::KickOld);
+        let (alice, mut alice_rx) = connect(&mut mngr, "alice").await;
+
+        let subscribe = SignalingMessage::PresenceSubscribe {
+            client_ids: vec!["bob".to_string(), "carol".to_string()],
+        };
+        mngr.handle_presence_message(&alice, subscribe).await;
+        assert_eq!(presence_update(&mut alice_rx), ("bob".to_string(), PresenceStatus::Offline));
+        assert_eq!(presence_update(&mut alice_rx), ("carol".to_string(), PresenceStatus::Offline));
+
+        let (_old_bob, mut bob_rx) = connect(&mut mngr, "bob").await;
+        assert_eq!(presence_update(&mut alice_rx), ("bob".to_string(), PresenceStatus::Online));
+
+        // 重连替换旧会话时不推送离线
+        let (bob, _bob_rx2) = connect(&mut mngr, "bob").await;
+        assert!(alice_rx.try_recv().is_err());
+        recv_msg(&mut bob_rx);
+
+        let (carol, _carol_rx) = connect(&mut mngr, "carol").await;
+        assert_eq!(presence_update(&mut alice_rx), ("carol".to_string(), PresenceStatus::Online));
+        mngr.handle_call_message(&carol, invite("bob")).await.unwrap();
+        let mut updates = vec![presence_update(&mut alice_rx), presence_update(&mut alice_rx)];
+        updates.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            updates,
+            vec![
+                ("bob".to_string(), PresenceStatus::Busy),
+                ("carol".to_string(), PresenceStatus::Busy)
+            ]
+        );
+
+        // 主叫断开：呼叫结束，bob恢复在线，carol离线
+        mngr.remove_session(&carol).await;
+        let mut updates = vec![presence_update(&mut alice_rx), presence_update(&mut alice_rx)];
+        updates.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            updates,
+            vec![
+                ("bob".to_string(), PresenceStatus::Online),
+                ("carol".to_string(), PresenceStatus::Offline)
+            ]
+        );
+
+        let away = SignalingMessage::PresenceSet { status: PresenceStatus::Away };
+        mngr.handle_presence_message(&bob, away).await;
+        assert_eq!(presence_update(&mut alice_rx), ("bob".to_string(), PresenceStatus::Away));
+
+        mngr.remove_session(&bob).await;
+        assert_eq!(presence_update(&mut alice_rx), ("bob".to_string(), PresenceStatus::Offline));
+        // 再次上线时不保留之前设置的离开状态
+        let (_bob, _bob_rx3) = connect(&mut mngr, "bob").await;
+        assert_eq!(presence_update(&mut alice_rx), ("bob".to_string(), PresenceStatus::Online));
+    }
+
+    #[tokio::test]
+    async fn test_presence_watchers_cleared_when_socket_drops() {
+        let (mut mngr, _sig_rx) = mngr_with(DuplicateClientPolicy::KickOld);
+        let (alice, mut alice_rx) = connect(&mut mngr, "alice").await;
+        let subscribe = SignalingMessage::PresenceSubscribe {
+            client_ids: vec!["bob".to_string()],
+        };
+        mngr.handle_presence_message(&alice, subscribe).await;
+        presence_update(&mut alice_rx);
+
+        mngr.remove_session(&alice).await;
+        assert!(mngr.presence_watchers.is_empty());
+        assert!(mngr.presence_published.is_empty());
+
+        let (_bob, _bob_rx) = connect(&mut mngr, "bob").await;
+        assert_eq!(mngr.presence_of("bob"), PresenceStatus::Online);
+        assert_eq!(mngr.presence_of("alice"), PresenceStatus::Offline);
+        assert_eq!(mngr.online_clients(), vec!["bob".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_corr_id_stamped_on_forwarded_messages() {
+        let (mut mngr, mut sig_rx) = mngr_with(DuplicateClientPolicy::KickOld);
+        let (alice, _alice_rx) = connect(&mut mngr, "alice").await;
+        assert_eq!(mngr.corr_id_of(&alice), None);
+
+        mngr.assign_server_to_session(&alice).await.unwrap();
+        let corr_id = mngr.corr_id_of(&alice).unwrap();
+
+        let offer = SignalingMessage::Offer {
+            from: "alice".to_string(),
+            to: "server_1".to_string(),
+            sdp: "sdp".to_string(),
+            corr_id: None,
+        };
+        assert!(mngr.forward_to_server_by_session(&alice, offer).await);
+        let forwarded = sig_rx.try_recv().unwrap();
+        assert_eq!(forwarded.corr_id(), Some(corr_id.as_str()));
+        assert!(serde_json::to_string(&forwarded).unwrap().contains(&corr_id));
+
+        // bot 控制命令同样带上关联ID，vox_server 据此找到该会话的 bot
+        let json = r#"{"type":"bot_command","payload":{"from":"alice","to":"bot","command":{"action":"remove_audio"}}}"#;
+        let command: SignalingMessage = serde_json::from_str(json).unwrap();
+        assert!(mngr.forward_to_server_by_session(&alice, command).await);
+        let forwarded = sig_rx.try_recv().unwrap();
+        assert_eq!(forwarded.corr_id(), Some(corr_id.as_str()));
+        assert!(serde_json::to_string(&forwarded)
+            .unwrap()
+            .contains(r#""command":{"action":"remove_audio"}"#));
+
+        mngr.remove_session(&alice).await;
+        let disconnect = sig_rx.try_recv().unwrap();
+        assert!(matches!(disconnect, SignalingMessage::ClientDisconnect { .. }));
+        assert_eq!(disconnect.corr_id(), Some(corr_id.as_str()));
+    }
+
+    #[test]
+    fn test_corr_id_is_optional_on_the_wire() {
+        let json = r#"{"type":"offer","payload":{"from":"a","to":"b","sdp":"x"}}"#;
+        let msg: SignalingMessage = serde_json::from_str(json).unwrap();
+        assert_eq!(msg.corr_id(), None);
+        assert_eq!(serde_json::to_string(&msg).unwrap(), json);
+    }
+
+    #[test]
+    fn test_policy_from_str() {
+        assert_eq!("reject".parse(), Ok(DuplicateClientPolicy::Reject));
+        assert_eq!("KICK_OLD".parse(), Ok(DuplicateClientPolicy::KickOld));
+        assert_eq!("multi_device".parse(), Ok(DuplicateClientPolicy::MultiDevice));
+        assert!("whatever".parse::<DuplicateClientPolicy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_assign_skips_full_server() {
+        let (mut mngr, _sig_rx) = mngr_with(DuplicateClientPolicy::KickOld);
+        let (sig_tx, _sig_rx2) = mpsc::channel(16);
+        mngr.register_server("server_2".to_string(), sig_tx).await;
+        mngr.update_server_load("server_1", 1, 1);
+        mngr.update_server_load("server_2", 0, 1);
+
+        let (alice, _alice_rx) = connect(&mut mngr, "alice").await;
+        assert_eq!(mngr.assign_server_to_session(&alice).await, Some("server_2".to_string()));
+
+        // 两台都已满
+        let (bob, _bob_rx) = connect(&mut mngr, "bob").await;
+        assert_eq!(mngr.assign_server_to_session(&bob).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_server_full_moves_session() {
+        let (mut mngr, _sig_rx) = mngr_with(DuplicateClientPolicy::KickOld);
+        let (alice, mut alice_rx) = connect(&mut mngr, "alice").await;
+        mngr.assign_server_to_session(&alice).await.unwrap();
+        let corr_id = mngr.corr_id_of(&alice).unwrap();
+
+        let (sig_tx, mut sig_rx2) = mpsc::channel(16);
+        mngr.register_server("server_2".to_string(), sig_tx).await;
+        mngr.handle_server_full("server_1", &corr_id, "full".to_string()).await;
+
+        assert_eq!(users(&mngr), 0);
+        assert!(!mngr.server_nodes["server_1"].has_capacity());
+        let new_corr_id = mngr.corr_id_of(&alice).unwrap();
+        assert_ne!(new_corr_id, corr_id);
+        match sig_rx2.try_recv().unwrap() {
+            SignalingMessage::ClientConnect { client_id, corr_id } => {
+                assert_eq!(client_id, "alice");
+                assert_eq!(corr_id, Some(new_corr_id.clone()));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        match recv_msg(&mut alice_rx) {
+            SignalingMessage::ClientConnected { server_id, .. } => assert_eq!(server_id, "server_2"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // 没有其他服务器时通知客户端
+        mngr.handle_server_full("server_2", &new_corr_id, "full".to_string()).await;
+        match recv_msg(&mut alice_rx) {
+            SignalingMessage::Error { code, .. } => assert_eq!(code, ERR_SERVER_FULL),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(mngr.corr_id_of(&alice), None);
+    }
+
+    #[tokio::test]
+    async fn test_bot_ended_releases_server_load() {
+        let (mut mngr, mut sig_rx) = mngr_with(DuplicateClientPolicy::KickOld);
+        let (tx, _rx) = mpsc::channel(4);
+        let alice = mngr.register_client("alice", tx).await.unwrap().session_id;
+        mngr.assign_server_to_session(&alice).await.unwrap();
+        let corr_id = mngr.corr_id_of(&alice).unwrap();
+        assert_eq!(mngr.server_nodes["server_1"].load(), 1);
+
+        // 其他关联ID的结束通知不影响当前会话
+        mngr.release_bot("server_1", "alice", Some("stale")).await;
+        assert_eq!(users(&mngr), 1);
+
+        mngr.release_bot("server_1", "alice", Some(&corr_id)).await;
+        assert_eq!(mngr.server_nodes["server_1"].load(), 0);
+        assert!(mngr.server_nodes["server_1"].session_ids.is_empty());
+        assert_eq!(mngr.corr_id_of(&alice), None);
+        assert_eq!(mngr.session_count("alice"), 1);
+
+        // bot 已结束，会话断开时不再通知服务器
+        mngr.remove_session(&alice).await;
+        assert_eq!(users(&mngr), 0);
+        assert!(sig_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_without_connection_removed_with_bot() {
+        let (mut mngr, mut sig_rx) = mngr_with(DuplicateClientPolicy::KickOld);
+        // HTTP 呼叫的会话没有接收端
+        let (tx, _) = mpsc::channel(4);
+        let caller = mngr.register_client("caller", tx).await.unwrap().session_id;
+        mngr.assign_server_to_session(&caller).await.unwrap();
+        let corr_id = mngr.corr_id_of(&caller).unwrap();
+        assert_eq!(users(&mngr), 1);
+
+        mngr.release_bot("server_1", "caller", Some(&corr_id)).await;
+        assert_eq!(mngr.session_count("caller"), 0);
+        assert_eq!(users(&mngr), 0);
+        assert!(sig_rx.try_recv().is_err());
+    }
+}
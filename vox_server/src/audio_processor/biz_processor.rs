@@ -1,12 +1,13 @@
 use crate::server::data;
 
 use super::*;
+use slog::Logger;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc;
 
-// 音频处理能力的trait
+// 音频处理能力的trait，返回要播放给用户的音频（TTS 采样率单声道），没有时返回 None
 pub trait AudioCapability: Send + Sync + 'static {
-    fn process(&mut self, pcm_data: &[i16]) -> Result<()>;
+    fn process(&mut self, pcm_data: &[i16]) -> Result<Option<data::AudioData>>;
 }
 
 // VAD能力
@@ -15,9 +16,9 @@ pub struct VadProcessor {
 }
 
 impl AudioCapability for VadProcessor {
-    fn process(&mut self, pcm_data: &[i16]) -> Result<()> {
+    fn process(&mut self, _pcm_data: &[i16]) -> Result<Option<data::AudioData>> {
         // VAD处理逻辑
-        Ok(())
+        Ok(None)
     }
 }
 
@@ -27,9 +28,9 @@ pub struct AsrProcessor {
 }
 
 impl AudioCapability for AsrProcessor {
-    fn process(&mut self, pcm_data: &[i16]) -> Result<()> {
+    fn process(&mut self, _pcm_data: &[i16]) -> Result<Option<data::AudioData>> {
         // ASR处理逻辑
-        Ok(())
+        Ok(None)
     }
 }
 
@@ -38,16 +39,25 @@ pub struct AudioBizProcessor {
     broadcast_rx: broadcast::Receiver<data::AudioData>,
     capabilities: Vec<Box<dyn AudioCapability>>,
     audio_rx: mpsc::Receiver<Vec<i16>>,
+    // 处理结果写入下行音频，按节奏编码发送给用户
+    output_tx: mpsc::Sender<data::AudioData>,
+    log: Logger,
 }
 
 impl AudioBizProcessor {
-    pub fn new(audio_rx: mpsc::Receiver<Vec<i16>>) -> Self {
+    pub fn new(
+        audio_rx: mpsc::Receiver<Vec<i16>>,
+        output_tx: mpsc::Sender<data::AudioData>,
+        log: Logger,
+    ) -> Self {
         let (broadcast_tx, broadcast_rx) = broadcast::channel(100);
         Self {
             capabilities: Vec::new(),
             audio_rx,
+            output_tx,
             broadcast_tx,
             broadcast_rx,
+            log,
         }
     }
 
@@ -55,15 +65,58 @@ impl AudioBizProcessor {
         self.capabilities.push(capability);
     }
 
+    // 运行到音频通道关闭或下行音频不再接收；由调用方 spawn，以便会话结束时取消
     pub async fn start(mut self) {
-        tokio::spawn(async move {
-            while let Some(pcm_data) = self.audio_rx.recv().await {
-                for capability in &mut self.capabilities {
-                    if let Err(e) = capability.process(&pcm_data) {
-                        error!("处理音频数据失败: {}", e);
+        while let Some(pcm_data) = self.audio_rx.recv().await {
+            for capability in &mut self.capabilities {
+                match capability.process(&pcm_data) {
+                    Ok(Some(audio)) => {
+                        if self.output_tx.send(audio).await.is_err() {
+                            debug!(log: self.log, "下行音频已关闭，停止音频处理");
+                            return;
+                        }
                     }
+                    Ok(None) => {}
+                    Err(e) => error!(log: self.log, "处理音频数据失败: {}", e),
                 }
             }
-        });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // 原样回放输入的能力
+    struct Echo;
+
+    impl AudioCapability for Echo {
+        fn process(&mut self, pcm_data: &[i16]) -> Result<Option<data::AudioData>> {
+            Ok(Some(data::AudioData {
+                data: pcm_data.to_vec(),
+                duration: Duration::from_millis(20),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_capability_output_goes_to_output() {
+        let (audio_tx, audio_rx) = mpsc::channel(8);
+        let (output_tx, mut output_rx) = mpsc::channel(8);
+        let log = Logger::root(slog::Discard, slog::o!());
+        let mut processor = AudioBizProcessor::new(audio_rx, output_tx, log);
+        processor.add_capability(Box::new(VadProcessor {}));
+        processor.add_capability(Box::new(Echo));
+        let handle = tokio::spawn(processor.start());
+
+        audio_tx.send(vec![1, 2, 3]).await.unwrap();
+        drop(audio_tx);
+        let audio = output_rx.recv().await.unwrap();
+        assert_eq!(audio.data, vec![1, 2, 3]);
+        // 没有输出的能力不写入下行
+        assert!(output_rx.recv().await.is_none());
+        handle.await.unwrap();
     }
 }
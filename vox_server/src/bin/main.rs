@@ -1,7 +1,10 @@
 use std::io::Write;
 use std::str::FromStr;
 use tokio::sync::mpsc;
+use vox_verse::config::{config_path_from_args, AppConfig, CONFIG};
 use vox_verse::{debug, error, info, warn};
+use vox_verse::prompt;
+use vox_verse::server::rtc::factory::RtcFactory;
 use vox_verse::{msg_center::msg_bus::MessageBus, server::ws_cli::run_signaling_client};
 #[tokio::main]
 async fn main() {
@@ -23,14 +26,65 @@ async fn main() {
     rustls::crypto::aws_lc_rs::default_provider()
         .install_default()
         .unwrap();
+
+    // 日志依赖配置，加载失败时只能输出到 stderr
+    let config_path = config_path_from_args();
+    let app_config = match &config_path {
+        Some(path) => AppConfig::load(path).await,
+        None => AppConfig::load_env().await,
+    };
+    let app_config = match app_config {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!(
+                "Failed to load config {}: {}",
+                config_path.as_deref().unwrap_or("(env)"),
+                e
+            );
+            std::process::exit(1);
+        }
+    };
+    vox_verse::utils::log::init(&app_config.log);
+    let rtc_factory = match RtcFactory::new(app_config.rtc.clone()).await {
+        Ok(factory) => factory,
+        Err(e) => {
+            error!("Failed to create webrtc api: {}", e);
+            std::process::exit(1);
+        }
+    };
+    tokio::spawn(rtc_factory.clone().refill());
+    tokio::spawn(rtc_factory.clone().follow_config());
+
+    // 预先编码配置的提示音与欢迎语，首次播放不再等待解码
+    let mut prompts = app_config.prompt.preload.clone();
+    if !app_config.bot.greeting_prompt.is_empty() {
+        prompts.push(app_config.bot.greeting_prompt.clone());
+    }
+    let (cache_max_bytes, rate, channels) = (
+        app_config.prompt.cache_max_bytes,
+        app_config.rtc.audio_sample_rate,
+        app_config.rtc.audio_channels,
+    );
+    tokio::spawn(async move {
+        prompt::cache::preload(&prompts, cache_max_bytes, rate, channels).await;
+    });
+    tokio::spawn(prompt::cache::follow_config());
+    *CONFIG.write().await = app_config;
+
     info!("Starting vox_server...");
+    if let Some(path) = config_path {
+        info!("Loaded config from {}", path);
+        if let Err(e) = AppConfig::watch_config(path) {
+            warn!("Failed to watch config file: {}", e);
+        }
+    }
     let (bus_tx, bus_rx) = mpsc::channel(100);
     let (ws_tx, ws_rx) = mpsc::channel(100);
 
     info!("Spawning MessageBus task");
     tokio::spawn(async move {
         info!("MessageBus task started");
-        MessageBus::run(bus_rx, ws_tx).await;
+        MessageBus::run(bus_rx, ws_tx, rtc_factory).await;
         warn!("MessageBus task exited");
     });
 
@@ -1,21 +1,71 @@
+use super::lifecycle::{BotState, BotTimeouts, Lifecycle};
 use super::*;
 use crate::audio_processor::biz_processor::{AsrProcessor, AudioBizProcessor, VadProcessor};
 use crate::config::AppConfig;
 use crate::error;
-use crate::msg_center::signaling_msgs::SignalingMessage;
-use crate::server::rtc::rtc_client::RTCClient;
+use crate::msg_center::signaling_msgs::{BotCommand, SignalingMessage, ERR_UNKNOWN_PROMPT};
+use crate::prompt::PromptPlayer;
+use crate::server::rtc::error::RtcError;
+use crate::server::rtc::factory::RtcFactory;
+use crate::server::rtc::quality::QualityStats;
+use crate::server::rtc::rtc_client::{RTCClient, RtcEvent};
 use crate::server::rtc::rtc_delegate::RTCDelegate;
 use crate::server::rtc::traits::WebRTCHandler;
 use crate::server::signal_cli::SERVER_ID;
+use crate::utils::log::session_logger;
+use slog::Logger;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
+use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+
+// 检查状态超时的间隔
+const LIFECYCLE_TICK: Duration = Duration::from_secs(1);
+
+// Bot 会话结束原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BotEndReason {
+    ClientDisconnect,
+    PeerFailed,
+    PeerClosed,
+    IceFailed,
+    Timeout(&'static str), // 某个阶段超时，见 Lifecycle::check_timeout
+    Shutdown,              // 消息通道关闭
+    Error(String),
+}
+
+impl std::fmt::Display for BotEndReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BotEndReason::ClientDisconnect => write!(f, "client_disconnect"),
+            BotEndReason::PeerFailed => write!(f, "peer_failed"),
+            BotEndReason::PeerClosed => write!(f, "peer_closed"),
+            BotEndReason::IceFailed => write!(f, "ice_failed"),
+            BotEndReason::Timeout(stage) => write!(f, "timeout_{}", stage),
+            BotEndReason::Shutdown => write!(f, "shutdown"),
+            BotEndReason::Error(e) => write!(f, "error: {}", e),
+        }
+    }
+}
 
 pub struct Bot {
     pub bot_id: String,
+    pub corr_id: String,
+    pub log: Logger,
     rtc: RTCClient,
+    lifecycle: Lifecycle,
+    // 本次断连已发起的 ICE restart 次数，连通后清零
+    ice_restarts: u32,
     cfg: AppConfig,
     audio_processor: Option<AudioBizProcessor>,
     audio_rx: Option<mpsc::Receiver<Vec<i16>>>,
+    // 下行音频入口，TTS 与提示音都写入这里
+    delegate: RTCDelegate,
+    prompt: PromptPlayer,
+    prompt_handle: Option<JoinHandle<()>>,
+    greeted: bool,
     message_rx: mpsc::Receiver<SignalingMessage>,
     ws_tx: mpsc::Sender<SignalingMessage>,
 
@@ -25,29 +75,57 @@ pub struct Bot {
 
 impl Bot {
     pub async fn new(
+        factory: Arc<RtcFactory>,
         cfg: AppConfig,
         client_id: String,
+        corr_id: String,
         ws_tx: mpsc::Sender<SignalingMessage>,
         message_rx: mpsc::Receiver<SignalingMessage>,
     ) -> Result<Self> {
         let bot_id = xid::new().to_string();
-        let (delegate, local_audio_rx) = RTCDelegate::new();
-        let mut rtc = RTCClient::new(client_id.clone(), bot_id.clone(), ws_tx.clone()).await?;
+        let log = session_logger(&corr_id, &client_id);
+        let (delegate, local_audio_rx, local_prompt_rx) = RTCDelegate::new();
+        let mut rtc = RTCClient::new(
+            factory,
+            cfg.rtc.clone(),
+            client_id.clone(),
+            bot_id.clone(),
+            corr_id.clone(),
+            log.clone(),
+            ws_tx.clone(),
+        )
+        .await?;
 
         let (audio_tx, audio_rx) = mpsc::channel(100);
-        rtc.set_remote_audio_tx(audio_tx);
+        rtc.set_remote_audio_tx(audio_tx, cfg.vad.sample_rate);
         rtc.set_local_audio_rx(local_audio_rx);
+        rtc.set_local_prompt_rx(local_prompt_rx);
         rtc.setup_pc_handlers().await?;
         rtc.setup_pc_other_handler()?;
         rtc.setup_media().await?;
 
-        info!("Bot created with id: {}", bot_id);
+        let (prompt, prompt_handle) = PromptPlayer::spawn(
+            delegate.get_local_prompt_tx(),
+            cfg.rtc.audio_sample_rate,
+            cfg.rtc.audio_channels,
+            log.clone(),
+        );
+
+        info!(log: log, "Bot created with id: {}", bot_id);
         Ok(Self {
             bot_id,
+            corr_id,
+            log,
             rtc,
+            lifecycle: Lifecycle::new(BotTimeouts::from(&cfg.bot), Instant::now()),
+            ice_restarts: 0,
             cfg,
             audio_processor: None,
             audio_rx: Some(audio_rx),
+            delegate,
+            prompt,
+            prompt_handle: Some(prompt_handle),
+            greeted: false,
             message_rx,
             ws_tx: ws_tx.clone(),
 
@@ -58,76 +136,317 @@ impl Bot {
 
     pub async fn setup_audio_processor(&mut self) {
         let audio_rx = self.audio_rx.take().unwrap();
-        let mut processor = AudioBizProcessor::new(audio_rx);
+        // 处理结果按 TTS 格式写入，重采样后由发送任务按 20ms 节奏编码发出
+        let mut processor = AudioBizProcessor::new(audio_rx, self.tts_audio_tx(), self.log.clone());
 
         // 添加音频处理能力
         processor.add_capability(Box::new(VadProcessor {}));
         processor.add_capability(Box::new(AsrProcessor {}));
 
         // 启动处理器
-        let processor_handle = tokio::spawn(async move {
-            processor.start().await;
-        });
+        let processor_handle = tokio::spawn(processor.start());
 
         self.processor_handle = Some(processor_handle);
     }
 
-    pub async fn handle_message(mut self) {
-        // 通过ws_tx发送BotConnected消息
-        //self.ws_tx.send(SignalingMessage::ClientConnected { client_id: self.client_id.clone(), server_id: SERVER_ID.clone() }).await.unwrap();
+    // 处理信令与连接状态，直到会话结束；返回结束原因
+    pub async fn handle_message(mut self) -> BotEndReason {
+        let mut rtc_events = self.rtc.take_event_rx().unwrap();
+        let media_rx = self.rtc.take_media_rx().unwrap();
+        let mut tick = tokio::time::interval(LIFECYCLE_TICK);
 
-        loop {
+        let reason = loop {
             tokio::select! {
                 control_msg = self.message_rx.recv() => {
-                    info!("Bot received message, {:?}", control_msg);
-                    if let Some(msg) = control_msg {
-                        match msg {
-
-                            SignalingMessage::Offer {from, to, sdp } => {
-                                info!("Bot received offer, {:?}", sdp);
-                                match self.rtc.handle_offer(sdp).await {
-                                    Ok(_) => {
-                                        info!("Bot sending answer done");
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to handle offer: {:?}", e);
+                    info!(log: self.log, "Bot received message, {:?}", control_msg);
+                    let Some(msg) = control_msg else {
+                        break BotEndReason::Shutdown;
+                    };
+                    match msg {
+                        SignalingMessage::Offer { sdp, .. } => {
+                            info!(log: self.log, "Bot received offer, {:?}", sdp);
+                            let prev = self.lifecycle.state();
+                            if self.lifecycle.on_offer(Instant::now()) {
+                                self.log_transition(prev);
+                            } else {
+                                info!(log: self.log, "Bot renegotiating in state {}", prev);
+                            }
+                            match self.rtc.handle_offer(sdp).await {
+                                Ok(_) => {
+                                    info!(log: self.log, "Bot sending answer done");
+                                }
+                                Err(e) => {
+                                    if let Some(reason) = self.on_rtc_error("handle offer", e).await {
+                                        break reason;
                                     }
                                 }
                             }
-                            // SignalingMessage::Answer { room_id, from, to, sdp } => todo!(),
-                            SignalingMessage::IceCandidate {from, to, candidate } => {
-                                match self.rtc.add_ice_candidate(candidate).await {
-                                    Ok(_) => {
-                                        info!("rtc client add ice candidate success");
-                                    }
-                                    Err(e) => {
-                                        error!("rtc client add ice candidate failed: {:?}", e);
+                        }
+                        SignalingMessage::Answer { sdp, .. } => {
+                            // 应答失败时由 ice_connect 超时兜底
+                            if let Err(e) = self.rtc.handle_answer(sdp).await {
+                                if let Some(reason) = self.on_rtc_error("handle answer", e).await {
+                                    break reason;
+                                }
+                            }
+                        }
+                        SignalingMessage::IceCandidate { candidate, .. } => {
+                            match self.rtc.add_ice_candidate(candidate).await {
+                                Ok(_) => {
+                                    info!(log: self.log, "rtc client add ice candidate success");
+                                }
+                                Err(e) => {
+                                    if let Some(reason) = self.on_rtc_error("add ice candidate", e).await {
+                                        break reason;
                                     }
                                 }
                             }
-                            _ => {
-                                error!("Bot received unknown message: {:?}", msg);
+                        }
+                        SignalingMessage::BotCommand { command, .. } => {
+                            if let Some(reason) = self.handle_command(command).await {
+                                break reason;
+                            }
+                        }
+                        SignalingMessage::ClientDisconnect { .. } => {
+                            break BotEndReason::ClientDisconnect;
+                        }
+                        _ => {
+                            error!(log: self.log, "Bot received unknown message: {:?}", msg);
+                        }
+                    }
+                }
+                Some(event) = rtc_events.recv() => {
+                    match event {
+                        // ICE 失败先于连接失败上报，重启进行中时忽略
+                        RtcEvent::PeerState(RTCPeerConnectionState::Failed) if !self.ice_restarting() => {
+                            break BotEndReason::PeerFailed;
+                        }
+                        RtcEvent::PeerState(RTCPeerConnectionState::Closed) => {
+                            break BotEndReason::PeerClosed;
+                        }
+                        RtcEvent::IceState(RTCIceConnectionState::Failed) => {
+                            if !self.try_ice_restart().await {
+                                break BotEndReason::IceFailed;
+                            }
+                        }
+                        RtcEvent::IceState(RTCIceConnectionState::Disconnected) => {
+                            // 网络切换时常见，可能自行恢复，失败后再重启
+                            warn!(log: self.log, "Bot {} ice disconnected", self.bot_id);
+                        }
+                        RtcEvent::PeerState(RTCPeerConnectionState::Connected)
+                        | RtcEvent::IceState(RTCIceConnectionState::Connected)
+                        | RtcEvent::IceState(RTCIceConnectionState::Completed) => {
+                            let prev = self.lifecycle.state();
+                            if self.lifecycle.on_connected(Instant::now()) {
+                                self.log_transition(prev);
                             }
+                            self.ice_restarts = 0;
+                            self.play_greeting().await;
                         }
+                        _ => {}
                     }
                 }
+                _ = tick.tick() => {
+                    // 媒体活动按 tick 采样，避免每个包都唤醒
+                    let last_media = *media_rx.borrow();
+                    if let Some(at) = last_media {
+                        let prev = self.lifecycle.state();
+                        if self.lifecycle.on_media(at) {
+                            self.log_transition(prev);
+                        }
+                    }
+                    if let Some(stage) = self.lifecycle.check_timeout(Instant::now()) {
+                        warn!(log: self.log, "Bot {} timed out in state {}", self.bot_id, self.lifecycle.state());
+                        break BotEndReason::Timeout(stage);
+                    }
+                }
+            }
+        };
+
+        self.shutdown(&reason).await;
+        reason
+    }
+
+    // 关闭连接、停止音频处理并上报结束原因
+    async fn shutdown(&mut self, reason: &BotEndReason) {
+        info!(log: self.log, "Bot {} ending: {}", self.bot_id, reason);
+        let prev = self.lifecycle.state();
+        self.lifecycle.on_closing(Instant::now());
+        self.log_transition(prev);
+        // 关闭前采集最后一次统计，输出本次会话的质量报告
+        self.rtc.refresh_quality().await;
+        info!(log: self.log, "Bot {} quality: {}", self.bot_id, self.quality());
+        self.rtc.close().await;
+        if let Some(handle) = self.processor_handle.take() {
+            handle.abort();
+        }
+        if let Some(handle) = self.prompt_handle.take() {
+            handle.abort();
+        }
+
+        // 客户端已离开时无需通知
+        if *reason != BotEndReason::ClientDisconnect {
+            let msg = SignalingMessage::BotEnded {
+                client_id: self.client_id.clone(),
+                reason: reason.to_string(),
+                corr_id: Some(self.corr_id.clone()),
+            };
+            if let Err(e) = self.ws_tx.send(msg).await {
+                warn!(log: self.log, "report bot end failed: {}", e);
+            }
+        }
+        self.lifecycle.on_closed(Instant::now());
+        self.log_transition(BotState::Closing);
+        info!(log: self.log, "Bot handle message loop exited");
+    }
+
+    // 客户端控制命令；增删本地轨道后由 bot 发起重协商，客户端回 Answer
+    async fn handle_command(&mut self, command: BotCommand) -> Option<BotEndReason> {
+        info!(log: self.log, "Bot received command {:?}", command);
+        let result = match command {
+            BotCommand::RemoveAudio => self.rtc.remove_local_audio().await,
+            BotCommand::RestoreAudio => self.rtc.restore_local_audio().await,
+            BotCommand::PlayPrompt { name, interrupt } => {
+                self.play_named_prompt(&name, interrupt).await;
+                return None;
+            }
+            BotCommand::StopPrompt => {
+                if let Err(e) = self.prompt.stop() {
+                    warn!(log: self.log, "Failed to stop prompt: {}", e);
+                }
+                return None;
+            }
+        };
+        match result {
+            Ok(_) => None,
+            Err(e) => self.on_rtc_error("handle command", e).await,
+        }
+    }
+
+    // 把 RTC 错误回给客户端；致命错误返回结束原因，只影响本会话
+    async fn on_rtc_error(&self, action: &str, err: RtcError) -> Option<BotEndReason> {
+        error!(log: self.log, "Bot failed to {}: {}", action, err);
+        if !matches!(err, RtcError::SignalingClosed) {
+            let msg = SignalingMessage::Error {
+                code: err.code(),
+                message: format!("{}: {}", action, err),
+                corr_id: Some(self.corr_id.clone()),
+            };
+            if let Err(e) = self.ws_tx.send(msg).await {
+                error!(log: self.log, "Failed to send error to client: {}", e);
+            }
+        }
+        if err.is_fatal() {
+            Some(BotEndReason::Error(format!("{}: {}", action, err)))
+        } else {
+            None
+        }
+    }
+
+    // TTS 输出的写入端，按 TTS 采样率单声道写入，重采样到轨道格式后发送
+    pub fn tts_audio_tx(&self) -> mpsc::Sender<crate::server::data::AudioData> {
+        self.delegate.get_converting_audio_tx(
+            self.cfg.tts.sample_rate,
+            1,
+            self.cfg.rtc.audio_sample_rate,
+            self.cfg.rtc.audio_channels,
+        )
+    }
+
+    // 当前的媒体质量统计快照
+    pub fn quality(&self) -> QualityStats {
+        self.rtc.quality()
+    }
+
+    // 由 BotManager 保存，会话进行中按关联ID查询质量统计
+    pub fn quality_handle(&self) -> Arc<std::sync::Mutex<QualityStats>> {
+        self.rtc.quality_handle()
+    }
+
+    pub fn prompt_player(&self) -> &PromptPlayer {
+        &self.prompt
+    }
 
+    // 首次连通后播放欢迎语，只播一次
+    async fn play_greeting(&mut self) {
+        if self.greeted || self.cfg.bot.greeting_prompt.is_empty() {
+            return;
+        }
+        self.greeted = true;
+        let path = self.cfg.bot.greeting_prompt.clone();
+        if let Err(e) = self.prompt.enqueue_file(&path).await {
+            error!(log: self.log, "Failed to play greeting {}: {}", path, e);
+        }
+    }
+
+    // 只播放配置中登记的提示音，客户端不能指定任意路径
+    async fn play_named_prompt(&self, name: &str, interrupt: bool) {
+        let Some(path) = self.cfg.prompt.named.get(name).cloned() else {
+            warn!(log: self.log, "Unknown prompt {}", name);
+            let msg = SignalingMessage::Error {
+                code: ERR_UNKNOWN_PROMPT,
+                message: format!("unknown prompt: {}", name),
+                corr_id: Some(self.corr_id.clone()),
+            };
+            if let Err(e) = self.ws_tx.send(msg).await {
+                error!(log: self.log, "Failed to send error to client: {}", e);
             }
+            return;
+        };
+        let result = if interrupt {
+            self.prompt.play_file_now(&path).await
+        } else {
+            self.prompt.enqueue_file(&path).await
+        };
+        if let Err(e) = result {
+            error!(log: self.log, "Failed to play prompt {} ({}): {}", name, path, e);
         }
+    }
 
-        info!("Bot handle message loop exited");
+    fn ice_restarting(&self) -> bool {
+        self.ice_restarts > 0 && self.lifecycle.state() == BotState::Negotiating
+    }
+
+    // ICE 失败时由 bot 发起 ICE restart，超过次数或尚未连通过时返回 false
+    async fn try_ice_restart(&mut self) -> bool {
+        if self.ice_restarts >= self.cfg.bot.ice_restart_attempts {
+            return false;
+        }
+        let prev = self.lifecycle.state();
+        if self.lifecycle.on_restart(Instant::now()) {
+            self.log_transition(prev);
+        } else if !self.ice_restarting() {
+            return false;
+        }
+        self.ice_restarts += 1;
+        warn!(log: self.log, "Bot {} ice restart attempt {}", self.bot_id, self.ice_restarts);
+        if let Err(e) = self.rtc.send_offer(true).await {
+            error!(log: self.log, "Failed to send ice restart offer: {:?}", e);
+            return false;
+        }
+        true
+    }
+
+    fn log_transition(&self, prev: BotState) {
+        info!(log: self.log, "Bot {} state {} -> {}", self.bot_id, prev, self.lifecycle.state());
     }
 }
 
 impl WebRTCHandler for Bot {
     async fn generate_answer(&mut self, offer_sdp: String) -> String {
         //let audio_tx = self.audio_tx.take().unwrap();
-        self.rtc.handle_offer(offer_sdp).await.unwrap()
+        match self.rtc.handle_offer(offer_sdp).await {
+            Ok(sdp) => sdp,
+            Err(e) => {
+                error!(log: self.log, "Failed to generate answer: {}", e);
+                String::new()
+            }
+        }
     }
 
     async fn handle_candidate(&mut self, candidate: String) {
         if let Err(e) = self.rtc.add_ice_candidate(candidate).await {
-            error!("Failed to add ICE candidate: {:?}", e);
+            error!(log: self.log, "Failed to add ICE candidate: {:?}", e);
         }
     }
 }
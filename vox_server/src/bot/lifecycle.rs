@@ -0,0 +1,253 @@
+use std::time::{Duration, Instant};
+
+use crate::config::BotConfig;
+
+// Bot 会话状态
+// Created -> Negotiating -> Connected -> Active -> Closing -> Closed
+// ICE restart 时 Connected/Active 回到 Negotiating
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotState {
+    Created,     // 已创建，等待 Offer
+    Negotiating, // 已应答，等待 ICE 连通
+    Connected,   // ICE 已连通，等待媒体
+    Active,      // 已收到远端音频
+    Closing,
+    Closed,
+}
+
+impl std::fmt::Display for BotState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            BotState::Created => "created",
+            BotState::Negotiating => "negotiating",
+            BotState::Connected => "connected",
+            BotState::Active => "active",
+            BotState::Closing => "closing",
+            BotState::Closed => "closed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BotTimeouts {
+    pub offer_wait: Duration,
+    pub ice_connect: Duration,
+    pub media_inactivity: Duration,
+}
+
+impl From<&BotConfig> for BotTimeouts {
+    fn from(cfg: &BotConfig) -> Self {
+        Self {
+            offer_wait: Duration::from_millis(cfg.offer_wait_ms),
+            ice_connect: Duration::from_millis(cfg.ice_connect_ms),
+            media_inactivity: Duration::from_millis(cfg.media_inactivity_ms),
+        }
+    }
+}
+
+// 状态迁移与超时判断，不涉及 IO，由 Bot 根据 RTCClient 回调驱动
+pub struct Lifecycle {
+    state: BotState,
+    entered_at: Instant,
+    last_media_at: Option<Instant>,
+    timeouts: BotTimeouts,
+}
+
+impl Lifecycle {
+    pub fn new(timeouts: BotTimeouts, now: Instant) -> Self {
+        Self {
+            state: BotState::Created,
+            entered_at: now,
+            last_media_at: None,
+            timeouts,
+        }
+    }
+
+    pub fn state(&self) -> BotState {
+        self.state
+    }
+
+    // 迁移成功返回 true；不合法的迁移保持原状态
+    fn transit(&mut self, from: &[BotState], to: BotState, now: Instant) -> bool {
+        if !from.contains(&self.state) {
+            return false;
+        }
+        self.state = to;
+        self.entered_at = now;
+        true
+    }
+
+    pub fn on_offer(&mut self, now: Instant) -> bool {
+        self.transit(&[BotState::Created], BotState::Negotiating, now)
+    }
+
+    pub fn on_connected(&mut self, now: Instant) -> bool {
+        self.transit(&[BotState::Negotiating], BotState::Connected, now)
+    }
+
+    // 重启前的媒体时间不再有效，重新连通后按新的媒体判断 Active 与超时
+    pub fn on_restart(&mut self, now: Instant) -> bool {
+        if !self.transit(
+            &[BotState::Connected, BotState::Active],
+            BotState::Negotiating,
+            now,
+        ) {
+            return false;
+        }
+        self.last_media_at = None;
+        true
+    }
+
+    // 早于进入当前状态的媒体时间（如重启前采样到的值）忽略
+    pub fn on_media(&mut self, at: Instant) -> bool {
+        if !matches!(self.state, BotState::Connected | BotState::Active) || at < self.entered_at {
+            return false;
+        }
+        self.last_media_at = Some(at);
+        self.transit(&[BotState::Connected], BotState::Active, at)
+    }
+
+    pub fn on_closing(&mut self, now: Instant) -> bool {
+        self.transit(
+            &[
+                BotState::Created,
+                BotState::Negotiating,
+                BotState::Connected,
+                BotState::Active,
+            ],
+            BotState::Closing,
+            now,
+        )
+    }
+
+    pub fn on_closed(&mut self, now: Instant) -> bool {
+        self.transit(&[BotState::Closing], BotState::Closed, now)
+    }
+
+    // 当前状态已超时时返回超时名称
+    pub fn check_timeout(&self, now: Instant) -> Option<&'static str> {
+        let in_state = now.saturating_duration_since(self.entered_at);
+        match self.state {
+            BotState::Created if in_state >= self.timeouts.offer_wait => Some("offer_wait"),
+            BotState::Negotiating if in_state >= self.timeouts.ice_connect => Some("ice_connect"),
+            BotState::Connected | BotState::Active => {
+                let since = self.last_media_at.unwrap_or(self.entered_at);
+                if now.saturating_duration_since(since) >= self.timeouts.media_inactivity {
+                    Some("media_inactivity")
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeouts() -> BotTimeouts {
+        BotTimeouts {
+            offer_wait: Duration::from_secs(10),
+            ice_connect: Duration::from_secs(5),
+            media_inactivity: Duration::from_secs(3),
+        }
+    }
+
+    #[test]
+    fn test_happy_path_transitions() {
+        let t0 = Instant::now();
+        let mut lc = Lifecycle::new(timeouts(), t0);
+        assert!(lc.on_offer(t0));
+        assert!(lc.on_connected(t0));
+        assert!(lc.on_media(t0));
+        assert_eq!(lc.state(), BotState::Active);
+        // 持续收到媒体不再迁移
+        assert!(!lc.on_media(t0));
+        assert!(lc.on_closing(t0));
+        assert!(lc.on_closed(t0));
+        assert_eq!(lc.state(), BotState::Closed);
+        assert!(!lc.on_closing(t0));
+    }
+
+    #[test]
+    fn test_invalid_transitions_are_ignored() {
+        let t0 = Instant::now();
+        let mut lc = Lifecycle::new(timeouts(), t0);
+        assert!(!lc.on_connected(t0));
+        assert!(!lc.on_media(t0));
+        assert!(!lc.on_closed(t0));
+        assert_eq!(lc.state(), BotState::Created);
+    }
+
+    #[test]
+    fn test_setup_timeouts() {
+        let t0 = Instant::now();
+        let mut lc = Lifecycle::new(timeouts(), t0);
+        assert_eq!(lc.check_timeout(t0 + Duration::from_secs(9)), None);
+        assert_eq!(lc.check_timeout(t0 + Duration::from_secs(10)), Some("offer_wait"));
+
+        let t1 = t0 + Duration::from_secs(9);
+        lc.on_offer(t1);
+        assert_eq!(lc.check_timeout(t1 + Duration::from_secs(4)), None);
+        assert_eq!(lc.check_timeout(t1 + Duration::from_secs(5)), Some("ice_connect"));
+    }
+
+    #[test]
+    fn test_media_inactivity() {
+        let t0 = Instant::now();
+        let mut lc = Lifecycle::new(timeouts(), t0);
+        lc.on_offer(t0);
+        lc.on_connected(t0);
+        // 连通后一直没有媒体
+        assert_eq!(lc.check_timeout(t0 + Duration::from_secs(3)), Some("media_inactivity"));
+
+        lc.on_media(t0 + Duration::from_secs(2));
+        assert_eq!(lc.check_timeout(t0 + Duration::from_secs(4)), None);
+        assert_eq!(lc.check_timeout(t0 + Duration::from_secs(5)), Some("media_inactivity"));
+
+        lc.on_closing(t0 + Duration::from_secs(5));
+        assert_eq!(lc.check_timeout(t0 + Duration::from_secs(60)), None);
+    }
+
+    #[test]
+    fn test_ice_restart() {
+        let t0 = Instant::now();
+        let mut lc = Lifecycle::new(timeouts(), t0);
+        // 尚未连通时不能重启
+        assert!(!lc.on_restart(t0));
+        lc.on_offer(t0);
+        lc.on_connected(t0);
+        lc.on_media(t0);
+
+        let t1 = t0 + Duration::from_secs(1);
+        assert!(lc.on_restart(t1));
+        assert_eq!(lc.state(), BotState::Negotiating);
+        assert_eq!(lc.check_timeout(t1 + Duration::from_secs(5)), Some("ice_connect"));
+        assert!(lc.on_connected(t1));
+    }
+
+    #[test]
+    fn test_stale_media_after_restart() {
+        let t0 = Instant::now();
+        let mut lc = Lifecycle::new(timeouts(), t0);
+        lc.on_offer(t0);
+        lc.on_connected(t0);
+        lc.on_media(t0);
+
+        let t1 = t0 + Duration::from_secs(10);
+        lc.on_restart(t1);
+        let t2 = t1 + Duration::from_secs(1);
+        assert!(lc.on_connected(t2));
+        // 重启前的媒体时间不能让 bot 进入 Active，也不计入不活跃超时
+        assert!(!lc.on_media(t0));
+        assert_eq!(lc.state(), BotState::Connected);
+        assert_eq!(lc.check_timeout(t2 + Duration::from_secs(2)), None);
+        assert_eq!(lc.check_timeout(t2 + Duration::from_secs(3)), Some("media_inactivity"));
+
+        assert!(lc.on_media(t2 + Duration::from_secs(1)));
+        assert_eq!(lc.state(), BotState::Active);
+    }
+}
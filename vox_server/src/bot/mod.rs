@@ -1,5 +1,6 @@
 pub mod bot;
 pub mod bot_manager;
+pub mod lifecycle;
 
 use crate::{debug, error, info, warn};
 use anyhow::Result;
@@ -1,91 +1,881 @@
-use config::{Config, ConfigError, File};
+use config::{Config, ConfigError, Environment, File};
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{watch, RwLock};
+use url::Url;
+
+use crate::{error, info};
+
+// 环境变量覆盖配置项，如 VOX_SERVER__SIGNALING_SERVER、VOX_LIMITS__MAX_BOTS
+const ENV_PREFIX: &str = "VOX";
+const ENV_SEPARATOR: &str = "__";
+// 未传 --config 时从该环境变量读取配置文件路径
+const ENV_CONFIG_PATH: &str = "VOX_CONFIG";
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ServerConfig {
     pub signaling_server: String,
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+}
+
+// 与信令服务器断开期间出站消息的处理策略
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OfflinePolicy {
+    #[default]
+    Buffer, // 缓存，重连后补发；超出上限时丢弃最旧的消息
+    Drop,   // 直接丢弃
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct ReconnectConfig {
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub offline_policy: OfflinePolicy,
+    pub offline_buffer_size: usize,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            offline_policy: OfflinePolicy::Buffer,
+            offline_buffer_size: 256,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct RtcConfig {}
+pub struct IceServerConfig {
+    pub urls: Vec<String>, // stun:/turn:/turns: 地址
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub credential: String,
+}
+
+// 音频轨道参数与 ICE/网络设置，应用到每个 bot 的 PeerConnection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct RtcConfig {
+    // bot 内部 PCM 的采样率与声道数，Opus 按此编解码；RTP 时钟固定为 48000
+    pub audio_sample_rate: u32,
+    pub audio_channels: u16,
+
+    pub ice_servers: Vec<IceServerConfig>,
+    // 1:1 NAT 的公网 IP；candidate_type 为 host 时替换主机候选，为 srflx 时追加反射候选
+    pub nat_1to1_ips: Vec<String>,
+    pub nat_1to1_candidate_type: String,
+    // 临时 UDP 端口范围，都为 0 时由系统分配
+    pub udp_port_min: u16,
+    pub udp_port_max: u16,
+    // 非 0 时进程内所有 PeerConnection 共用这个 IPv4 UDP 端口，与端口范围互斥；修改需重启
+    pub udp_mux_port: u16,
+    // 非 0 时在这个 TCP 端口提供 ICE-TCP passive，转发到 udp_mux_port；修改需重启
+    pub ice_tcp_port: u16,
+    // udp4/udp6，为空时使用全部；ICE-TCP 由 ice_tcp_port 提供
+    pub network_types: Vec<String>,
+    pub ice_lite: bool,
+    // 预先创建、等待 Offer 的 PeerConnection 数量，0 表示不预热
+    pub warm_pool_size: usize,
+    // 接收抖动缓冲：目标延迟为下限，随抖动自适应增大，不超过最大延迟
+    pub jitter_target_ms: u64,
+    pub jitter_max_ms: u64,
+    // 本地轨道的 Opus 编码参数
+    pub opus: OpusConfig,
+    // 协商时注册的编码及 Opus fmtp 参数
+    pub codecs: CodecConfig,
+    // 媒体质量统计的采集间隔
+    pub stats_interval_ms: u64,
+
+    // ICE 超时，未设置时使用 webrtc 默认值
+    pub ice_disconnected_timeout_ms: Option<u64>,
+    pub ice_failed_timeout_ms: Option<u64>,
+    pub ice_keepalive_interval_ms: Option<u64>,
+    // 各类候选收集后的最短等待时间
+    pub host_acceptance_min_wait_ms: Option<u64>,
+    pub srflx_acceptance_min_wait_ms: Option<u64>,
+    pub relay_acceptance_min_wait_ms: Option<u64>,
+}
+
+impl Default for RtcConfig {
+    fn default() -> Self {
+        Self {
+            audio_sample_rate: 48000,
+            audio_channels: 1,
+            ice_servers: Vec::new(),
+            nat_1to1_ips: Vec::new(),
+            nat_1to1_candidate_type: "host".to_string(),
+            udp_port_min: 0,
+            udp_port_max: 0,
+            udp_mux_port: 0,
+            ice_tcp_port: 0,
+            network_types: Vec::new(),
+            ice_lite: false,
+            warm_pool_size: 0,
+            jitter_target_ms: 40,
+            jitter_max_ms: 200,
+            opus: OpusConfig::default(),
+            codecs: CodecConfig::default(),
+            stats_interval_ms: 5000,
+            ice_disconnected_timeout_ms: None,
+            ice_failed_timeout_ms: None,
+            ice_keepalive_interval_ms: None,
+            host_acceptance_min_wait_ms: None,
+            srflx_acceptance_min_wait_ms: None,
+            relay_acceptance_min_wait_ms: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct OpusConfig {
+    pub application: String, // voip/audio/lowdelay
+    pub bitrate: i32,        // bps，0 表示由编码器决定
+    pub complexity: u8,      // 0-10
+    pub vbr: bool,
+    pub vbr_constraint: bool,
+    pub dtx: bool,
+    pub inband_fec: bool,
+    pub packet_loss_perc: u8, // 预期丢包率，FEC 按此分配码率
+}
+
+impl Default for OpusConfig {
+    fn default() -> Self {
+        Self {
+            application: "voip".to_string(),
+            bitrate: 0,
+            complexity: 9,
+            vbr: true,
+            vbr_constraint: false,
+            dtx: false,
+            inband_fec: true,
+            packet_loss_perc: 10,
+        }
+    }
+}
+
+// 未注册的编码不参与协商，对应的 m-line 在 Answer 中被拒绝（端口为 0）
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct CodecConfig {
+    pub audio: Vec<String>, // 按优先级排列：opus/pcmu/pcma，本地轨道按协商结果发送
+    pub accept_video: bool, // 为 false 时不注册视频编码，视频 m-line 被拒绝
+    pub opus_ptime: u32,    // 期望收到的包时长，ms
+    pub opus_max_average_bitrate: u32, // bps，0 表示不声明
+    pub opus_stereo: bool,
+    pub opus_usedtx: bool,
+    pub opus_useinbandfec: bool,
+}
+
+impl Default for CodecConfig {
+    fn default() -> Self {
+        Self {
+            audio: vec!["opus".to_string(), "pcmu".to_string(), "pcma".to_string()],
+            accept_video: false,
+            opus_ptime: 20,
+            opus_max_average_bitrate: 0,
+            opus_stereo: false,
+            opus_usedtx: false,
+            opus_useinbandfec: true,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct VadConfig {
+    pub model_path: String,
+    pub sample_rate: u32, // silero 支持 8000/16000
+    pub frame_ms: u32,
+    pub max_cache_frames: usize, // 确认说话前最多缓存的帧数
+}
+
+impl Default for VadConfig {
+    fn default() -> Self {
+        Self {
+            model_path: String::new(),
+            sample_rate: 16000,
+            frame_ms: 32,
+            max_cache_frames: 100,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct AsrConfig {
+    pub provider: String, // 为空表示不启用
+    pub endpoint: String,
+    pub api_key: String,
+    pub language: String,
+}
+
+impl Default for AsrConfig {
+    fn default() -> Self {
+        Self {
+            provider: String::new(),
+            endpoint: String::new(),
+            api_key: String::new(),
+            language: "zh".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct LlmConfig {
+    pub provider: String, // 为空表示不启用
+    pub endpoint: String,
+    pub api_key: String,
+    pub model: String,
+    pub system_prompt: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct TtsConfig {
+    pub provider: String, // 为空表示不启用
+    pub endpoint: String,
+    pub api_key: String,
+    pub voice: String,
+    pub sample_rate: u32,
+}
+
+impl Default for TtsConfig {
+    fn default() -> Self {
+        Self {
+            provider: String::new(),
+            endpoint: String::new(),
+            api_key: String::new(),
+            voice: String::new(),
+            sample_rate: 24000,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LimitsConfig {
+    pub max_bots: usize,         // 同时存在的 bot 上限
+    pub bot_channel_size: usize, // 每个 bot 信令消息通道容量
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_bots: 100,
+            bot_channel_size: 100,
+        }
+    }
+}
+
+// Bot 各阶段超时
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct BotConfig {
+    pub offer_wait_ms: u64,       // 创建后等待 Offer
+    pub ice_connect_ms: u64,      // 应答后等待 ICE 连通
+    pub media_inactivity_ms: u64, // 连通后没有远端音频
+    pub ice_restart_attempts: u32, // ICE 失败后 bot 主动重启的次数，0 表示直接结束
+    pub greeting_prompt: String,   // 连通后播放的提示音文件（wav/opus/ogg），为空不播放
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            offer_wait_ms: 30_000,
+            ice_connect_ms: 20_000,
+            media_inactivity_ms: 60_000,
+            ice_restart_attempts: 2,
+            greeting_prompt: String::new(),
+        }
+    }
+}
+
+// 提示音缓存，文件预先解码并编码为 20ms Opus 包
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct PromptConfig {
+    pub preload: Vec<String>, // 启动时加载的文件，其余在首次播放时加载
+    pub cache_max_bytes: usize,
+    // 客户端可通过 play_prompt 命令按名称播放的提示音，名称 -> 文件
+    pub named: HashMap<String, String>,
+}
+
+impl Default for PromptConfig {
+    fn default() -> Self {
+        Self {
+            preload: Vec::new(),
+            cache_max_bytes: 32 * 1024 * 1024,
+            named: HashMap::new(),
+        }
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LogConfig {
     pub level: String,
-    pub path: String,
+    pub path: String, // 为空时只输出到终端
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppConfig {
     pub server: ServerConfig,
     pub log: LogConfig,
+    #[serde(default)]
     pub rtc: RtcConfig,
+    #[serde(default)]
+    pub vad: VadConfig,
+    #[serde(default)]
+    pub asr: AsrConfig,
+    #[serde(default)]
+    pub llm: LlmConfig,
+    #[serde(default)]
+    pub tts: TtsConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub bot: BotConfig,
+    #[serde(default)]
+    pub prompt: PromptConfig,
 }
 
 // 全局配置实例
+// Bot 创建时拷贝一份，热更新只影响之后创建的 Bot
 pub static CONFIG: Lazy<Arc<RwLock<AppConfig>>> =
     Lazy::new(|| Arc::new(RwLock::new(AppConfig::default())));
 
+// 每次重新加载配置后递增，需要跟随配置变化的模块通过 config_reloaded 订阅
+static CONFIG_RELOADED: Lazy<watch::Sender<u64>> = Lazy::new(|| watch::channel(0).0);
+
+pub fn config_reloaded() -> watch::Receiver<u64> {
+    CONFIG_RELOADED.subscribe()
+}
+
+// 配置文件的 watcher
+static CONFIG_WATCHER: Lazy<std::sync::Mutex<Option<RecommendedWatcher>>> =
+    Lazy::new(|| std::sync::Mutex::new(None));
+
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
             server: ServerConfig {
                 signaling_server: "ws://127.0.0.1:9527/ws/server".to_string(),
+                reconnect: ReconnectConfig::default(),
             },
             log: LogConfig {
                 level: "info".to_string(),
                 path: "./logs".to_string(),
             },
-            rtc: RtcConfig {},
+            rtc: RtcConfig::default(),
+            vad: VadConfig::default(),
+            asr: AsrConfig::default(),
+            llm: LlmConfig::default(),
+            tts: TtsConfig::default(),
+            limits: LimitsConfig::default(),
+            bot: BotConfig::default(),
+            prompt: PromptConfig::default(),
         }
     }
 }
 
+// 命令行 `--config <path>` / `--config=<path>` / `-c <path>`，其次是 VOX_CONFIG 环境变量
+pub fn config_path_from_args() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" || arg == "-c" {
+            return args.next();
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(path.to_string());
+        }
+    }
+    std::env::var(ENV_CONFIG_PATH).ok().filter(|p| !p.is_empty())
+}
+
+fn invalid(field: &str, reason: impl std::fmt::Display) -> ConfigError {
+    ConfigError::Message(format!("invalid config `{}`: {}", field, reason))
+}
+
 impl AppConfig {
     pub async fn load(config_path: &str) -> Result<Self, ConfigError> {
-        let config = Config::builder()
-            .add_source(File::with_name(config_path))
+        Self::build(Some(config_path))
+    }
+
+    // 没有配置文件时只应用环境变量
+    pub async fn load_env() -> Result<Self, ConfigError> {
+        Self::build(None)
+    }
+
+    // 默认值 <- 配置文件 <- 环境变量，合并后校验
+    fn build(config_path: Option<&str>) -> Result<Self, ConfigError> {
+        let mut builder = Config::builder().add_source(Config::try_from(&AppConfig::default())?);
+        if let Some(path) = config_path {
+            builder = builder.add_source(File::with_name(path));
+        }
+        let config = builder
+            .add_source(
+                Environment::with_prefix(ENV_PREFIX)
+                    .prefix_separator("_")
+                    .separator(ENV_SEPARATOR)
+                    .try_parsing(true),
+            )
             .build()?;
 
-        config.try_deserialize()
+        let app_config: AppConfig = config.try_deserialize()?;
+        app_config.validate()?;
+        Ok(app_config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let url = Url::parse(&self.server.signaling_server)
+            .map_err(|e| invalid("server.signaling_server", e))?;
+        if url.scheme() != "ws" && url.scheme() != "wss" {
+            return Err(invalid(
+                "server.signaling_server",
+                "scheme must be ws or wss",
+            ));
+        }
+
+        let reconnect = &self.server.reconnect;
+        if reconnect.initial_backoff_ms == 0 {
+            return Err(invalid("server.reconnect.initial_backoff_ms", "must be > 0"));
+        }
+        if reconnect.max_backoff_ms < reconnect.initial_backoff_ms {
+            return Err(invalid(
+                "server.reconnect.max_backoff_ms",
+                "must be >= initial_backoff_ms",
+            ));
+        }
+
+        if slog::Level::from_str(&self.log.level).is_err() {
+            return Err(invalid("log.level", format!("unknown level {}", self.log.level)));
+        }
+
+        if !matches!(self.rtc.audio_sample_rate, 8000 | 12000 | 16000 | 24000 | 48000) {
+            return Err(invalid(
+                "rtc.audio_sample_rate",
+                "must be 8000, 12000, 16000, 24000 or 48000",
+            ));
+        }
+        if !(1..=2).contains(&self.rtc.audio_channels) {
+            return Err(invalid("rtc.audio_channels", "must be 1 or 2"));
+        }
+        self.validate_rtc_network()?;
+        self.validate_opus()?;
+        self.validate_codecs()?;
+
+        if self.vad.sample_rate != 8000 && self.vad.sample_rate != 16000 {
+            return Err(invalid("vad.sample_rate", "must be 8000 or 16000"));
+        }
+        if self.vad.frame_ms == 0 {
+            return Err(invalid("vad.frame_ms", "must be > 0"));
+        }
+
+        for (field, provider, endpoint) in [
+            ("asr.endpoint", &self.asr.provider, &self.asr.endpoint),
+            ("llm.endpoint", &self.llm.provider, &self.llm.endpoint),
+            ("tts.endpoint", &self.tts.provider, &self.tts.endpoint),
+        ] {
+            if !provider.is_empty() && endpoint.is_empty() {
+                return Err(invalid(field, format!("required by provider {}", provider)));
+            }
+        }
+        if !self.llm.provider.is_empty() && self.llm.model.is_empty() {
+            return Err(invalid("llm.model", "required when llm.provider is set"));
+        }
+        if self.tts.sample_rate == 0 {
+            return Err(invalid("tts.sample_rate", "must be > 0"));
+        }
+
+        if self.limits.max_bots == 0 {
+            return Err(invalid("limits.max_bots", "must be > 0"));
+        }
+        if self.limits.bot_channel_size == 0 {
+            return Err(invalid("limits.bot_channel_size", "must be > 0"));
+        }
+        if self.prompt.cache_max_bytes == 0 {
+            return Err(invalid("prompt.cache_max_bytes", "must be > 0"));
+        }
+
+        for (field, value) in [
+            ("bot.offer_wait_ms", self.bot.offer_wait_ms),
+            ("bot.ice_connect_ms", self.bot.ice_connect_ms),
+            ("bot.media_inactivity_ms", self.bot.media_inactivity_ms),
+        ] {
+            if value == 0 {
+                return Err(invalid(field, "must be > 0"));
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_rtc_network(&self) -> Result<(), ConfigError> {
+        let rtc = &self.rtc;
+        for server in &rtc.ice_servers {
+            if server.urls.is_empty() {
+                return Err(invalid("rtc.ice_servers", "urls must not be empty"));
+            }
+            for url in &server.urls {
+                let scheme = url.split(':').next().unwrap_or_default();
+                if !matches!(scheme, "stun" | "stuns" | "turn" | "turns") {
+                    return Err(invalid("rtc.ice_servers", format!("unsupported url {}", url)));
+                }
+                if scheme.starts_with("turn") && server.username.is_empty() {
+                    return Err(invalid("rtc.ice_servers", format!("{} requires username", url)));
+                }
+            }
+        }
+
+        for ip in &rtc.nat_1to1_ips {
+            if ip.parse::<std::net::IpAddr>().is_err() {
+                return Err(invalid("rtc.nat_1to1_ips", format!("bad ip {}", ip)));
+            }
+        }
+        match rtc.nat_1to1_candidate_type.as_str() {
+            "host" => {}
+            // webrtc 不允许 srflx 类型与 STUN 同时使用
+            "srflx" if !rtc.nat_1to1_ips.is_empty() && !rtc.ice_servers.is_empty() => {
+                return Err(invalid(
+                    "rtc.nat_1to1_candidate_type",
+                    "srflx cannot be used together with ice_servers",
+                ));
+            }
+            "srflx" => {}
+            other => {
+                return Err(invalid(
+                    "rtc.nat_1to1_candidate_type",
+                    format!("must be host or srflx, got {}", other),
+                ));
+            }
+        }
+
+        let ports_set = rtc.udp_port_min != 0 || rtc.udp_port_max != 0;
+        if ports_set && (rtc.udp_port_min == 0 || rtc.udp_port_min > rtc.udp_port_max) {
+            return Err(invalid(
+                "rtc.udp_port_min",
+                "udp_port_min and udp_port_max must both be set with min <= max",
+            ));
+        }
+
+        if rtc.jitter_max_ms == 0 || rtc.jitter_target_ms > rtc.jitter_max_ms {
+            return Err(invalid(
+                "rtc.jitter_target_ms",
+                "jitter_max_ms must be > 0 and >= jitter_target_ms",
+            ));
+        }
+
+        if rtc.stats_interval_ms == 0 {
+            return Err(invalid("rtc.stats_interval_ms", "must be > 0"));
+        }
+
+        if rtc.udp_mux_port != 0 && ports_set {
+            return Err(invalid(
+                "rtc.udp_mux_port",
+                "cannot be used together with udp_port_min/udp_port_max",
+            ));
+        }
+        if rtc.udp_mux_port != 0 && rtc.network_types.iter().any(|t| t == "udp6") {
+            return Err(invalid("rtc.network_types", "udp_mux_port only supports udp4"));
+        }
+        if rtc.ice_tcp_port != 0 && rtc.udp_mux_port == 0 {
+            return Err(invalid("rtc.ice_tcp_port", "requires udp_mux_port"));
+        }
+
+        for network_type in &rtc.network_types {
+            match network_type.as_str() {
+                "udp4" | "udp6" => {}
+                "tcp4" | "tcp6" => {
+                    return Err(invalid(
+                        "rtc.network_types",
+                        format!(
+                            "{} is not supported by the ICE agent, use ice_tcp_port for ICE-TCP passive",
+                            network_type
+                        ),
+                    ));
+                }
+                other => {
+                    return Err(invalid(
+                        "rtc.network_types",
+                        format!("unknown network type {}", other),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_opus(&self) -> Result<(), ConfigError> {
+        let opus = &self.rtc.opus;
+        if !matches!(opus.application.as_str(), "voip" | "audio" | "lowdelay") {
+            return Err(invalid(
+                "rtc.opus.application",
+                format!("unknown application {}", opus.application),
+            ));
+        }
+        if opus.bitrate != 0 && !(500..=512_000).contains(&opus.bitrate) {
+            return Err(invalid("rtc.opus.bitrate", "must be 0 or 500..=512000"));
+        }
+        if opus.complexity > 10 {
+            return Err(invalid("rtc.opus.complexity", "must be <= 10"));
+        }
+        if opus.packet_loss_perc > 100 {
+            return Err(invalid("rtc.opus.packet_loss_perc", "must be <= 100"));
+        }
+        Ok(())
+    }
+
+    fn validate_codecs(&self) -> Result<(), ConfigError> {
+        let codecs = &self.rtc.codecs;
+        for (i, codec) in codecs.audio.iter().enumerate() {
+            if !matches!(codec.as_str(), "opus" | "pcmu" | "pcma") {
+                return Err(invalid(
+                    "rtc.codecs.audio",
+                    format!("unknown codec {}", codec),
+                ));
+            }
+            if codecs.audio[..i].contains(codec) {
+                return Err(invalid(
+                    "rtc.codecs.audio",
+                    format!("duplicate codec {}", codec),
+                ));
+            }
+        }
+        if codecs.audio.is_empty() {
+            return Err(invalid("rtc.codecs.audio", "must not be empty"));
+        }
+        if !matches!(codecs.opus_ptime, 10 | 20 | 40 | 60 | 80 | 100 | 120) {
+            return Err(invalid(
+                "rtc.codecs.opus_ptime",
+                "must be 10, 20, 40, 60, 80, 100 or 120",
+            ));
+        }
+        let bitrate = codecs.opus_max_average_bitrate;
+        if bitrate != 0 && !(6000..=510_000).contains(&bitrate) {
+            return Err(invalid(
+                "rtc.codecs.opus_max_average_bitrate",
+                "must be 0 or 6000..=510000",
+            ));
+        }
+        Ok(())
     }
 
+    // 校验失败时保留旧配置
     pub async fn reload(config_path: &str) -> Result<(), ConfigError> {
         let new_config = Self::load(config_path).await?;
         let mut config = CONFIG.write().await;
         *config = new_config;
+        drop(config);
+        CONFIG_RELOADED.send_modify(|version| *version += 1);
         Ok(())
     }
 
+    // 监听配置文件所在目录并按文件名过滤：编辑器保存时常用临时文件 rename 覆盖，
+    // 直接监听文件会在第一次保存后失效
     pub fn watch_config(config_path: String) -> notify::Result<()> {
+        let file = Path::new(&config_path).canonicalize()?;
+        let dir = file
+            .parent()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| notify::Error::generic("config file has no parent directory"))?;
         let path = config_path.clone();
+        // notify 回调不在 tokio 线程上，需要通过 handle 投递任务
+        let handle = tokio::runtime::Handle::current();
         let mut watcher = RecommendedWatcher::new(
-            move |res| {
+            move |res: notify::Result<notify::Event>| {
                 let path = path.clone();
                 match res {
-                    Ok(_) => {
-                        tokio::spawn(async move {
-                            if let Err(e) = AppConfig::reload(&path).await {
-                                eprintln!("Failed to reload config: {}", e);
+                    Ok(event)
+                        if (event.kind.is_modify() || event.kind.is_create())
+                            && event.paths.iter().any(|p| is_config_path(&file, p)) =>
+                    {
+                        handle.spawn(async move {
+                            match AppConfig::reload(&path).await {
+                                Ok(()) => info!("config reloaded from {}", path),
+                                Err(e) => error!("Failed to reload config: {}", e),
                             }
                         });
                     }
-                    Err(e) => eprintln!("Watch error: {}", e),
+                    Ok(_) => {}
+                    Err(e) => error!("Watch error: {}", e),
                 }
             },
             notify::Config::default(),
         )?;
 
-        watcher.watch(Path::new(&config_path), RecursiveMode::NonRecursive)?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
 
-        // 保持 watcher 存活
-        std::mem::forget(watcher);
+        // 保持 watcher 存活，重复调用时替换旧的 watcher
+        *CONFIG_WATCHER.lock().unwrap() = Some(watcher);
 
         Ok(())
     }
 }
+
+// 目录事件中的路径是否为配置文件，file 为配置文件的绝对路径
+fn is_config_path(file: &Path, event_path: &Path) -> bool {
+    let dir = event_path.parent().and_then(|dir| dir.canonicalize().ok());
+    match (dir, event_path.file_name()) {
+        (Some(dir), Some(name)) => dir.join(name) == file,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_config_is_valid() {
+        assert!(AppConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_bad_values() {
+        let mut cfg = AppConfig::default();
+        cfg.server.signaling_server = "http://127.0.0.1:9527".to_string();
+        assert!(cfg.validate().is_err());
+
+        let mut cfg = AppConfig::default();
+        cfg.log.level = "loud".to_string();
+        assert!(cfg.validate().is_err());
+
+        let mut cfg = AppConfig::default();
+        cfg.llm.provider = "openai".to_string();
+        assert!(cfg.validate().is_err());
+
+        let mut cfg = AppConfig::default();
+        cfg.limits.max_bots = 0;
+        assert!(cfg.validate().is_err());
+
+        let mut cfg = AppConfig::default();
+        cfg.rtc.audio_sample_rate = 44100;
+        assert!(cfg.validate().is_err());
+        cfg.rtc.audio_sample_rate = 16000;
+        assert!(cfg.validate().is_ok());
+
+        let mut cfg = AppConfig::default();
+        cfg.rtc.opus.application = "music".to_string();
+        assert!(cfg.validate().is_err());
+
+        let mut cfg = AppConfig::default();
+        cfg.rtc.opus.bitrate = 100;
+        assert!(cfg.validate().is_err());
+        cfg.rtc.opus.bitrate = 32000;
+        cfg.rtc.opus.complexity = 11;
+        assert!(cfg.validate().is_err());
+
+        let mut cfg = AppConfig::default();
+        cfg.rtc.codecs.audio = vec![];
+        assert!(cfg.validate().is_err());
+        cfg.rtc.codecs.audio = vec!["pcmu".to_string()];
+        assert!(cfg.validate().is_ok());
+        cfg.rtc.codecs.audio = vec!["pcma".to_string(), "opus".to_string()];
+        assert!(cfg.validate().is_ok());
+        cfg.rtc.codecs.audio.push("opus".to_string());
+        assert!(cfg.validate().is_err());
+        cfg.rtc.codecs.audio = vec!["opus".to_string(), "g722".to_string()];
+        assert!(cfg.validate().is_err());
+
+        let mut cfg = AppConfig::default();
+        cfg.rtc.codecs.opus_ptime = 30;
+        assert!(cfg.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_rtc_network() {
+        let mut cfg = AppConfig::default();
+        cfg.rtc.ice_servers.push(IceServerConfig {
+            urls: vec!["stun:stun.l.google.com:19302".to_string()],
+            username: String::new(),
+            credential: String::new(),
+        });
+        cfg.rtc.nat_1to1_ips = vec!["203.0.113.10".to_string()];
+        cfg.rtc.udp_port_min = 50000;
+        cfg.rtc.udp_port_max = 50100;
+        cfg.rtc.network_types = vec!["udp4".to_string()];
+        assert!(cfg.validate().is_ok());
+
+        // srflx 与 STUN 冲突
+        cfg.rtc.nat_1to1_candidate_type = "srflx".to_string();
+        assert!(cfg.validate().is_err());
+        cfg.rtc.nat_1to1_candidate_type = "host".to_string();
+
+        cfg.rtc.udp_port_max = 40000;
+        assert!(cfg.validate().is_err());
+        cfg.rtc.udp_port_max = 50100;
+
+        cfg.rtc.udp_mux_port = 3478;
+        assert!(cfg.validate().is_err());
+        cfg.rtc.udp_mux_port = 0;
+
+        cfg.rtc.network_types = vec!["udp4".to_string(), "tcp4".to_string()];
+        assert!(cfg.validate().is_err());
+        cfg.rtc.network_types = vec!["udp4".to_string()];
+
+        // ICE-TCP 转发到共享 UDP mux
+        cfg.rtc.ice_tcp_port = 3478;
+        assert!(cfg.validate().is_err());
+        cfg.rtc.udp_port_min = 0;
+        cfg.rtc.udp_port_max = 0;
+        cfg.rtc.udp_mux_port = 3478;
+        assert!(cfg.validate().is_ok());
+        cfg.rtc.network_types = vec!["udp6".to_string()];
+        assert!(cfg.validate().is_err());
+        cfg.rtc.network_types = vec!["udp4".to_string()];
+        cfg.rtc.ice_tcp_port = 0;
+        cfg.rtc.udp_mux_port = 0;
+        cfg.rtc.udp_port_min = 50000;
+        cfg.rtc.udp_port_max = 50100;
+
+        cfg.rtc.jitter_target_ms = 300;
+        assert!(cfg.validate().is_err());
+        cfg.rtc.jitter_target_ms = 40;
+
+        cfg.rtc.ice_servers[0].urls = vec!["turn:turn.example.com:3478".to_string()];
+        assert!(cfg.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_load_merges_file_over_defaults() {
+        let path = std::env::temp_dir().join(format!("vox_config_{}.toml", xid::new()));
+        std::fs::write(
+            &path,
+            "[server]\nsignaling_server = \"ws://10.0.0.1:9527/ws/server\"\n\n[limits]\nmax_bots = 8\n",
+        )
+        .unwrap();
+
+        let cfg = AppConfig::load(path.to_str().unwrap()).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(cfg.server.signaling_server, "ws://10.0.0.1:9527/ws/server");
+        assert_eq!(cfg.limits.max_bots, 8);
+        assert_eq!(cfg.limits.bot_channel_size, 100);
+        assert_eq!(cfg.log.level, "info");
+    }
+
+    #[test]
+    fn test_is_config_path_filters_by_file_name() {
+        let dir = std::env::temp_dir().join(format!("vox_config_dir_{}", xid::new()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("config.toml");
+        std::fs::write(&file, "").unwrap();
+        let file = file.canonicalize().unwrap();
+
+        // 临时文件 rename 覆盖后，目录事件中的新文件仍对应到配置文件
+        assert!(is_config_path(&file, &dir.join("config.toml")));
+        assert!(!is_config_path(&file, &dir.join("config.toml.swp")));
+        assert!(!is_config_path(&file, &dir.join("other.toml")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
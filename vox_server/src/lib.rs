@@ -4,6 +4,7 @@ pub mod config;
 pub mod msg_center;
 pub mod pipeline;
 pub mod prelude;
+pub mod prompt;
 pub mod server;
 pub mod services;
 pub mod utils;
@@ -1,20 +1,24 @@
 use super::*;
 use crate::{
-    bot::bot::Bot, config::CONFIG, msg_center::signaling_msgs::SignalingMessage,
-    server::rtc::traits::WebRTCHandler,
+    bot::bot::{Bot, BotEndReason},
+    config::CONFIG,
+    msg_center::signaling_msgs::{SignalingMessage, ERR_BOT_CREATE_FAILED, ERR_SERVER_FULL},
+    server::{
+        rtc::{factory::RtcFactory, quality::QualityStats},
+        signal_cli::SERVER_ID,
+    },
 };
 use anyhow::Result;
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::{mpsc, Mutex, RwLock};
-
-const DEFAULT_CHANNEL_SIZE: usize = 100;
+use tokio::task::JoinHandle;
 
 // 消息路由表,管理所有的发送端
 #[derive(Default)]
 struct MessageRouter {
-    // client_id/bot_id -> sender
+    // bot 键（见 bot_key）-> sender
     bots_senders: HashMap<String, mpsc::Sender<SignalingMessage>>,
     //bots: HashMap<String, Bot>,
 }
@@ -53,6 +57,15 @@ impl MessageRouter {
         // self.bots.remove(id);
     }
 
+    // 仅当路由仍指向该 bot 时移除，避免误删同一客户端重连后的新路由
+    fn remove_route_of(&mut self, id: &str, sender: &mpsc::Sender<SignalingMessage>) {
+        if let Some(current) = self.bots_senders.get(id) {
+            if current.same_channel(sender) {
+                self.bots_senders.remove(id);
+            }
+        }
+    }
+
     fn get_sender(&self, id: &str) -> Option<mpsc::Sender<SignalingMessage>> {
         self.bots_senders.get(id).cloned()
     }
@@ -62,47 +75,130 @@ impl MessageRouter {
     // }
 }
 
+// 拒绝创建 bot 的原因
+#[derive(Debug)]
+pub enum AdmissionError {
+    Full { active: usize, max: usize }, // 已达并发上限
+    Failed(anyhow::Error),
+}
+
+impl std::fmt::Display for AdmissionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AdmissionError::Full { active, max } => {
+                write!(f, "bot limit reached: {}/{}", active, max)
+            }
+            AdmissionError::Failed(e) => write!(f, "create bot failed: {}", e),
+        }
+    }
+}
+
+impl From<anyhow::Error> for AdmissionError {
+    fn from(e: anyhow::Error) -> Self {
+        AdmissionError::Failed(e)
+    }
+}
+
+// 运行中的 bot：消息入口与质量统计句柄
+struct BotEntry {
+    sender: mpsc::Sender<SignalingMessage>,
+    quality: Arc<std::sync::Mutex<QualityStats>>,
+}
+
 pub struct BotManager {
-    bots: HashMap<String, mpsc::Sender<SignalingMessage>>,
+    bots: HashMap<String, BotEntry>,
+    rtc_factory: Arc<RtcFactory>,
 }
 
 impl BotManager {
-    pub fn new() -> Self {
+    pub fn new(rtc_factory: Arc<RtcFactory>) -> Self {
         Self {
             bots: HashMap::new(),
+            rtc_factory,
         }
     }
 
     pub async fn create_bot(
         &mut self,
+        key: String,
         client_id: String,
+        corr_id: String,
         ws_sender: mpsc::Sender<SignalingMessage>,
-    ) -> Result<mpsc::Sender<SignalingMessage>> {
-        let (message_tx, message_rx) = mpsc::channel(DEFAULT_CHANNEL_SIZE);
+    ) -> std::result::Result<
+        (mpsc::Sender<SignalingMessage>, JoinHandle<BotEndReason>),
+        AdmissionError,
+    > {
+        // 使用创建时的配置快照，热更新不影响已存在的 bot
+        let cfg = CONFIG.read().await.clone();
+        if self.bots.len() >= cfg.limits.max_bots {
+            return Err(AdmissionError::Full {
+                active: self.bots.len(),
+                max: cfg.limits.max_bots,
+            });
+        }
+        let (message_tx, message_rx) = mpsc::channel(cfg.limits.bot_channel_size);
 
         let mut bot = Bot::new(
-            CONFIG.read().await.clone(),
-            client_id.clone(),
+            self.rtc_factory.clone(),
+            cfg,
+            client_id,
+            corr_id,
             ws_sender,
             message_rx,
         )
         .await?;
 
         bot.setup_audio_processor().await;
+        let quality = bot.quality_handle();
 
-        tokio::spawn(async move {
-            debug!("start bot handle message with id: {}", &bot.bot_id);
-            bot.handle_message().await;
+        let handle = tokio::spawn(async move {
+            debug!(log: bot.log, "start bot handle message with id: {}", &bot.bot_id);
+            bot.handle_message().await
         });
 
-        self.bots.insert(client_id, message_tx.clone());
+        self.bots.insert(
+            key,
+            BotEntry {
+                sender: message_tx.clone(),
+                quality,
+            },
+        );
 
-        Ok(message_tx)
+        Ok((message_tx, handle))
     }
 
     pub fn remove_bot(&mut self, id: &str) {
         self.bots.remove(id);
     }
+
+    pub fn bot_count(&self) -> usize {
+        self.bots.len()
+    }
+
+    pub fn quality(&self, id: &str) -> Option<QualityStats> {
+        let entry = self.bots.get(id)?;
+        let quality = *entry.quality.lock().unwrap();
+        Some(quality)
+    }
+
+    pub fn rtc_factory(&self) -> &Arc<RtcFactory> {
+        &self.rtc_factory
+    }
+
+    // 同 MessageRouter::remove_route_of
+    pub fn remove_bot_of(&mut self, id: &str, sender: &mpsc::Sender<SignalingMessage>) {
+        if let Some(current) = self.bots.get(id) {
+            if current.sender.same_channel(sender) {
+                self.bots.remove(id);
+            }
+        }
+    }
+}
+
+// 同一客户端可以有多个会话（多设备），每个会话一个 bot：
+// 以信令服务器为会话分配的关联ID作为键，旧版信令服务器不带关联ID时退回 client_id
+fn bot_key(corr_id: Option<&str>, client_id: &str) -> String {
+    corr_id.unwrap_or(client_id).to_string()
 }
 
 #[derive(Clone)]
@@ -113,21 +209,30 @@ pub struct MessageBus {
 }
 
 impl MessageBus {
-    pub fn new(websocket_sender: mpsc::Sender<SignalingMessage>) -> Self {
+    pub fn new(
+        websocket_sender: mpsc::Sender<SignalingMessage>,
+        rtc_factory: Arc<RtcFactory>,
+    ) -> Self {
         Self {
             router: Arc::new(RwLock::new(MessageRouter::default())),
-            bot_manager: Arc::new(RwLock::new(BotManager::new())),
+            bot_manager: Arc::new(RwLock::new(BotManager::new(rtc_factory))),
             back2ws_sender: websocket_sender,
         }
     }
 
     // 注册一个新的消息通道
-    pub async fn register(&self, client_id: String) {
-        info!("Attempting to register client with ID: {}", client_id);
-        if self.router.read().await.get_sender(&client_id).is_some() {
+    pub async fn register(&self, client_id: String, corr_id: Option<String>) {
+        let key = bot_key(corr_id.as_deref(), &client_id);
+        // 旧版信令服务器不携带关联ID时本地生成一个
+        let corr_id = corr_id.unwrap_or_else(|| xid::new().to_string());
+        info!(
+            "[{}] Attempting to register client with ID: {}",
+            corr_id, client_id
+        );
+        if self.router.read().await.get_sender(&key).is_some() {
             warn!(
-                "Client {} already registered, skipping registration",
-                client_id
+                "[{}] Client {} already registered, skipping registration",
+                corr_id, client_id
             );
             return;
         }
@@ -135,19 +240,36 @@ impl MessageBus {
         let ws_sender = self.back2ws_sender.clone();
         debug!("Creating bot for client: {}", client_id);
 
-        let message_tx = match self
+        // 先释放 bot_manager 写锁，后面上报负载时需要读锁
+        let created = self
             .bot_manager
             .write()
             .await
-            .create_bot(client_id.clone(), ws_sender)
-            .await
-        {
-            Ok(tx) => {
-                info!("Successfully created bot for client: {}", client_id);
-                tx
+            .create_bot(key.clone(), client_id.clone(), corr_id.clone(), ws_sender)
+            .await;
+        let (message_tx, handle) = match created {
+            Ok(created) => {
+                info!("[{}] Successfully created bot for client: {}", corr_id, client_id);
+                created
             }
             Err(e) => {
-                error!("Failed to create bot for client {}: {}", client_id, e);
+                error!("[{}] Failed to create bot for client {}: {}", corr_id, client_id, e);
+                let code = match e {
+                    AdmissionError::Full { .. } => {
+                        // 先更新负载，信令服务器据此改派
+                        self.report_load().await;
+                        ERR_SERVER_FULL
+                    }
+                    AdmissionError::Failed(_) => ERR_BOT_CREATE_FAILED,
+                };
+                let refuse = SignalingMessage::Error {
+                    code,
+                    message: e.to_string(),
+                    corr_id: Some(corr_id),
+                };
+                if let Err(e) = self.back2ws_sender.send(refuse).await {
+                    error!("Failed to send admission error: {}", e);
+                }
                 return;
             }
         };
@@ -156,9 +278,56 @@ impl MessageBus {
         self.router
             .write()
             .await
-            .add_route(&client_id, message_tx)
+            .add_route(&key, message_tx.clone())
             .await;
-        info!("Successfully registered client: {}", client_id);
+        info!("[{}] Successfully registered client: {}", corr_id, client_id);
+        self.report_load().await;
+
+        // bot 结束后清理路由与 bot 记录
+        let bus = self.clone();
+        tokio::spawn(async move {
+            let reason = match handle.await {
+                Ok(reason) => reason,
+                Err(e) => BotEndReason::Error(format!("bot task aborted: {}", e)),
+            };
+            bus.router
+                .write()
+                .await
+                .remove_route_of(&key, &message_tx);
+            bus.bot_manager
+                .write()
+                .await
+                .remove_bot_of(&key, &message_tx);
+            info!(
+                "[{}] bot for client {} removed, reason: {}",
+                corr_id, client_id, reason
+            );
+            bus.report_load().await;
+        });
+    }
+
+    // 向信令服务器上报当前 bot 数量与上限
+    pub async fn report_load(&self) {
+        let (active_bots, answer_stats) = {
+            let manager = self.bot_manager.read().await;
+            (manager.bot_count() as u32, manager.rtc_factory().answer_stats())
+        };
+        let max_bots = CONFIG.read().await.limits.max_bots as u32;
+        let msg = SignalingMessage::ServerLoad {
+            server_id: SERVER_ID.to_string(),
+            active_bots,
+            max_bots,
+            avg_answer_ms: answer_stats.avg().as_millis() as u32,
+            max_answer_ms: answer_stats.max().as_millis() as u32,
+        };
+        if let Err(e) = self.back2ws_sender.send(msg).await {
+            error!("Failed to report server load: {}", e);
+        }
+    }
+
+    // 会话进行中的媒体质量快照，按信令服务器分配的关联ID查询
+    pub async fn quality(&self, corr_id: &str) -> Option<QualityStats> {
+        self.bot_manager.read().await.quality(corr_id)
     }
 
     // 注销消息通道
@@ -168,16 +337,20 @@ impl MessageBus {
 
     // 发送消息到指定目标
     pub async fn send_from(&self, from: &str, message: SignalingMessage) -> Result<()> {
-        debug!("Attempting to send message from {}: {:?}", from, message);
+        let corr_id = message.corr_id().unwrap_or("-").to_string();
+        debug!(
+            "[{}] Attempting to send message from {}: {:?}",
+            corr_id, from, message
+        );
         if let Some(sender) = self.router.read().await.get_sender(from) {
-            info!("Found sender for {}, sending message", from);
+            info!("[{}] Found sender for {}, sending message", corr_id, from);
             sender.send(message).await.map_err(|e| {
-                error!("Failed to send message from {}: {}", from, e);
+                error!("[{}] Failed to send message from {}: {}", corr_id, from, e);
                 anyhow::anyhow!("Failed to send message: {}", e)
             })?;
-            debug!("Successfully sent message from {}", from);
+            debug!("[{}] Successfully sent message from {}", corr_id, from);
         } else {
-            warn!("No route found for sender: {}", from);
+            warn!("[{}] No route found for sender: {}", corr_id, from);
         }
         Ok(())
     }
@@ -188,24 +361,49 @@ impl MessageBus {
     pub async fn run(
         mut msg_recv: mpsc::Receiver<SignalingMessage>,
         ws_tx: mpsc::Sender<SignalingMessage>,
+        rtc_factory: Arc<RtcFactory>,
     ) {
         info!("Starting MessageBus...");
-        let mut bus = Self::new(ws_tx);
+        let bus = Self::new(ws_tx, rtc_factory);
 
         while let Some(message) = msg_recv.recv().await {
             debug!("MessageBus received message: {:?}", message);
             match message {
-                SignalingMessage::ClientConnect { client_id } => {
+                SignalingMessage::ClientConnect { client_id, corr_id } => {
                     info!("Received client connect message for client: {}", client_id);
-                    bus.register(client_id.clone()).await;
+                    bus.register(client_id.clone(), corr_id).await;
+                }
+                SignalingMessage::ServerRegistered { server_id } => {
+                    // 注册或重连成功后上报负载
+                    info!("Registered to signaling server as {}", server_id);
+                    bus.report_load().await;
+                }
+                SignalingMessage::ClientDisconnect { ref client_id, .. } => {
+                    info!(
+                        "[{}] Received client disconnect for client: {}",
+                        message.corr_id().unwrap_or("-"),
+                        client_id
+                    );
+                    // 通知 bot 结束会话，并立即注销路由，客户端重连时可以创建新的 bot
+                    let key = bot_key(message.corr_id(), client_id);
+                    if let Err(e) = bus.send_from(&key, message.clone()).await {
+                        error!("Failed to notify bot of disconnect: {}, error: {}", client_id, e);
+                    }
+                    bus.unregister(&key).await;
                 }
                 SignalingMessage::Offer {
                     ref from,
                     ref to,
                     ref sdp,
+                    ..
                 } => {
-                    info!("Received offer message from: {} to: {}", from, to);
-                    match bus.send_from(&from, message.clone()).await {
+                    info!(
+                        "[{}] Received offer message from: {} to: {}",
+                        message.corr_id().unwrap_or("-"),
+                        from,
+                        to
+                    );
+                    match bus.send_from(&bot_key(message.corr_id(), from), message.clone()).await {
                         Ok(_) => info!("Successfully sent offer message to bot: {}", from),
                         Err(e) => error!(
                             "Failed to send offer message to bot: {}, error: {}",
@@ -217,9 +415,15 @@ impl MessageBus {
                     ref from,
                     ref to,
                     ref candidate,
+                    ..
                 } => {
-                    info!("Received ICE candidate from: {} to: {}", from, to);
-                    match bus.send_from(&from, message.clone()).await {
+                    info!(
+                        "[{}] Received ICE candidate from: {} to: {}",
+                        message.corr_id().unwrap_or("-"),
+                        from,
+                        to
+                    );
+                    match bus.send_from(&bot_key(message.corr_id(), from), message.clone()).await {
                         Ok(_) => info!("Successfully sent ICE candidate to bot: {}", from),
                         Err(e) => error!(
                             "Failed to send ICE candidate to bot: {}, error: {}",
@@ -231,9 +435,15 @@ impl MessageBus {
                     ref from,
                     ref to,
                     ref sdp,
+                    ..
                 } => {
-                    info!("Received answer message from: {} to: {}", from, to);
-                    match bus.send_from(&from, message.clone()).await {
+                    info!(
+                        "[{}] Received answer message from: {} to: {}",
+                        message.corr_id().unwrap_or("-"),
+                        from,
+                        to
+                    );
+                    match bus.send_from(&bot_key(message.corr_id(), from), message.clone()).await {
                         Ok(_) => info!("Successfully sent answer message to bot: {}", from),
                         Err(e) => error!(
                             "Failed to send answer message to bot: {}, error: {}",
@@ -241,6 +451,21 @@ impl MessageBus {
                         ),
                     }
                 }
+                SignalingMessage::BotCommand {
+                    ref from,
+                    ref command,
+                    ..
+                } => {
+                    info!(
+                        "[{}] Received bot command {:?} from: {}",
+                        message.corr_id().unwrap_or("-"),
+                        command,
+                        from
+                    );
+                    if let Err(e) = bus.send_from(&bot_key(message.corr_id(), from), message.clone()).await {
+                        error!("Failed to send bot command to bot: {}, error: {}", from, e);
+                    }
+                }
                 _ => {
                     warn!("Received unknown message type: {:?}", message);
                 }
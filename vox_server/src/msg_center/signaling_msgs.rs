@@ -1,6 +1,16 @@
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
+// SignalingMessage::Error 错误码，与信令服务器保持一致
+pub const ERR_SERVER_FULL: i32 = 5003; // 已达 bot 并发上限
+pub const ERR_BOT_CREATE_FAILED: i32 = 5004; // 创建 bot 失败
+pub const ERR_INVALID_SDP: i32 = 4011; // 客户端的 SDP 无法解析或被拒绝
+pub const ERR_INVALID_CANDIDATE: i32 = 4012; // 客户端的 ICE 候选无法解析或被拒绝
+pub const ERR_UNEXPECTED_STATE: i32 = 4013; // 信令消息与当前协商状态不符，已忽略
+pub const ERR_UNKNOWN_PROMPT: i32 = 4014; // play_prompt 命令的提示音名称未在配置中登记
+pub const ERR_NEGOTIATION_FAILED: i32 = 5005; // 生成应答等协商步骤失败
+pub const ERR_RTC_INTERNAL: i32 = 5006; // bot 内部错误
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "payload", rename_all = "snake_case")]
 pub enum SignalingMessage {
@@ -8,20 +18,137 @@ pub enum SignalingMessage {
     ServerRegister { server_id: String },
     ServerRegistered { server_id: String },
     ServerDisconnect { server_id: String },
+    // 上报当前 bot 数量与上限
+    // avg/max_answer_ms 为收到 Offer 到发出 Answer 的耗时统计
+    ServerLoad {
+        server_id: String,
+        active_bots: u32,
+        max_bots: u32,
+        #[serde(default)]
+        avg_answer_ms: u32,
+        #[serde(default)]
+        max_answer_ms: u32,
+    },
     
     // 客户端管理
-    ClientConnect { client_id: String },
-    ClientConnected { client_id: String, server_id: String },
-    ClientDisconnect { client_id: String },
-    
+    // corr_id 为客户端分配到服务器时生成的关联ID，随所有转发的消息携带，用于串联两端日志
+    ClientConnect {
+        client_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+    ClientConnected {
+        client_id: String,
+        server_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+    ClientDisconnect {
+        client_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+
     // WebRTC 信令
-    Offer { from: String, to: String, sdp: String },
-    Answer { from: String, to: String, sdp: String },
-    IceCandidate { from: String, to: String, candidate: String },
-    
+    Offer {
+        from: String,
+        to: String,
+        sdp: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+    Answer {
+        from: String,
+        to: String,
+        sdp: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+    IceCandidate {
+        from: String,
+        to: String,
+        candidate: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+
+    // 客户端对 bot 的控制命令，按 corr_id 路由到对应会话的 bot
+    BotCommand {
+        from: String,
+        to: String,
+        command: BotCommand,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+
+    // bot 会话结束，reason 见 vox_server BotEndReason
+    BotEnded {
+        client_id: String,
+        reason: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
+
     // 错误处理
-    Error { code: i32, message: String }
+    Error {
+        code: i32,
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        corr_id: Option<String>,
+    },
 }
+
+// BotCommand 的命令内容
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BotCommand {
+    // 停止向客户端发送音频：移除本地轨道并重协商
+    RemoveAudio,
+    // 恢复发送音频：重新添加本地轨道并重协商
+    RestoreAudio,
+    // 播放配置中 prompt.named 的提示音，interrupt 为 true 时打断当前提示音
+    PlayPrompt {
+        name: String,
+        #[serde(default)]
+        interrupt: bool,
+    },
+    StopPrompt,
+}
+
+impl SignalingMessage {
+    pub fn corr_id(&self) -> Option<&str> {
+        match self {
+            SignalingMessage::ClientConnect { corr_id, .. }
+            | SignalingMessage::ClientConnected { corr_id, .. }
+            | SignalingMessage::ClientDisconnect { corr_id, .. }
+            | SignalingMessage::Offer { corr_id, .. }
+            | SignalingMessage::Answer { corr_id, .. }
+            | SignalingMessage::IceCandidate { corr_id, .. }
+            | SignalingMessage::BotCommand { corr_id, .. }
+            | SignalingMessage::BotEnded { corr_id, .. }
+            | SignalingMessage::Error { corr_id, .. } => corr_id.as_deref(),
+            _ => None,
+        }
+    }
+
+    // 为可携带关联ID的消息设置 corr_id，其他消息原样返回
+    pub fn with_corr_id(mut self, id: Option<String>) -> Self {
+        match &mut self {
+            SignalingMessage::ClientConnect { corr_id, .. }
+            | SignalingMessage::ClientConnected { corr_id, .. }
+            | SignalingMessage::ClientDisconnect { corr_id, .. }
+            | SignalingMessage::Offer { corr_id, .. }
+            | SignalingMessage::Answer { corr_id, .. }
+            | SignalingMessage::IceCandidate { corr_id, .. }
+            | SignalingMessage::BotCommand { corr_id, .. }
+            | SignalingMessage::BotEnded { corr_id, .. }
+            | SignalingMessage::Error { corr_id, .. } => *corr_id = id,
+            _ => {}
+        }
+        self
+    }
+}
+
 // 用于 WebSocket 传输的消息包装
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WsMessage {
@@ -38,6 +165,7 @@ mod tests {
     fn test_signaling_message_json() {
         let message = SignalingMessage::ClientConnect {
             client_id: "client_123".to_string(),
+            corr_id: Some("corr_123".to_string()),
         };
 
         let json = serde_json::to_string(&message).unwrap();
@@ -47,6 +175,7 @@ mod tests {
             from: "client_123".to_string(),
             to: "client_456".to_string(),
             sdp: "sdp_data".to_string(),
+            corr_id: None,
         };
 
         let json = serde_json::to_string(&message).unwrap();
@@ -54,4 +183,30 @@ mod tests {
 
         // Add more tests for other variants as needed
     }
+
+    #[test]
+    fn test_bot_command_json() {
+        let json = r#"{"type":"bot_command","payload":{"from":"c1","to":"bot","command":{"action":"remove_audio"},"corr_id":"corr_1"}}"#;
+        let message: SignalingMessage = serde_json::from_str(json).unwrap();
+        assert_eq!(message.corr_id(), Some("corr_1"));
+        match &message {
+            SignalingMessage::BotCommand { command, .. } => {
+                assert_eq!(*command, BotCommand::RemoveAudio)
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        let back: SignalingMessage =
+            serde_json::from_str(&serde_json::to_string(&message).unwrap()).unwrap();
+        assert_eq!(back.corr_id(), Some("corr_1"));
+
+        let command: BotCommand =
+            serde_json::from_str(r#"{"action":"play_prompt","name":"hold"}"#).unwrap();
+        assert_eq!(
+            command,
+            BotCommand::PlayPrompt {
+                name: "hold".to_string(),
+                interrupt: false
+            }
+        );
+    }
 }
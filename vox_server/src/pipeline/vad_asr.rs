@@ -1,11 +1,15 @@
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use anyhow::{anyhow, Result};
+
 use super::*;
 use crate::audio_processor::AudioSink;
+use crate::config::VadConfig;
 use crate::server::data::AudioData;
 use crate::{debug, info, warn};
 
+use slog::Logger;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::Mutex;
 use vad_rs::ort_vad::speech_state::SpeechState;
@@ -54,32 +58,45 @@ pub struct VadProcessor {
     vad_iter: VadIter,
     // 用于缓存音频数据的buffer
     audio_buffer: Vec<i16>,
-    // 32ms 数据需要的采样点数 (16000 * 0.032 = 512)
+    // 每帧采样点数 = sample_rate * frame_ms / 1000
     samples_per_frame: usize,
     cli: Arc<Mutex<NopClient>>,
     audio_cache: AudioCache,
     last_state: SpeechState,
     audio_sink: Option<Box<dyn AudioSink>>,
+    log: Logger,
 }
 
 impl VadProcessor {
-    pub fn new(audio_frame_rx: UnboundedReceiver<AudioData>, cli: Arc<Mutex<NopClient>>) -> Self {
-        let silero = Silero::new(SampleRate::SixteenkHz, "").unwrap();
+    pub fn new(
+        audio_frame_rx: UnboundedReceiver<AudioData>,
+        cli: Arc<Mutex<NopClient>>,
+        cfg: &VadConfig,
+        log: Logger,
+    ) -> Result<Self> {
+        let sample_rate = if cfg.sample_rate == 8000 {
+            SampleRate::EightkHz
+        } else {
+            SampleRate::SixteenkHz
+        };
+        let silero = Silero::new(sample_rate, &cfg.model_path)
+            .map_err(|e| anyhow!("load vad model {} failed: {}", cfg.model_path, e))?;
         let params = VadParams::default();
         let vad_iter = VadIter::new(silero, params);
         // let file = FileSink::new(PathBuf::from("./user_audios"));
         // let file_sink = Some(Box::new(file));
 
-        Self {
+        Ok(Self {
             audio_frame_rx,
             vad_iter,
             audio_buffer: Vec::new(),
-            samples_per_frame: 512, // 16000Hz * 32ms = 512 samples
+            samples_per_frame: (cfg.sample_rate * cfg.frame_ms / 1000) as usize,
             cli,
-            audio_cache: AudioCache::new(100),
+            audio_cache: AudioCache::new(cfg.max_cache_frames),
             last_state: SpeechState::Silent,
             audio_sink: None,
-        }
+            log,
+        })
     }
 
     async fn handle_speech_state(
@@ -90,12 +107,12 @@ impl VadProcessor {
         match speech_state {
             SpeechState::StartSpeaking => {
                 if self.last_state == SpeechState::Silent {
-                    info!("检测到开始说话");
+                    info!(log: self.log, "检测到开始说话");
                     if let Some(frame) = current_frame {
                         self.audio_cache.push(frame);
                         if let Some(sink) = &mut self.audio_sink {
                             if let Err(e) = sink.write(frame) {
-                                warn!("写入音频文件失败: {}", e);
+                                warn!(log: self.log, "写入音频文件失败: {}", e);
                             }
                         }
                     }
@@ -104,11 +121,11 @@ impl VadProcessor {
 
             SpeechState::Speaking => {
                 if self.last_state == SpeechState::StartSpeaking {
-                    info!("确认持续说话，发送缓存的音频");
+                    info!(log: self.log, "确认持续说话，发送缓存的音频");
                     let mut cli = self.cli.lock().await;
                     for frame in self.audio_cache.buffer.iter() {
                         if let Err(e) = cli.send_audio_frame(frame.as_slice()).await {
-                            warn!("发送缓存音频帧失败: {}", e);
+                            warn!(log: self.log, "发送缓存音频帧失败: {}", e);
                         }
                     }
                 }
@@ -116,36 +133,36 @@ impl VadProcessor {
                 if let Some(frame) = current_frame {
                     let mut cli = self.cli.lock().await;
                     if let Err(e) = cli.send_audio_frame(frame).await {
-                        warn!("发送当前音频帧失败: {}", e);
+                        warn!(log: self.log, "发送当前音频帧失败: {}", e);
                     }
                     if let Some(sink) = &mut self.audio_sink {
                         if let Err(e) = sink.write(frame) {
-                            warn!("写入音频文件失败: {}", e);
+                            warn!(log: self.log, "写入音频文件失败: {}", e);
                         }
                     }
                 }
             }
 
             SpeechState::StopSpeaking => {
-                info!("检测到说话结束");
+                info!(log: self.log, "检测到说话结束");
                 if let Some(frame) = current_frame {
                     let mut cli = self.cli.lock().await;
                     if let Err(e) = cli.send_audio_frame(frame).await {
-                        warn!("发送最后音频帧失败: {}", e);
+                        warn!(log: self.log, "发送最后音频帧失败: {}", e);
                     }
                     if let Err(e) = cli.commit_audio_frame().await {
-                        warn!("提交音频帧失败: {}", e);
+                        warn!(log: self.log, "提交音频帧失败: {}", e);
                     }
                     if let Some(sink) = &mut self.audio_sink {
                         if let Err(e) = sink.write(frame) {
-                            warn!("写入最后音频帧失败: {}", e);
+                            warn!(log: self.log, "写入最后音频帧失败: {}", e);
                         }
                         if let Err(e) = sink.finish() {
-                            warn!("完成音频文件写入失败: {}", e);
+                            warn!(log: self.log, "完成音频文件写入失败: {}", e);
                         }
                     }
 
-                    info!("发送音频帧结束");
+                    info!(log: self.log, "发送音频帧结束");
                 }
 
                 self.audio_cache.clear();
@@ -170,7 +187,7 @@ impl VadProcessor {
                     self.handle_speech_state(speech_state, Some(frame)).await;
                 }
                 Err(e) => {
-                    warn!("VAD process error: {}", e);
+                    warn!(log: self.log, "VAD process error: {}", e);
                 }
             }
 
@@ -181,7 +198,7 @@ impl VadProcessor {
 
     pub async fn handle(&mut self) {
         // 移除已处理的数据
-        info!("VAD processor handle start");
+        info!(log: self.log, "VAD processor handle start");
         let mut audio_buffer = Vec::new();
 
         while let Some(frame) = self.audio_frame_rx.recv().await {
@@ -192,7 +209,7 @@ impl VadProcessor {
 
             self.process_buffer(&mut audio_buffer).await;
         }
-        info!("VAD processor handle end");
+        info!(log: self.log, "VAD processor handle end");
     }
 }
 // 处理缓冲区中的数据
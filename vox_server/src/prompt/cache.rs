@@ -0,0 +1,371 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use bytes::Bytes;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use once_cell::sync::Lazy;
+
+use super::decode::{self, PromptAudio};
+use crate::config::{config_reloaded, OpusConfig, PromptConfig, CONFIG};
+use crate::server::rtc::audio_out::FRAME_DURATION;
+use crate::server::rtc::en_decoder::OpusAudioEncoder;
+use crate::utils::audio::Rechunker;
+use crate::{error, info, warn};
+
+// 编码好的提示音，可直接按 20ms 写入本地轨道
+#[derive(Debug)]
+pub struct EncodedPrompt {
+    pub packets: Vec<Bytes>,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl EncodedPrompt {
+    pub fn encode(audio: &PromptAudio, sample_rate: u32, channels: u16) -> Result<Self> {
+        let audio = audio.convert(sample_rate, channels);
+        // 提示音不是实时语音，按音乐模式编码
+        let cfg = OpusConfig {
+            application: "audio".to_string(),
+            ..OpusConfig::default()
+        };
+        let mut encoder = OpusAudioEncoder::with_config(sample_rate, channels, &cfg)?;
+        let mut reframer = Rechunker::for_duration(sample_rate, channels, FRAME_DURATION);
+        reframer.push(&audio.pcm);
+
+        let mut packets = Vec::new();
+        while let Some(frame) = reframer.pop_frame().or_else(|| reframer.flush()) {
+            packets.push(encoder.encode_blocking(&frame)?);
+        }
+        Ok(Self {
+            packets,
+            sample_rate,
+            channels,
+        })
+    }
+
+    pub fn duration(&self) -> Duration {
+        FRAME_DURATION * self.packets.len() as u32
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.packets.iter().map(|p| p.len()).sum()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: PathBuf,
+    sample_rate: u32,
+    channels: u16,
+}
+
+struct CacheEntry {
+    prompt: Arc<EncodedPrompt>,
+    last_used: u64,
+}
+
+// 按路径与轨道格式缓存，超过内存上限时淘汰最久未使用的条目
+pub struct PromptCache {
+    max_bytes: usize,
+    entries: HashMap<CacheKey, CacheEntry>,
+    total_bytes: usize,
+    clock: u64,
+}
+
+impl PromptCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            entries: HashMap::new(),
+            total_bytes: 0,
+            clock: 0,
+        }
+    }
+
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = max_bytes;
+        self.evict();
+    }
+
+    fn key(path: &Path, sample_rate: u32, channels: u16) -> CacheKey {
+        CacheKey {
+            path: path.to_path_buf(),
+            sample_rate,
+            channels,
+        }
+    }
+
+    pub fn get(&mut self, path: &Path, sample_rate: u32, channels: u16) -> Option<Arc<EncodedPrompt>> {
+        self.clock += 1;
+        let entry = self.entries.get_mut(&Self::key(path, sample_rate, channels))?;
+        entry.last_used = self.clock;
+        Some(entry.prompt.clone())
+    }
+
+    pub fn insert(&mut self, path: &Path, prompt: Arc<EncodedPrompt>) {
+        self.clock += 1;
+        let key = Self::key(path, prompt.sample_rate, prompt.channels);
+        self.total_bytes += prompt.size_bytes();
+        let entry = CacheEntry {
+            prompt,
+            last_used: self.clock,
+        };
+        if let Some(old) = self.entries.insert(key, entry) {
+            self.total_bytes -= old.prompt.size_bytes();
+        }
+        self.evict();
+    }
+
+    // 文件对应的所有格式
+    fn formats_of(&self, path: &Path) -> Vec<(u32, u16)> {
+        self.entries
+            .keys()
+            .filter(|key| key.path == path)
+            .map(|key| (key.sample_rate, key.channels))
+            .collect()
+    }
+
+    fn evict(&mut self) {
+        while self.total_bytes > self.max_bytes && !self.entries.is_empty() {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.total_bytes -= entry.prompt.size_bytes();
+                info!("prompt cache evict {}", oldest.path.display());
+            }
+        }
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+// 进程内共享的提示音缓存，容量在启动时按配置设置，配置重新加载后随之更新
+pub static PROMPT_CACHE: Lazy<Mutex<PromptCache>> =
+    Lazy::new(|| Mutex::new(PromptCache::new(PromptConfig::default().cache_max_bytes)));
+
+// 监听提示音所在的目录而不是文件本身：编辑器保存时常写临时文件再改名替换，
+// 直接监听文件会在替换后失效。dirs 记录已监听的目录，避免重复注册
+struct PromptWatcher {
+    watcher: RecommendedWatcher,
+    dirs: HashSet<PathBuf>,
+}
+
+static WATCHER: Lazy<Mutex<Option<PromptWatcher>>> = Lazy::new(|| Mutex::new(None));
+
+// 已加载的文件，规范化路径 -> 缓存中使用的路径，用于按文件名过滤目录事件。
+// 与 WATCHER 分开加锁：监听回调在监听线程中执行，注册目录时会等待该线程
+static WATCHED_FILES: Lazy<Mutex<HashMap<PathBuf, PathBuf>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn encode_file(path: &Path, sample_rate: u32, channels: u16) -> Result<Arc<EncodedPrompt>> {
+    let audio = decode::load_file(path)?;
+    let prompt = Arc::new(EncodedPrompt::encode(&audio, sample_rate, channels)?);
+    PROMPT_CACHE.lock().unwrap().insert(path, prompt.clone());
+    Ok(prompt)
+}
+
+// 解码与编码都是阻塞操作，调用方应放在阻塞线程
+pub fn load_blocking(path: &Path, sample_rate: u32, channels: u16) -> Result<Arc<EncodedPrompt>> {
+    if let Some(prompt) = PROMPT_CACHE.lock().unwrap().get(path, sample_rate, channels) {
+        return Ok(prompt);
+    }
+    let prompt = encode_file(path, sample_rate, channels)?;
+    watch_file(path);
+    Ok(prompt)
+}
+
+pub async fn load(path: PathBuf, sample_rate: u32, channels: u16) -> Result<Arc<EncodedPrompt>> {
+    tokio::task::spawn_blocking(move || load_blocking(&path, sample_rate, channels)).await?
+}
+
+// 启动时预加载配置的提示音
+pub async fn preload(paths: &[String], max_bytes: usize, sample_rate: u32, channels: u16) {
+    PROMPT_CACHE.lock().unwrap().set_max_bytes(max_bytes);
+    for path in paths {
+        match load(PathBuf::from(path), sample_rate, channels).await {
+            Ok(prompt) => info!("prompt {} loaded, {:?}", path, prompt.duration()),
+            Err(e) => error!("Failed to preload prompt {}: {}", path, e),
+        }
+    }
+}
+
+// 配置重新加载后应用新的缓存上限
+pub async fn follow_config() {
+    let mut reloaded = config_reloaded();
+    while reloaded.changed().await.is_ok() {
+        let max_bytes = CONFIG.read().await.prompt.cache_max_bytes;
+        let mut cache = PROMPT_CACHE.lock().unwrap();
+        cache.set_max_bytes(max_bytes);
+        info!(
+            "prompt cache max bytes {}, {} entries {} bytes",
+            max_bytes,
+            cache.len(),
+            cache.total_bytes()
+        );
+    }
+}
+
+// 目录事件中的路径对应的已加载文件，其他文件返回 None
+fn watched_path(files: &HashMap<PathBuf, PathBuf>, event_path: &Path) -> Option<PathBuf> {
+    let dir = event_path.parent()?.canonicalize().ok()?;
+    files.get(&dir.join(event_path.file_name()?)).cloned()
+}
+
+// 文件变化后按已缓存的格式重新编码，失败时保留旧版本（写入过程中可能读到不完整的文件）。
+// 在监听线程中执行，不能再调用 watch
+fn reload_path(path: &Path) {
+    let formats = PROMPT_CACHE.lock().unwrap().formats_of(path);
+    for (sample_rate, channels) in formats {
+        match encode_file(path, sample_rate, channels) {
+            Ok(_) => info!("prompt {} reloaded", path.display()),
+            Err(e) => error!("Failed to reload prompt {}: {}", path.display(), e),
+        }
+    }
+}
+
+fn on_watch_event(res: notify::Result<notify::Event>) {
+    match res {
+        Ok(event) if event.kind.is_modify() || event.kind.is_create() => {
+            for event_path in event.paths {
+                let path = watched_path(&WATCHED_FILES.lock().unwrap(), &event_path);
+                if let Some(path) = path {
+                    reload_path(&path);
+                }
+            }
+        }
+        Ok(_) => {}
+        Err(e) => error!("Prompt watch error: {}", e),
+    }
+}
+
+fn watch_file(path: &Path) {
+    let Ok(file) = path.canonicalize() else {
+        warn!("Failed to resolve prompt {}", path.display());
+        return;
+    };
+    let Some(dir) = file.parent().map(Path::to_path_buf) else {
+        return;
+    };
+    WATCHED_FILES
+        .lock()
+        .unwrap()
+        .insert(file, path.to_path_buf());
+
+    let mut watcher = WATCHER.lock().unwrap();
+    if watcher.is_none() {
+        match RecommendedWatcher::new(on_watch_event, notify::Config::default()) {
+            Ok(created) => {
+                *watcher = Some(PromptWatcher {
+                    watcher: created,
+                    dirs: HashSet::new(),
+                })
+            }
+            Err(e) => {
+                warn!("Failed to create prompt watcher: {}", e);
+                return;
+            }
+        }
+    }
+    if let Some(watcher) = watcher.as_mut() {
+        if watcher.dirs.contains(&dir) {
+            return;
+        }
+        match watcher.watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            Ok(_) => {
+                watcher.dirs.insert(dir);
+            }
+            Err(e) => warn!("Failed to watch prompt dir {}: {}", dir.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt(packets: usize, size: usize) -> Arc<EncodedPrompt> {
+        Arc::new(EncodedPrompt {
+            packets: vec![Bytes::from(vec![0u8; size]); packets],
+            sample_rate: 48000,
+            channels: 1,
+        })
+    }
+
+    #[test]
+    fn test_lru_evicts_oldest_over_cap() {
+        let mut cache = PromptCache::new(300);
+        cache.insert(Path::new("a.wav"), prompt(1, 100));
+        cache.insert(Path::new("b.wav"), prompt(1, 100));
+        cache.insert(Path::new("c.wav"), prompt(1, 100));
+        assert_eq!(cache.total_bytes(), 300);
+
+        // a 最近被使用，应淘汰 b
+        assert!(cache.get(Path::new("a.wav"), 48000, 1).is_some());
+        cache.insert(Path::new("d.wav"), prompt(1, 100));
+        assert_eq!(cache.len(), 3);
+        assert!(cache.get(Path::new("b.wav"), 48000, 1).is_none());
+        assert!(cache.get(Path::new("a.wav"), 48000, 1).is_some());
+
+        // 格式不同视为不同条目
+        assert!(cache.get(Path::new("a.wav"), 24000, 1).is_none());
+
+        cache.insert(Path::new("a.wav"), prompt(2, 100));
+        assert_eq!(cache.total_bytes(), 300);
+        cache.set_max_bytes(100);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn test_watched_path_filters_by_file_name() {
+        let dir = std::env::temp_dir().join(format!("vox_prompt_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("hold.wav");
+        std::fs::write(&file, b"x").unwrap();
+
+        let mut files = HashMap::new();
+        files.insert(
+            file.canonicalize().unwrap(),
+            PathBuf::from("prompts/hold.wav"),
+        );
+        // 目录中的事件按文件名对应到缓存使用的路径，同目录的其他文件忽略
+        assert_eq!(
+            watched_path(&files, &file),
+            Some(PathBuf::from("prompts/hold.wav"))
+        );
+        assert_eq!(watched_path(&files, &dir.join("hold.wav.tmp")), None);
+        assert_eq!(watched_path(&files, &dir.join("other.wav")), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_encode_prompt_into_frames() {
+        let audio = PromptAudio {
+            pcm: vec![0i16; 16000 / 10 * 3 + 100], // 16k 单声道 300ms 多一点
+            sample_rate: 16000,
+            channels: 1,
+        };
+        let prompt = EncodedPrompt::encode(&audio, 48000, 1).unwrap();
+        assert_eq!(prompt.packets.len(), 16);
+        assert_eq!(prompt.duration(), Duration::from_millis(320));
+    }
+}
@@ -0,0 +1,294 @@
+use std::io::Cursor;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use audiopus::coder::Decoder;
+use audiopus::{Channels, SampleRate};
+use byteorder::{LittleEndian, ReadBytesExt};
+use lewton::inside_ogg::OggStreamReader;
+use ogg::PacketReader;
+
+use crate::utils::audio;
+
+// Opus 解码固定输出 48kHz
+const OPUS_RATE: u32 = 48000;
+// 单个 Opus 包最长 120ms
+const OPUS_MAX_FRAME: usize = 5760;
+
+// 解码后的提示音，交织 PCM
+#[derive(Debug, Clone)]
+pub struct PromptAudio {
+    pub pcm: Vec<i16>,
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+impl PromptAudio {
+    pub fn duration(&self) -> Duration {
+        let frames = self.pcm.len() as u64 / self.channels.max(1) as u64;
+        Duration::from_micros(frames * 1_000_000 / self.sample_rate.max(1) as u64)
+    }
+
+    // 转换到轨道的采样率与声道数
+    pub fn convert(&self, sample_rate: u32, channels: u16) -> PromptAudio {
+        PromptAudio {
+            pcm: audio::convert(
+                &self.pcm,
+                self.sample_rate,
+                self.channels,
+                sample_rate,
+                channels,
+            ),
+            sample_rate,
+            channels,
+        }
+    }
+}
+
+// 按文件头识别格式：RIFF/WAVE、Ogg Opus、Ogg Vorbis
+pub fn load_file(path: &Path) -> Result<PromptAudio> {
+    let bytes = std::fs::read(path)?;
+    decode_bytes(&bytes).map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+pub fn decode_bytes(bytes: &[u8]) -> Result<PromptAudio> {
+    if bytes.starts_with(b"RIFF") {
+        decode_wav(bytes)
+    } else if bytes.starts_with(b"OggS") {
+        decode_ogg(bytes)
+    } else {
+        bail!("unsupported audio format")
+    }
+}
+
+fn decode_wav(bytes: &[u8]) -> Result<PromptAudio> {
+    let mut reader = Cursor::new(bytes);
+    reader.set_position(8);
+    let mut wave = [0u8; 4];
+    std::io::Read::read_exact(&mut reader, &mut wave)?;
+    if &wave != b"WAVE" {
+        bail!("missing WAVE header");
+    }
+
+    let mut format = None;
+    while (reader.position() as usize) + 8 <= bytes.len() {
+        let mut id = [0u8; 4];
+        std::io::Read::read_exact(&mut reader, &mut id)?;
+        let size = reader.read_u32::<LittleEndian>()? as usize;
+        let start = reader.position() as usize;
+        let end = (start + size).min(bytes.len());
+        match &id {
+            b"fmt " => {
+                let tag = reader.read_u16::<LittleEndian>()?;
+                let channels = reader.read_u16::<LittleEndian>()?;
+                let sample_rate = reader.read_u32::<LittleEndian>()?;
+                reader.set_position(start as u64 + 14);
+                let bits = reader.read_u16::<LittleEndian>()?;
+                format = Some((tag, channels, sample_rate, bits));
+            }
+            b"data" => {
+                let (tag, channels, sample_rate, bits) =
+                    format.ok_or_else(|| anyhow!("data chunk before fmt chunk"))?;
+                if channels == 0 || sample_rate == 0 {
+                    bail!("invalid wav format");
+                }
+                let data = &bytes[start..end];
+                let pcm = match (tag, bits) {
+                    // PCM 16bit
+                    (1, 16) => data
+                        .chunks_exact(2)
+                        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                        .collect(),
+                    // IEEE float 32bit
+                    (3, 32) => data
+                        .chunks_exact(4)
+                        .map(|b| {
+                            let s = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                            (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+                        })
+                        .collect(),
+                    _ => bail!("unsupported wav encoding {} with {} bits", tag, bits),
+                };
+                return Ok(PromptAudio {
+                    pcm,
+                    sample_rate,
+                    channels,
+                });
+            }
+            _ => {}
+        }
+        // chunk 按偶数字节对齐
+        reader.set_position((start + size + (size & 1)) as u64);
+    }
+    bail!("missing data chunk")
+}
+
+fn decode_ogg(bytes: &[u8]) -> Result<PromptAudio> {
+    let mut packets = PacketReader::new(Cursor::new(bytes));
+    let first = packets
+        .read_packet()?
+        .ok_or_else(|| anyhow!("empty ogg stream"))?;
+    if first.data.starts_with(b"OpusHead") {
+        let head = OpusHead::parse(&first.data)?;
+        decode_opus_packets(head, packets)
+    } else if first.data.get(1..7) == Some(b"vorbis".as_slice()) {
+        decode_vorbis(bytes)
+    } else {
+        bail!("unsupported ogg codec")
+    }
+}
+
+// RFC 7845 OpusHead
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpusHead {
+    pub channels: u8,
+    pub pre_skip: u16,
+    pub input_sample_rate: u32,
+    pub output_gain: i16, // Q7.8 dB
+    pub mapping_family: u8,
+}
+
+impl OpusHead {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 19 || !data.starts_with(b"OpusHead") {
+            bail!("invalid OpusHead");
+        }
+        let mut reader = Cursor::new(&data[8..]);
+        let version = reader.read_u8()?;
+        if version >> 4 != 0 {
+            bail!("unsupported OpusHead version {}", version);
+        }
+        let head = Self {
+            channels: reader.read_u8()?,
+            pre_skip: reader.read_u16::<LittleEndian>()?,
+            input_sample_rate: reader.read_u32::<LittleEndian>()?,
+            output_gain: reader.read_i16::<LittleEndian>()?,
+            mapping_family: reader.read_u8()?,
+        };
+        if head.channels == 0 || head.channels > 2 {
+            bail!("unsupported opus channel count {}", head.channels);
+        }
+        Ok(head)
+    }
+}
+
+fn decode_opus_packets(
+    head: OpusHead,
+    mut packets: PacketReader<Cursor<&[u8]>>,
+) -> Result<PromptAudio> {
+    let channels = head.channels as usize;
+    let opus_channels = if channels == 2 {
+        Channels::Stereo
+    } else {
+        Channels::Mono
+    };
+    let mut decoder = Decoder::new(SampleRate::Hz48000, opus_channels)?;
+    let mut frame = vec![0i16; OPUS_MAX_FRAME * channels];
+    let mut pcm = Vec::new();
+
+    // 第二个包是 OpusTags
+    packets.read_packet()?;
+    while let Some(packet) = packets.read_packet()? {
+        let samples = decoder.decode(Some(&packet.data), &mut frame, false)?;
+        pcm.extend_from_slice(&frame[..samples * channels]);
+    }
+
+    let skip = (head.pre_skip as usize * channels).min(pcm.len());
+    pcm.drain(..skip);
+    if head.output_gain != 0 {
+        let gain = 10f32.powf(head.output_gain as f32 / (20.0 * 256.0));
+        for s in pcm.iter_mut() {
+            *s = (*s as f32 * gain).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        }
+    }
+    Ok(PromptAudio {
+        pcm,
+        sample_rate: OPUS_RATE,
+        channels: head.channels as u16,
+    })
+}
+
+fn decode_vorbis(bytes: &[u8]) -> Result<PromptAudio> {
+    let mut reader = OggStreamReader::new(Cursor::new(bytes))?;
+    let sample_rate = reader.ident_hdr.audio_sample_rate;
+    let channels = reader.ident_hdr.audio_channels as u16;
+    let mut pcm = Vec::new();
+    while let Some(packet) = reader.read_dec_packet_itl()? {
+        pcm.extend_from_slice(&packet);
+    }
+    Ok(PromptAudio {
+        pcm,
+        sample_rate,
+        channels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wav_bytes(pcm: &[i16], sample_rate: u32, channels: u16) -> Vec<u8> {
+        let data_len = (pcm.len() * 2) as u32;
+        let mut out = Vec::new();
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(36 + data_len).to_le_bytes());
+        out.extend_from_slice(b"WAVEfmt ");
+        out.extend_from_slice(&16u32.to_le_bytes());
+        out.extend_from_slice(&1u16.to_le_bytes());
+        out.extend_from_slice(&channels.to_le_bytes());
+        out.extend_from_slice(&sample_rate.to_le_bytes());
+        out.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+        out.extend_from_slice(&(channels * 2).to_le_bytes());
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.extend_from_slice(b"data");
+        out.extend_from_slice(&data_len.to_le_bytes());
+        for s in pcm {
+            out.extend_from_slice(&s.to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn test_decode_wav() {
+        let pcm: Vec<i16> = (0..1600).map(|i| (i % 100) as i16).collect();
+        let audio = decode_bytes(&wav_bytes(&pcm, 16000, 1)).unwrap();
+        assert_eq!(audio.sample_rate, 16000);
+        assert_eq!(audio.channels, 1);
+        assert_eq!(audio.pcm, pcm);
+        assert_eq!(audio.duration(), Duration::from_millis(100));
+
+        assert!(decode_bytes(b"not audio").is_err());
+    }
+
+    #[test]
+    fn test_parse_opus_head() {
+        let mut data = b"OpusHead".to_vec();
+        data.push(1); // version
+        data.push(2); // channels
+        data.extend_from_slice(&312u16.to_le_bytes());
+        data.extend_from_slice(&44100u32.to_le_bytes());
+        data.extend_from_slice(&0i16.to_le_bytes());
+        data.push(0);
+        let head = OpusHead::parse(&data).unwrap();
+        assert_eq!(head.channels, 2);
+        assert_eq!(head.pre_skip, 312);
+        assert_eq!(head.input_sample_rate, 44100);
+
+        assert!(OpusHead::parse(&data[..10]).is_err());
+    }
+
+    #[test]
+    fn test_convert_rate_and_channels() {
+        let audio = PromptAudio {
+            pcm: [100, 300].repeat(1600), // 16k 立体声 100ms
+            sample_rate: 16000,
+            channels: 2,
+        };
+        let converted = audio.convert(48000, 1);
+        assert_eq!(converted.pcm.len(), 4800);
+        // 首尾受滤波器边界影响，中间稳态不变
+        assert!(converted.pcm[1000..3800].iter().all(|s| *s == 200));
+        assert_eq!(converted.duration(), Duration::from_millis(100));
+    }
+}
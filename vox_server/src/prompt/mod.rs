@@ -0,0 +1,322 @@
+pub mod cache;
+pub mod decode;
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use slog::Logger;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::server::rtc::audio_out::FRAME_DURATION;
+use crate::{debug, info, warn};
+use cache::EncodedPrompt;
+use decode::PromptAudio;
+
+// 待播放的提示音：运行时生成的 PCM，或缓存中已编码好的 Opus 包
+#[derive(Clone)]
+pub enum Clip {
+    Pcm(Arc<PromptAudio>),
+    Opus(Arc<EncodedPrompt>),
+}
+
+impl Clip {
+    fn len(&self) -> usize {
+        match self {
+            Clip::Pcm(audio) => audio.pcm.len(),
+            Clip::Opus(prompt) => prompt.packets.len(),
+        }
+    }
+}
+
+// 交给发送任务的一帧提示音，PCM 为轨道格式，不足一帧时由发送任务补静音
+#[derive(Debug)]
+pub enum Frame {
+    Pcm(Vec<i16>),
+    Opus(Bytes),
+}
+
+pub enum PromptCommand {
+    Enqueue(Clip),
+    PlayNow(Clip), // 清空队列并打断当前播放
+    Stop,
+    Pause,
+    Resume,
+}
+
+impl PromptCommand {
+    fn name(&self) -> &'static str {
+        match self {
+            PromptCommand::Enqueue(_) => "enqueue",
+            PromptCommand::PlayNow(_) => "play_now",
+            PromptCommand::Stop => "stop",
+            PromptCommand::Pause => "pause",
+            PromptCommand::Resume => "resume",
+        }
+    }
+}
+
+// 播放状态，不涉及 IO，由播放任务每 20ms 取一帧
+#[derive(Default)]
+pub struct PlayerState {
+    queue: VecDeque<Clip>,
+    current: Option<(Clip, usize)>,
+    paused: bool,
+}
+
+impl PlayerState {
+    pub fn apply(&mut self, cmd: PromptCommand) {
+        match cmd {
+            PromptCommand::Enqueue(clip) => self.queue.push_back(clip),
+            PromptCommand::PlayNow(clip) => {
+                self.queue.clear();
+                self.current = Some((clip, 0));
+                self.paused = false;
+            }
+            PromptCommand::Stop => {
+                self.queue.clear();
+                self.current = None;
+                self.paused = false;
+            }
+            PromptCommand::Pause => self.paused = true,
+            PromptCommand::Resume => self.paused = false,
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        !self.paused && (self.current.is_some() || !self.queue.is_empty())
+    }
+
+    // 取下一帧，PCM 最后一帧可能不足 frame_len，Opus 每包即一帧
+    pub fn next_frame(&mut self, frame_len: usize) -> Option<Frame> {
+        if self.paused {
+            return None;
+        }
+        loop {
+            if self.current.is_none() {
+                self.current = Some((self.queue.pop_front()?, 0));
+            }
+            let (clip, offset) = self.current.as_mut()?;
+            if *offset < clip.len() {
+                let frame = match clip {
+                    Clip::Pcm(audio) => {
+                        let end = (*offset + frame_len).min(audio.pcm.len());
+                        let frame = audio.pcm[*offset..end].to_vec();
+                        *offset = end;
+                        Frame::Pcm(frame)
+                    }
+                    Clip::Opus(prompt) => {
+                        let packet = prompt.packets[*offset].clone();
+                        *offset += 1;
+                        Frame::Opus(packet)
+                    }
+                };
+                return Some(frame);
+            }
+            self.current = None;
+        }
+    }
+}
+
+// bot 的提示音播放器，帧写入发送任务的提示音通道，播放期间 TTS 等 PCM 暂停发送
+#[derive(Clone)]
+pub struct PromptPlayer {
+    cmd_tx: mpsc::UnboundedSender<PromptCommand>,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl PromptPlayer {
+    pub fn spawn(
+        frame_tx: mpsc::Sender<Frame>,
+        sample_rate: u32,
+        channels: u16,
+        log: Logger,
+    ) -> (Self, JoinHandle<()>) {
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let frame_len =
+            sample_rate as usize * FRAME_DURATION.as_millis() as usize / 1000 * channels as usize;
+        let handle = tokio::spawn(run_player(cmd_rx, frame_tx, frame_len, log));
+        (
+            Self {
+                cmd_tx,
+                sample_rate,
+                channels,
+            },
+            handle,
+        )
+    }
+
+    fn send(&self, cmd: PromptCommand) -> Result<()> {
+        self.cmd_tx
+            .send(cmd)
+            .map_err(|_| anyhow!("prompt player stopped"))
+    }
+
+    // 转换为轨道格式后排队播放
+    pub fn enqueue(&self, audio: &PromptAudio) -> Result<()> {
+        let audio = audio.convert(self.sample_rate, self.channels);
+        self.send(PromptCommand::Enqueue(Clip::Pcm(Arc::new(audio))))
+    }
+
+    pub fn play_now(&self, audio: &PromptAudio) -> Result<()> {
+        let audio = audio.convert(self.sample_rate, self.channels);
+        self.send(PromptCommand::PlayNow(Clip::Pcm(Arc::new(audio))))
+    }
+
+    // 文件经进程级缓存编码为轨道格式，命中时无需解码
+    pub async fn enqueue_file(&self, path: impl Into<PathBuf>) -> Result<()> {
+        let prompt = cache::load(path.into(), self.sample_rate, self.channels).await?;
+        self.send(PromptCommand::Enqueue(Clip::Opus(prompt)))
+    }
+
+    pub async fn play_file_now(&self, path: impl Into<PathBuf>) -> Result<()> {
+        let prompt = cache::load(path.into(), self.sample_rate, self.channels).await?;
+        self.send(PromptCommand::PlayNow(Clip::Opus(prompt)))
+    }
+
+    pub fn stop(&self) -> Result<()> {
+        self.send(PromptCommand::Stop)
+    }
+
+    pub fn pause(&self) -> Result<()> {
+        self.send(PromptCommand::Pause)
+    }
+
+    pub fn resume(&self) -> Result<()> {
+        self.send(PromptCommand::Resume)
+    }
+}
+
+// 由发送任务按 20ms 取帧，通道容量很小，停止/暂停只有几帧的延迟；
+// 播放期间通道保持有帧，发送任务不会在提示音中间插入 TTS
+async fn run_player(
+    mut cmd_rx: mpsc::UnboundedReceiver<PromptCommand>,
+    frame_tx: mpsc::Sender<Frame>,
+    frame_len: usize,
+    log: Logger,
+) {
+    let mut state = PlayerState::default();
+
+    loop {
+        tokio::select! {
+            cmd = cmd_rx.recv() => {
+                let Some(cmd) = cmd else {
+                    break;
+                };
+                debug!(log: log, "prompt command {}", cmd.name());
+                state.apply(cmd);
+            }
+            permit = frame_tx.reserve(), if state.is_playing() => {
+                let Ok(permit) = permit else {
+                    warn!(log: log, "local audio channel closed, prompt player exits");
+                    break;
+                };
+                if let Some(frame) = state.next_frame(frame_len) {
+                    permit.send(frame);
+                }
+            }
+        }
+    }
+    info!(log: log, "prompt player stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn clip(len: usize, value: i16) -> Clip {
+        Clip::Pcm(Arc::new(PromptAudio {
+            pcm: vec![value; len],
+            sample_rate: 48000,
+            channels: 1,
+        }))
+    }
+
+    fn pcm(frame: Option<Frame>) -> Vec<i16> {
+        match frame {
+            Some(Frame::Pcm(data)) => data,
+            _ => panic!("expected pcm frame"),
+        }
+    }
+
+    #[test]
+    fn test_queue_plays_in_order() {
+        let mut state = PlayerState::default();
+        state.apply(PromptCommand::Enqueue(clip(15, 1)));
+        state.apply(PromptCommand::Enqueue(clip(10, 2)));
+        assert_eq!(pcm(state.next_frame(10)), vec![1; 10]);
+        assert_eq!(pcm(state.next_frame(10)), vec![1; 5]);
+        assert_eq!(pcm(state.next_frame(10)), vec![2; 10]);
+        assert!(state.next_frame(10).is_none());
+        assert!(!state.is_playing());
+    }
+
+    #[test]
+    fn test_pause_stop_and_play_now() {
+        let mut state = PlayerState::default();
+        state.apply(PromptCommand::Enqueue(clip(30, 1)));
+        state.next_frame(10);
+        state.apply(PromptCommand::Pause);
+        assert!(state.next_frame(10).is_none());
+        state.apply(PromptCommand::Resume);
+        assert_eq!(pcm(state.next_frame(10)), vec![1; 10]);
+
+        state.apply(PromptCommand::Enqueue(clip(10, 2)));
+        state.apply(PromptCommand::PlayNow(clip(10, 3)));
+        assert_eq!(pcm(state.next_frame(10)), vec![3; 10]);
+        assert!(state.next_frame(10).is_none());
+
+        state.apply(PromptCommand::Enqueue(clip(10, 4)));
+        state.apply(PromptCommand::Stop);
+        assert!(state.next_frame(10).is_none());
+    }
+
+    #[test]
+    fn test_encoded_clip_one_packet_per_frame() {
+        let prompt = Arc::new(EncodedPrompt {
+            packets: vec![Bytes::from_static(b"a"), Bytes::from_static(b"b")],
+            sample_rate: 48000,
+            channels: 1,
+        });
+        let mut state = PlayerState::default();
+        state.apply(PromptCommand::Enqueue(Clip::Opus(prompt)));
+        state.apply(PromptCommand::Enqueue(clip(5, 1)));
+        for expected in [b"a", b"b"] {
+            match state.next_frame(10) {
+                Some(Frame::Opus(packet)) => assert_eq!(&packet[..], expected),
+                _ => panic!("expected opus frame"),
+            }
+        }
+        assert_eq!(pcm(state.next_frame(10)), vec![1; 5]);
+        assert!(state.next_frame(10).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_player_feeds_frames_as_sender_consumes() {
+        let (frame_tx, mut frame_rx) = mpsc::channel(2);
+        let log = Logger::root(slog::Discard, slog::o!());
+        let (player, handle) = PromptPlayer::spawn(frame_tx, 1000, 1, log);
+        // 1000Hz 单声道每帧 20 个采样点
+        let audio = PromptAudio {
+            pcm: vec![7; 50],
+            sample_rate: 1000,
+            channels: 1,
+        };
+        player.enqueue(&audio).unwrap();
+        // 发送任务取走一帧就补一帧，中间没有空档
+        for expected in [20, 20, 10] {
+            let frame = frame_rx.recv().await;
+            assert_eq!(pcm(frame), vec![7; expected]);
+        }
+        let idle = tokio::time::timeout(Duration::from_millis(50), frame_rx.recv()).await;
+        assert!(idle.is_err());
+
+        drop(player);
+        handle.await.unwrap();
+    }
+}
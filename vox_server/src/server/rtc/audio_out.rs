@@ -0,0 +1,133 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use bytes::Bytes;
+use slog::Logger;
+use tokio::sync::mpsc;
+use tokio::time::MissedTickBehavior;
+use webrtc::media::Sample;
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
+
+use super::en_decoder::{create_encoder, AudioDecoder, CodecType, OpusAudioDecoder};
+use crate::config::OpusConfig;
+use crate::prompt::Frame;
+use crate::server::data::AudioData;
+use crate::utils::audio::{AudioConverter, Rechunker};
+use crate::{debug, info};
+
+pub const FRAME_DURATION: Duration = Duration::from_millis(20);
+// 最多预读的帧数，超过后不再从通道读取，让生产端感受到背压
+const MAX_BUFFERED_FRAMES: usize = 50;
+
+// 消费管线输出的 PCM（轨道采样率与声道数，交织），按本地轨道的编码每 20ms 编码后匀速写入。
+// G.711 先转换为 8kHz 单声道再编码。
+// 提示音帧（PCM 或缓存中已编码的包）优先发送，期间管线的 PCM 留在缓冲中，不与提示音交错。
+// 队列为空时发送静音保持 RTP 连续，通道关闭且缓存发完后退出
+pub async fn run_audio_sender(
+    mut receiver: mpsc::Receiver<AudioData>,
+    mut prompt_rx: mpsc::Receiver<Frame>,
+    audio_track: Arc<TrackLocalStaticSample>,
+    sample_rate: u32,
+    channels: u16,
+    opus: OpusConfig,
+    log: Logger,
+) -> Result<()> {
+    let mime_type = audio_track.codec().mime_type;
+    let Some(codec) = CodecType::from_mime_type(&mime_type) else {
+        bail!("unsupported local audio codec {}", mime_type);
+    };
+    let (encode_rate, encode_channels) = codec.pcm_format(sample_rate, channels);
+    let mut encoder = create_encoder(codec, encode_rate, encode_channels, &opus)?;
+    let mut converter = AudioConverter::new(sample_rate, channels, encode_rate, encode_channels);
+    let mut reframer = Rechunker::for_duration(encode_rate, encode_channels, FRAME_DURATION);
+    let silence = encoder.encode(&vec![0i16; reframer.frame_len()]).await?;
+    // 提示音为轨道格式，非 Opus 编码时解码、转换后重新编码
+    let track_frame_len =
+        Rechunker::for_duration(sample_rate, channels, FRAME_DURATION).frame_len();
+    let mut prompt_converter =
+        AudioConverter::new(sample_rate, channels, encode_rate, encode_channels);
+    let mut prompt_decoder: Option<OpusAudioDecoder> = None;
+
+    let mut ticker = tokio::time::interval(FRAME_DURATION);
+    // 调度延迟后不补发，按 20ms 继续
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut closed = false;
+    let mut speaking = false;
+
+    loop {
+        tokio::select! {
+            audio = receiver.recv(), if !closed && reframer.buffered_frames() < MAX_BUFFERED_FRAMES => {
+                match audio {
+                    Some(audio) => reframer.push(&converter.process(&audio.data)),
+                    None => {
+                        debug!(log: log, "local audio channel closed");
+                        closed = true;
+                    }
+                }
+            }
+            _ = ticker.tick() => {
+                if let Ok(frame) = prompt_rx.try_recv() {
+                    speaking = true;
+                    let mut pcm = match frame {
+                        Frame::Opus(packet) if codec == CodecType::Opus => {
+                            write_frame(&audio_track, packet).await?;
+                            continue;
+                        }
+                        Frame::Opus(packet) => {
+                            let decoder = match prompt_decoder.as_mut() {
+                                Some(decoder) => decoder,
+                                None => prompt_decoder
+                                    .insert(OpusAudioDecoder::new(sample_rate, channels)?),
+                            };
+                            decoder.decode(&packet).await?
+                        }
+                        Frame::Pcm(pcm) => pcm,
+                    };
+                    pcm.resize(track_frame_len, 0);
+                    let mut pcm = prompt_converter.process(&pcm);
+                    pcm.resize(reframer.frame_len(), 0);
+                    let data = encoder.encode(&pcm).await?;
+                    write_frame(&audio_track, data).await?;
+                    continue;
+                }
+                let frame = match reframer.pop_frame() {
+                    Some(frame) => Some(frame),
+                    None if closed => reframer.flush(),
+                    None => None,
+                };
+                let data = match frame {
+                    Some(frame) => {
+                        if !speaking {
+                            debug!(log: log, "local audio started");
+                            speaking = true;
+                        }
+                        encoder.encode(&frame).await?
+                    }
+                    None if closed => break,
+                    None => {
+                        if speaking {
+                            debug!(log: log, "local audio idle, sending silence");
+                            speaking = false;
+                        }
+                        silence.clone()
+                    }
+                };
+                write_frame(&audio_track, data).await?;
+            }
+        }
+    }
+
+    info!(log: log, "local audio sender stopped");
+    Ok(())
+}
+
+async fn write_frame(audio_track: &TrackLocalStaticSample, data: Bytes) -> Result<()> {
+    let sample = Sample {
+        data,
+        duration: FRAME_DURATION,
+        ..Default::default()
+    };
+    audio_track.write_sample(&sample).await?;
+    Ok(())
+}
@@ -1,14 +1,91 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use async_trait::async_trait;
+use audiopus::coder::{Decoder as OpusDecoder, Encoder as OpusEncoder};
+use audiopus::{ffi, Application, Bitrate, Channels, SampleRate};
 use bytes::Bytes;
-use opus::Decoder as OpusDecoder;
-use opus::Encoder as OpusEncoder;
 use tokio::sync::Mutex;
+use webrtc::api::media_engine::{MIME_TYPE_OPUS, MIME_TYPE_PCMA, MIME_TYPE_PCMU};
+
+use crate::config::{CodecConfig, OpusConfig};
+
+// Opus 的 RTP 时钟固定为 48kHz（RFC 7587）
+pub const OPUS_CLOCK_RATE: u32 = 48000;
+// G.711 固定为 8kHz 单声道，RTP 时钟与采样率相同
+pub const G711_CLOCK_RATE: u32 = 8000;
+// 单个 Opus 包的上限（libopus 建议值）
+pub const OPUS_MAX_PACKET_BYTES: usize = 4000;
+// 单帧最长 120ms
+const OPUS_MAX_FRAME_MS: usize = 120;
+
+// 我们的 Opus fmtp 参数，声明希望对端如何发送（RFC 7587）
+pub fn opus_fmtp_line(cfg: &CodecConfig) -> String {
+    let mut params = vec![
+        "minptime=10".to_string(),
+        format!("ptime={}", cfg.opus_ptime),
+        format!("useinbandfec={}", cfg.opus_useinbandfec as u8),
+    ];
+    if cfg.opus_stereo {
+        params.push("stereo=1".to_string());
+    }
+    if cfg.opus_usedtx {
+        params.push("usedtx=1".to_string());
+    }
+    if cfg.opus_max_average_bitrate > 0 {
+        params.push(format!(
+            "maxaveragebitrate={}",
+            cfg.opus_max_average_bitrate
+        ));
+    }
+    params.join(";")
+}
+
+fn opus_sample_rate(sample_rate: u32) -> Result<SampleRate> {
+    match sample_rate {
+        8000 => Ok(SampleRate::Hz8000),
+        12000 => Ok(SampleRate::Hz12000),
+        16000 => Ok(SampleRate::Hz16000),
+        24000 => Ok(SampleRate::Hz24000),
+        48000 => Ok(SampleRate::Hz48000),
+        other => bail!("unsupported opus sample rate {}", other),
+    }
+}
+
+pub fn opus_channels(channels: u16) -> Result<Channels> {
+    match channels {
+        1 => Ok(Channels::Mono),
+        2 => Ok(Channels::Stereo),
+        n => bail!("unsupported opus channel count {}", n),
+    }
+}
+
+fn opus_application(name: &str) -> Result<Application> {
+    match name {
+        "voip" => Ok(Application::Voip),
+        "audio" => Ok(Application::Audio),
+        "lowdelay" => Ok(Application::LowDelay),
+        other => bail!("unknown opus application {}", other),
+    }
+}
+
+// 每声道采样数对应的帧长是否为 2.5/5/10/20/40/60/80/100/120ms 之一
+pub fn is_valid_frame(sample_rate: u32, samples: usize) -> bool {
+    let unit = sample_rate as usize / 400;
+    unit > 0
+        && samples.is_multiple_of(unit)
+        && matches!(samples / unit, 1 | 2 | 4 | 8 | 16 | 24 | 32 | 40 | 48)
+}
+
 // 音频解码器trait
 #[async_trait]
 pub trait AudioDecoder: Send + 'static {
     // 解码音频数据
     async fn decode(&mut self, input: &[u8]) -> Result<Vec<i16>>;
+    // 补偿 lost 个丢失的包，共 samples 个采样（每声道），next 为丢包后收到的第一个包。
+    // 默认填充静音
+    async fn conceal(&mut self, next: &[u8], lost: usize, samples: usize) -> Result<Vec<i16>> {
+        let _ = (next, lost);
+        Ok(vec![0i16; samples * self.channels() as usize])
+    }
     // 获取采样率
     fn sample_rate(&self) -> u32;
     // 获取通道数
@@ -24,7 +101,7 @@ pub struct OpusAudioDecoder {
 impl OpusAudioDecoder {
     pub fn new(sample_rate: u32, channels: u16) -> Result<Self> {
         Ok(Self {
-            decoder: OpusDecoder::new(sample_rate, opus::Channels::Mono)?,
+            decoder: OpusDecoder::new(opus_sample_rate(sample_rate)?, opus_channels(channels)?)?,
             sample_rate,
             channels,
         })
@@ -34,12 +111,47 @@ impl OpusAudioDecoder {
 #[async_trait]
 impl AudioDecoder for OpusAudioDecoder {
     async fn decode(&mut self, input: &[u8]) -> Result<Vec<i16>> {
-        let mut output = vec![0i16; 960 * self.channels as usize]; // 20ms at 48kHz
-        let samples = self.decoder.decode(input, &mut output, false)?;
+        // 包内帧长由发送端决定，按最长 120ms 分配
+        let max = self.sample_rate as usize * OPUS_MAX_FRAME_MS / 1000;
+        let mut output = vec![0i16; max * self.channels as usize];
+        let samples = self.decoder.decode(Some(input), &mut output, false)?;
         output.truncate(samples * self.channels as usize);
         Ok(output)
     }
 
+    // 最后一个丢失的包用下一个包的带内 FEC 恢复（包内没有 FEC 时 libopus 退化为 PLC），
+    // 更早的包用 PLC。每次解码的时长须为 2.5ms 的整数倍且不超过 120ms
+    async fn conceal(&mut self, next: &[u8], lost: usize, samples: usize) -> Result<Vec<i16>> {
+        let unit = self.sample_rate as usize / 400;
+        let max = self.sample_rate as usize * OPUS_MAX_FRAME_MS / 1000;
+        let channels = self.channels as usize;
+        let lost = lost.max(1);
+        let per_packet = (samples / lost / unit * unit).clamp(unit, max);
+
+        let mut output = Vec::with_capacity(samples * channels);
+        let mut frame = vec![0i16; max * channels];
+        let mut remaining = samples / unit * unit;
+        for i in 0..lost {
+            if remaining == 0 {
+                break;
+            }
+            let len = if i + 1 == lost {
+                remaining.min(max)
+            } else {
+                per_packet.min(remaining)
+            };
+            // 没有输入表示丢包，由 PLC 补偿
+            let fec = i + 1 == lost && !next.is_empty();
+            let input = if fec { Some(next) } else { None };
+            let decoded = self
+                .decoder
+                .decode(input, &mut frame[..len * channels], fec)?;
+            output.extend_from_slice(&frame[..decoded * channels]);
+            remaining -= len;
+        }
+        Ok(output)
+    }
+
     fn sample_rate(&self) -> u32 {
         self.sample_rate
     }
@@ -57,26 +169,56 @@ pub struct OpusAudioEncoder {
 
 impl OpusAudioEncoder {
     pub fn new(sample_rate: u32, channels: u16) -> Result<Self> {
+        Self::with_config(sample_rate, channels, &OpusConfig::default())
+    }
+
+    pub fn with_config(sample_rate: u32, channels: u16, cfg: &OpusConfig) -> Result<Self> {
+        let mut encoder = OpusEncoder::new(
+            opus_sample_rate(sample_rate)?,
+            opus_channels(channels)?,
+            opus_application(&cfg.application)?,
+        )?;
+        encoder.set_bitrate(match cfg.bitrate {
+            0 => Bitrate::Auto,
+            bits => Bitrate::BitsPerSecond(bits),
+        })?;
+        encoder.set_complexity(cfg.complexity)?;
+        encoder.set_vbr(cfg.vbr)?;
+        encoder.set_vbr_constraint(cfg.vbr_constraint)?;
+        // audiopus 0.2 没有单独的 DTX 设置
+        encoder.set_encoder_ctl_request(ffi::OPUS_SET_DTX_REQUEST, cfg.dtx as i32)?;
+        encoder.set_inband_fec(cfg.inband_fec)?;
+        encoder.set_packet_loss_perc(cfg.packet_loss_perc)?;
         Ok(Self {
-            encoder: Mutex::new(OpusEncoder::new(
-                sample_rate,
-                opus::Channels::Mono,
-                opus::Application::Voip,
-            )?),
+            encoder: Mutex::new(encoder),
             sample_rate,
             channels,
         })
     }
+
+    // 同步编码一帧（交织），供阻塞线程使用
+    pub fn encode_blocking(&mut self, input: &[i16]) -> Result<Bytes> {
+        let channels = self.channels as usize;
+        if !input.len().is_multiple_of(channels) || !is_valid_frame(self.sample_rate, input.len() / channels)
+        {
+            bail!(
+                "invalid opus frame: {} samples at {}Hz x{}",
+                input.len(),
+                self.sample_rate,
+                channels
+            );
+        }
+        let mut output = vec![0u8; OPUS_MAX_PACKET_BYTES];
+        let len = self.encoder.get_mut().encode(input, &mut output)?;
+        output.truncate(len);
+        Ok(Bytes::from(output))
+    }
 }
 
 #[async_trait]
 impl AudioEncoder for OpusAudioEncoder {
     async fn encode(&mut self, input: &[i16]) -> Result<Bytes> {
-        let mut output = vec![0u8; 1920]; // 20ms at 48kHz
-        let mut encoder = self.encoder.lock().await;
-        let samples = encoder.encode(input, &mut output)?;
-        output.truncate(samples * self.channels as usize);
-        Ok(Bytes::from(output))
+        self.encode_blocking(input)
     }
 
     fn sample_rate(&self) -> u32 {
@@ -88,14 +230,203 @@ impl AudioEncoder for OpusAudioEncoder {
     }
 }
 
+// G.711 μ-law / A-law（ITU-T G.711），每个采样编码为一个字节
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum G711Law {
+    Mu,
+    A,
+}
+
+const ULAW_BIAS: i32 = 0x84;
+const ULAW_CLIP: i32 = 32635;
+
+pub fn linear_to_ulaw(sample: i16) -> u8 {
+    let mut pcm = sample as i32;
+    let sign = if pcm < 0 {
+        pcm = -pcm;
+        0x80
+    } else {
+        0
+    };
+    let pcm = pcm.min(ULAW_CLIP) + ULAW_BIAS;
+    // 段号为 pcm >> 7 最高位的位置
+    let exponent = 31 - ((pcm >> 7) as u32).leading_zeros() as i32;
+    let mantissa = (pcm >> (exponent + 3)) & 0x0F;
+    !(sign | (exponent << 4) | mantissa) as u8
+}
+
+pub fn ulaw_to_linear(code: u8) -> i16 {
+    let code = !code;
+    let exponent = (code >> 4) & 0x07;
+    let magnitude = ((((code & 0x0F) as i32) << 3) + ULAW_BIAS) << exponent;
+    if code & 0x80 != 0 {
+        (ULAW_BIAS - magnitude) as i16
+    } else {
+        (magnitude - ULAW_BIAS) as i16
+    }
+}
+
+pub fn linear_to_alaw(sample: i16) -> u8 {
+    // A-law 按 13 位量化
+    let mut pcm = (sample >> 3) as i32;
+    let mask = if pcm >= 0 {
+        0xD5
+    } else {
+        pcm = -pcm - 1;
+        0x55
+    };
+    let segment = match pcm {
+        0..=0x1F => 0,
+        _ => (32 - (pcm as u32).leading_zeros() - 5) as i32,
+    };
+    if segment >= 8 {
+        return (0x7F ^ mask) as u8;
+    }
+    let quant = if segment < 2 {
+        (pcm >> 1) & 0x0F
+    } else {
+        (pcm >> segment) & 0x0F
+    };
+    (((segment << 4) | quant) ^ mask) as u8
+}
+
+pub fn alaw_to_linear(code: u8) -> i16 {
+    let code = code ^ 0x55;
+    let segment = (code >> 4) & 0x07;
+    let mut magnitude = ((code & 0x0F) as i32) << 4;
+    magnitude += match segment {
+        0 => 8,
+        _ => 0x108,
+    };
+    if segment > 1 {
+        magnitude <<= segment - 1;
+    }
+    if code & 0x80 != 0 {
+        magnitude as i16
+    } else {
+        -magnitude as i16
+    }
+}
+
+fn check_g711_format(sample_rate: u32, channels: u16) -> Result<()> {
+    if sample_rate != G711_CLOCK_RATE || channels != 1 {
+        bail!(
+            "G.711 requires {}Hz mono, got {}Hz x{}",
+            G711_CLOCK_RATE,
+            sample_rate,
+            channels
+        );
+    }
+    Ok(())
+}
+
+pub struct G711AudioDecoder {
+    law: G711Law,
+}
+
+impl G711AudioDecoder {
+    pub fn new(law: G711Law, sample_rate: u32, channels: u16) -> Result<Self> {
+        check_g711_format(sample_rate, channels)?;
+        Ok(Self { law })
+    }
+}
+
+#[async_trait]
+impl AudioDecoder for G711AudioDecoder {
+    async fn decode(&mut self, input: &[u8]) -> Result<Vec<i16>> {
+        let decode = match self.law {
+            G711Law::Mu => ulaw_to_linear,
+            G711Law::A => alaw_to_linear,
+        };
+        Ok(input.iter().map(|&code| decode(code)).collect())
+    }
+
+    fn sample_rate(&self) -> u32 {
+        G711_CLOCK_RATE
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+}
+
+pub struct G711AudioEncoder {
+    law: G711Law,
+}
+
+impl G711AudioEncoder {
+    pub fn new(law: G711Law, sample_rate: u32, channels: u16) -> Result<Self> {
+        check_g711_format(sample_rate, channels)?;
+        Ok(Self { law })
+    }
+}
+
+#[async_trait]
+impl AudioEncoder for G711AudioEncoder {
+    async fn encode(&mut self, input: &[i16]) -> Result<Bytes> {
+        let encode = match self.law {
+            G711Law::Mu => linear_to_ulaw,
+            G711Law::A => linear_to_alaw,
+        };
+        Ok(input.iter().map(|&sample| encode(sample)).collect())
+    }
+
+    fn sample_rate(&self) -> u32 {
+        G711_CLOCK_RATE
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+}
+
 // 工厂函数用于创建不同类型的解码器
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CodecType {
     Opus,
-    // 未来可以添加更多解码器类型
-    // AAC,
-    // MP3,
-    // etc.
+    Pcmu,
+    Pcma,
+}
+
+impl CodecType {
+    // 按协商结果的 mime_type 选择，大小写不敏感
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        if mime_type.eq_ignore_ascii_case(MIME_TYPE_OPUS) {
+            Some(Self::Opus)
+        } else if mime_type.eq_ignore_ascii_case(MIME_TYPE_PCMU) {
+            Some(Self::Pcmu)
+        } else if mime_type.eq_ignore_ascii_case(MIME_TYPE_PCMA) {
+            Some(Self::Pcma)
+        } else {
+            None
+        }
+    }
+
+    // 配置中的编码名（rtc.codecs.audio）
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "opus" => Some(Self::Opus),
+            "pcmu" => Some(Self::Pcmu),
+            "pcma" => Some(Self::Pcma),
+            _ => None,
+        }
+    }
+
+    // RTP 时间戳的时钟频率
+    pub fn clock_rate(&self) -> u32 {
+        match self {
+            Self::Opus => OPUS_CLOCK_RATE,
+            Self::Pcmu | Self::Pcma => G711_CLOCK_RATE,
+        }
+    }
+
+    // 编解码使用的 PCM 格式：Opus 沿用轨道配置，G.711 固定为 8kHz 单声道
+    pub fn pcm_format(&self, sample_rate: u32, channels: u16) -> (u32, u16) {
+        match self {
+            Self::Opus => (sample_rate, channels),
+            Self::Pcmu | Self::Pcma => (G711_CLOCK_RATE, 1),
+        }
+    }
 }
 
 pub fn create_decoder(
@@ -107,7 +438,17 @@ pub fn create_decoder(
         CodecType::Opus => {
             let decoder = OpusAudioDecoder::new(sample_rate, channels)?;
             Ok(Box::new(decoder))
-        } // 未来添加更多解码器类型的匹配
+        }
+        CodecType::Pcmu => Ok(Box::new(G711AudioDecoder::new(
+            G711Law::Mu,
+            sample_rate,
+            channels,
+        )?)),
+        CodecType::Pcma => Ok(Box::new(G711AudioDecoder::new(
+            G711Law::A,
+            sample_rate,
+            channels,
+        )?)),
     }
 }
 
@@ -122,18 +463,27 @@ pub trait AudioEncoder: Send + Sync {
 // 统一的音频处理器结构体
 pub struct VoxDecoder {
     decoder: Box<dyn AudioDecoder>,
+    clock_rate: u32,
 }
 
 impl VoxDecoder {
     pub fn new(decoder_type: CodecType, sample_rate: u32, channels: u16) -> Result<Self> {
+        let clock_rate = decoder_type.clock_rate();
         let decoder = create_decoder(decoder_type, sample_rate, channels)?;
-        Ok(Self { decoder })
+        Ok(Self {
+            decoder,
+            clock_rate,
+        })
     }
 
     pub async fn decode(&mut self, input: &[u8]) -> Result<Vec<i16>> {
         self.decoder.decode(input).await
     }
 
+    pub async fn conceal(&mut self, next: &[u8], lost: usize, samples: usize) -> Result<Vec<i16>> {
+        self.decoder.conceal(next, lost, samples).await
+    }
+
     pub fn sample_rate(&self) -> u32 {
         self.decoder.sample_rate()
     }
@@ -141,17 +491,241 @@ impl VoxDecoder {
     pub fn channels(&self) -> u16 {
         self.decoder.channels()
     }
+
+    pub fn clock_rate(&self) -> u32 {
+        self.clock_rate
+    }
 }
 
 pub fn create_encoder(
     codec_type: CodecType,
     sample_rate: u32,
     channels: u16,
+    opus: &OpusConfig,
 ) -> Result<Box<dyn AudioEncoder>> {
     match codec_type {
         CodecType::Opus => {
-            let encoder = OpusAudioEncoder::new(sample_rate, channels)?;
+            let encoder = OpusAudioEncoder::with_config(sample_rate, channels, opus)?;
             Ok(Box::new(encoder))
         }
+        CodecType::Pcmu => Ok(Box::new(G711AudioEncoder::new(
+            G711Law::Mu,
+            sample_rate,
+            channels,
+        )?)),
+        CodecType::Pcma => Ok(Box::new(G711AudioEncoder::new(
+            G711Law::A,
+            sample_rate,
+            channels,
+        )?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 左声道正弦，右声道静音
+    fn sine(sample_rate: u32, channels: u16, frames: usize) -> Vec<i16> {
+        (0..frames)
+            .flat_map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                let left = ((t * 440.0 * std::f32::consts::TAU).sin() * 8000.0) as i16;
+                std::iter::once(left).chain(std::iter::repeat_n(0, channels as usize - 1))
+            })
+            .collect()
+    }
+
+    fn energy(samples: impl Iterator<Item = i16>) -> f64 {
+        samples.map(|s| s as f64 * s as f64).sum()
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_rates_and_channels() {
+        for (rate, channels) in [(48000, 1), (48000, 2), (24000, 1), (16000, 2), (8000, 1)] {
+            let mut encoder = OpusAudioEncoder::new(rate, channels).unwrap();
+            let mut decoder = OpusAudioDecoder::new(rate, channels).unwrap();
+            let frame = rate as usize / 50;
+            let input = sine(rate, channels, frame * 20);
+
+            let mut output = Vec::new();
+            for chunk in input.chunks(frame * channels as usize) {
+                let packet = encoder.encode(chunk).await.unwrap();
+                let decoded = decoder.decode(&packet).await.unwrap();
+                assert_eq!(decoded.len(), chunk.len(), "{}Hz x{}", rate, channels);
+                output.extend(decoded);
+            }
+
+            // 跳过编码器起始阶段比较能量
+            let skip = frame * 5 * channels as usize;
+            let ch = channels as usize;
+            let left_in = energy(input[skip..].iter().step_by(ch).copied());
+            let left_out = energy(output[skip..].iter().step_by(ch).copied());
+            let ratio = left_out / left_in;
+            assert!(
+                ratio > 0.5 && ratio < 2.0,
+                "{}Hz x{} ratio {}",
+                rate,
+                channels,
+                ratio
+            );
+            if channels == 2 {
+                let right_out = energy(output[skip + 1..].iter().step_by(2).copied());
+                assert!(
+                    right_out < left_out * 0.1,
+                    "{}Hz right channel leaked",
+                    rate
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_frame_durations() {
+        let mut encoder = OpusAudioEncoder::new(48000, 2).unwrap();
+        let mut decoder = OpusAudioDecoder::new(48000, 2).unwrap();
+        // 单位 0.1ms
+        for tenths in [25, 50, 100, 200, 400, 600, 800, 1000, 1200] {
+            let frames = 48000 * tenths / 10000;
+            let packet = encoder.encode(&sine(48000, 2, frames)).await.unwrap();
+            assert_eq!(decoder.decode(&packet).await.unwrap().len(), frames * 2);
+        }
+        // 7ms 不是合法帧长，奇数个采样无法按声道拆分
+        assert!(encoder.encode(&sine(48000, 2, 336)).await.is_err());
+        assert!(encoder.encode(&[0i16; 1921]).await.is_err());
+    }
+
+    #[test]
+    fn test_encoder_settings() {
+        let cfg = OpusConfig {
+            application: "audio".to_string(),
+            bitrate: 24000,
+            complexity: 5,
+            vbr: false,
+            vbr_constraint: false,
+            dtx: true,
+            inband_fec: false,
+            packet_loss_perc: 20,
+        };
+        let mut encoder = OpusAudioEncoder::with_config(16000, 1, &cfg).unwrap();
+        let inner = encoder.encoder.get_mut();
+        assert_eq!(inner.application().unwrap(), Application::Audio);
+        assert_eq!(inner.bitrate().unwrap(), Bitrate::BitsPerSecond(24000));
+        assert_eq!(inner.complexity().unwrap(), 5);
+        assert!(!inner.vbr().unwrap());
+        assert_eq!(
+            inner
+                .encoder_ctl_request(ffi::OPUS_GET_DTX_REQUEST)
+                .unwrap(),
+            1
+        );
+        assert!(!inner.inband_fec().unwrap());
+        assert_eq!(inner.packet_loss_perc().unwrap(), 20);
+
+        assert!(OpusAudioEncoder::new(44100, 1).is_err());
+        assert!(OpusAudioEncoder::new(48000, 3).is_err());
+        assert!(OpusAudioDecoder::new(48000, 3).is_err());
+        let cfg = OpusConfig {
+            application: "music".to_string(),
+            ..OpusConfig::default()
+        };
+        assert!(OpusAudioEncoder::with_config(48000, 1, &cfg).is_err());
+    }
+
+    #[test]
+    fn test_g711_reference_values() {
+        assert_eq!(linear_to_ulaw(0), 0xFF);
+        assert_eq!(linear_to_ulaw(i16::MAX), 0x80);
+        assert_eq!(linear_to_ulaw(i16::MIN), 0x00);
+        assert_eq!(ulaw_to_linear(0x80), 32124);
+        assert_eq!(ulaw_to_linear(0x00), -32124);
+        assert_eq!(linear_to_alaw(0), 0xD5);
+        assert_eq!(linear_to_alaw(i16::MAX), 0xAA);
+        assert_eq!(linear_to_alaw(i16::MIN), 0x2A);
+        assert_eq!(alaw_to_linear(0xAA), 32256);
+        assert_eq!(alaw_to_linear(0x2A), -32256);
+
+        // 每个码字解码后重新编码不变（μ-law 的 -0 与 +0 相同）
+        for code in 0..=255u8 {
+            if code != 0x7F {
+                assert_eq!(
+                    linear_to_ulaw(ulaw_to_linear(code)),
+                    code,
+                    "ulaw {:#x}",
+                    code
+                );
+            }
+            assert_eq!(
+                linear_to_alaw(alaw_to_linear(code)),
+                code,
+                "alaw {:#x}",
+                code
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_g711_round_trip() {
+        for codec in [CodecType::Pcmu, CodecType::Pcma] {
+            let mut encoder = create_encoder(codec, 8000, 1, &OpusConfig::default()).unwrap();
+            let mut decoder = VoxDecoder::new(codec, 8000, 1).unwrap();
+            let input = sine(8000, 1, 160);
+            let packet = encoder.encode(&input).await.unwrap();
+            assert_eq!(packet.len(), 160);
+            let output = decoder.decode(&packet).await.unwrap();
+            assert_eq!(output.len(), 160);
+            // 对数量化的误差随幅度增大，不超过约 1/16
+            for (a, b) in input.iter().zip(&output) {
+                assert!((*a as i32 - *b as i32).abs() <= (a.abs() as i32 / 16).max(16));
+            }
+        }
+        assert!(G711AudioDecoder::new(G711Law::Mu, 48000, 1).is_err());
+        assert!(create_encoder(CodecType::Pcma, 8000, 2, &OpusConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_codec_from_mime_type() {
+        assert_eq!(
+            CodecType::from_mime_type("audio/opus"),
+            Some(CodecType::Opus)
+        );
+        assert_eq!(
+            CodecType::from_mime_type("audio/PCMU"),
+            Some(CodecType::Pcmu)
+        );
+        assert_eq!(
+            CodecType::from_mime_type("audio/pcma"),
+            Some(CodecType::Pcma)
+        );
+        assert!(CodecType::from_mime_type("audio/G722").is_none());
+        assert_eq!(CodecType::Pcmu.clock_rate(), 8000);
+        assert_eq!(CodecType::from_name("pcma"), Some(CodecType::Pcma));
+        assert!(CodecType::from_name("g722").is_none());
+        assert_eq!(CodecType::Opus.pcm_format(48000, 2), (48000, 2));
+        assert_eq!(CodecType::Pcmu.pcm_format(48000, 2), (8000, 1));
+    }
+
+    #[test]
+    fn test_opus_fmtp_line() {
+        let mut cfg = CodecConfig::default();
+        assert_eq!(opus_fmtp_line(&cfg), "minptime=10;ptime=20;useinbandfec=1");
+        cfg.opus_ptime = 40;
+        cfg.opus_useinbandfec = false;
+        cfg.opus_stereo = true;
+        cfg.opus_usedtx = true;
+        cfg.opus_max_average_bitrate = 32000;
+        assert_eq!(
+            opus_fmtp_line(&cfg),
+            "minptime=10;ptime=40;useinbandfec=0;stereo=1;usedtx=1;maxaveragebitrate=32000"
+        );
+    }
+
+    #[test]
+    fn test_valid_frame() {
+        assert!(is_valid_frame(48000, 120));
+        assert!(is_valid_frame(8000, 960));
+        assert!(is_valid_frame(16000, 320));
+        assert!(!is_valid_frame(48000, 336));
+        assert!(!is_valid_frame(48000, 48000 * 140 / 1000));
     }
 }
@@ -0,0 +1,96 @@
+use crate::msg_center::signaling_msgs::{
+    ERR_INVALID_CANDIDATE, ERR_INVALID_SDP, ERR_NEGOTIATION_FAILED, ERR_RTC_INTERNAL,
+    ERR_UNEXPECTED_STATE,
+};
+
+// RTC 层错误，Bot 据此决定是否结束会话以及回给客户端的错误码
+#[derive(Debug)]
+pub enum RtcError {
+    InvalidSdp(String),       // 客户端发来的 SDP 无法解析
+    InvalidCandidate(String), // 客户端发来的候选无法解析或被拒绝
+    UnexpectedState(String),  // 消息与当前信令状态不符，例如重复或迟到的 Answer
+    Negotiation(webrtc::Error),
+    NotReady(&'static str), // 调用顺序错误，所需的通道或状态未就绪
+    SignalingClosed,        // 信令通道已关闭，无法回复客户端
+    Internal(anyhow::Error),
+}
+
+pub type RtcResult<T> = std::result::Result<T, RtcError>;
+
+impl RtcError {
+    pub fn code(&self) -> i32 {
+        match self {
+            RtcError::InvalidSdp(_) => ERR_INVALID_SDP,
+            RtcError::InvalidCandidate(_) => ERR_INVALID_CANDIDATE,
+            RtcError::UnexpectedState(_) => ERR_UNEXPECTED_STATE,
+            RtcError::Negotiation(_) => ERR_NEGOTIATION_FAILED,
+            RtcError::NotReady(_) | RtcError::SignalingClosed | RtcError::Internal(_) => {
+                ERR_RTC_INTERNAL
+            }
+        }
+    }
+
+    // 客户端输入错误时会话仍可继续，例如丢弃一个坏的候选或多余的 Answer
+    pub fn is_fatal(&self) -> bool {
+        !matches!(
+            self,
+            RtcError::InvalidCandidate(_) | RtcError::UnexpectedState(_)
+        )
+    }
+}
+
+impl std::fmt::Display for RtcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RtcError::InvalidSdp(e) => write!(f, "invalid sdp: {}", e),
+            RtcError::InvalidCandidate(e) => write!(f, "invalid ice candidate: {}", e),
+            RtcError::UnexpectedState(e) => write!(f, "unexpected state: {}", e),
+            RtcError::Negotiation(e) => write!(f, "negotiation failed: {}", e),
+            RtcError::NotReady(what) => write!(f, "{} not ready", what),
+            RtcError::SignalingClosed => write!(f, "signaling channel closed"),
+            RtcError::Internal(e) => write!(f, "internal error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RtcError {}
+
+impl From<webrtc::Error> for RtcError {
+    fn from(e: webrtc::Error) -> Self {
+        RtcError::Negotiation(e)
+    }
+}
+
+impl From<anyhow::Error> for RtcError {
+    fn from(e: anyhow::Error) -> Self {
+        RtcError::Internal(e)
+    }
+}
+
+impl<T> From<tokio::sync::mpsc::error::SendError<T>> for RtcError {
+    fn from(_: tokio::sync::mpsc::error::SendError<T>) -> Self {
+        RtcError::SignalingClosed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes() {
+        let err = RtcError::InvalidSdp("eof".to_string());
+        assert_eq!(err.code(), ERR_INVALID_SDP);
+        assert!(err.is_fatal());
+
+        let err = RtcError::InvalidCandidate("bad".to_string());
+        assert_eq!(err.code(), ERR_INVALID_CANDIDATE);
+        assert!(!err.is_fatal());
+
+        let err = RtcError::UnexpectedState("stable".to_string());
+        assert_eq!(err.code(), ERR_UNEXPECTED_STATE);
+        assert!(!err.is_fatal());
+
+        assert_eq!(RtcError::SignalingClosed.code(), ERR_RTC_INTERNAL);
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use webrtc::api::interceptor_registry::register_default_interceptors;
+use webrtc::api::media_engine::{
+    MediaEngine, MIME_TYPE_H264, MIME_TYPE_OPUS, MIME_TYPE_PCMA, MIME_TYPE_PCMU, MIME_TYPE_VP8,
+};
+use webrtc::api::setting_engine::SettingEngine;
+use webrtc::api::{APIBuilder, API};
+use webrtc::interceptor::registry::Registry;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtp_transceiver::rtp_codec::{
+    RTCRtpCodecCapability, RTCRtpCodecParameters, RTPCodecType,
+};
+use webrtc::rtp_transceiver::RTCPFeedback;
+
+use super::en_decoder::{opus_fmtp_line, CodecType};
+use super::network;
+use crate::config::{config_reloaded, CodecConfig, RtcConfig, CONFIG};
+use crate::{debug, info, warn};
+
+// 上报的应答耗时只统计最近一段时间，旧的慢应答不会一直影响调度
+const ANSWER_STATS_WINDOW: Duration = Duration::from_secs(60);
+const ANSWER_STATS_MAX_SAMPLES: usize = 1024;
+
+// 尚未收到 Offer 的 PeerConnection 及创建它的 API 注册的编码，bot 按此编码创建本地轨道
+pub struct WarmPeer {
+    pub peer_connection: Arc<RTCPeerConnection>,
+    pub codecs: CodecConfig,
+}
+
+// 最近 ANSWER_STATS_WINDOW 内的应答耗时
+#[derive(Debug, Default, Clone)]
+pub struct AnswerStats {
+    samples: VecDeque<(Instant, Duration)>,
+}
+
+impl AnswerStats {
+    pub fn record(&mut self, elapsed: Duration, now: Instant) {
+        self.expire(now);
+        if self.samples.len() >= ANSWER_STATS_MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((now, elapsed));
+    }
+
+    pub fn expire(&mut self, now: Instant) {
+        while let Some((at, _)) = self.samples.front() {
+            if now.duration_since(*at) <= ANSWER_STATS_WINDOW {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn avg(&self) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+        let total: Duration = self.samples.iter().map(|(_, elapsed)| *elapsed).sum();
+        total / self.samples.len() as u32
+    }
+
+    pub fn max(&self) -> Duration {
+        self.samples
+            .iter()
+            .map(|(_, elapsed)| *elapsed)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+// 音频编码在 SDP 中的参数与静态负载类型，注册编码与创建本地轨道共用
+pub(crate) fn audio_codec_capability(
+    codec: CodecType,
+    cfg: &CodecConfig,
+) -> (RTCRtpCodecCapability, u8) {
+    let (mime_type, channels, fmtp, payload_type) = match codec {
+        CodecType::Opus => (MIME_TYPE_OPUS, 2, opus_fmtp_line(cfg), 111),
+        CodecType::Pcmu => (MIME_TYPE_PCMU, 0, String::new(), 0),
+        CodecType::Pcma => (MIME_TYPE_PCMA, 0, String::new(), 8),
+    };
+    let capability = RTCRtpCodecCapability {
+        mime_type: mime_type.to_owned(),
+        clock_rate: codec.clock_rate(),
+        channels,
+        sdp_fmtp_line: fmtp,
+        rtcp_feedback: vec![],
+    };
+    (capability, payload_type)
+}
+
+// 按配置的顺序注册音频编码（决定 Answer 中的优先级），视频按需注册。
+// 没有可用编码的 m-line 在 Answer 中被拒绝
+fn register_codecs(media_engine: &mut MediaEngine, cfg: &CodecConfig) -> Result<()> {
+    for name in &cfg.audio {
+        let Some(codec) = CodecType::from_name(name) else {
+            bail!("unknown audio codec {}", name);
+        };
+        let (capability, payload_type) = audio_codec_capability(codec, cfg);
+        media_engine.register_codec(
+            RTCRtpCodecParameters {
+                capability,
+                payload_type,
+                ..Default::default()
+            },
+            RTPCodecType::Audio,
+        )?;
+    }
+
+    if cfg.accept_video {
+        let feedback = vec![
+            RTCPFeedback {
+                typ: "nack".to_owned(),
+                parameter: String::new(),
+            },
+            RTCPFeedback {
+                typ: "nack".to_owned(),
+                parameter: "pli".to_owned(),
+            },
+        ];
+        for (mime_type, fmtp, payload_type) in [
+            (MIME_TYPE_VP8, "", 96),
+            (
+                MIME_TYPE_H264,
+                "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f",
+                125,
+            ),
+        ] {
+            media_engine.register_codec(
+                RTCRtpCodecParameters {
+                    capability: RTCRtpCodecCapability {
+                        mime_type: mime_type.to_owned(),
+                        clock_rate: 90000,
+                        channels: 0,
+                        sdp_fmtp_line: fmtp.to_owned(),
+                        rtcp_feedback: feedback.clone(),
+                    },
+                    payload_type,
+                    ..Default::default()
+                },
+                RTPCodecType::Video,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn build_api(codecs: &CodecConfig, setting_engine: SettingEngine) -> Result<API> {
+    let mut media_engine = MediaEngine::default();
+    register_codecs(&mut media_engine, codecs)?;
+    let registry = register_default_interceptors(Registry::new(), &mut media_engine)?;
+    Ok(APIBuilder::new()
+        .with_media_engine(media_engine)
+        .with_interceptor_registry(registry)
+        .with_setting_engine(setting_engine)
+        .build())
+}
+
+// 进程内共享的 webrtc API，负责创建 PeerConnection 并维护预热池
+// 网络相关配置在启动时确定，修改需要重启；编码配置变化时按新编码重建 API，
+// ICE 服务器与预热池大小随配置热更新，更新后按新配置重建预热池
+pub struct RtcFactory {
+    cfg: Mutex<RtcConfig>,
+    // 只在持有 cfg 锁时替换，与 cfg.codecs 保持一致
+    api: Mutex<Arc<API>>,
+    setting_engine: SettingEngine,
+    pool: Mutex<VecDeque<WarmPeer>>,
+    // 配置每次更新加一，补齐任务据此丢弃按旧配置创建的连接
+    generation: AtomicU64,
+    refilling: AtomicBool,
+    answer_stats: Mutex<AnswerStats>,
+}
+
+impl RtcFactory {
+    pub async fn new(cfg: RtcConfig) -> Result<Arc<Self>> {
+        let setting_engine = network::setting_engine_for(&cfg).await?;
+        let api = build_api(&cfg.codecs, setting_engine.clone())?;
+
+        Ok(Arc::new(Self {
+            cfg: Mutex::new(cfg),
+            api: Mutex::new(Arc::new(api)),
+            setting_engine,
+            pool: Mutex::new(VecDeque::new()),
+            generation: AtomicU64::new(0),
+            refilling: AtomicBool::new(false),
+            answer_stats: Mutex::new(AnswerStats::default()),
+        }))
+    }
+
+    fn warm_pool_size(&self) -> usize {
+        self.cfg.lock().unwrap().warm_pool_size
+    }
+
+    async fn create_peer(&self) -> Result<WarmPeer> {
+        let (api, ice_servers, codecs) = {
+            let cfg = self.cfg.lock().unwrap();
+            let api = self.api.lock().unwrap().clone();
+            (api, network::ice_servers(&cfg), cfg.codecs.clone())
+        };
+        let config = RTCConfiguration {
+            ice_servers,
+            ..Default::default()
+        };
+        let peer_connection = Arc::new(api.new_peer_connection(config).await?);
+        Ok(WarmPeer {
+            peer_connection,
+            codecs,
+        })
+    }
+
+    // 优先取预热好的连接，池空时现建；取走后在后台补齐
+    pub async fn acquire(self: &Arc<Self>) -> Result<WarmPeer> {
+        let warm = self.pool.lock().unwrap().pop_front();
+        let peer = match warm {
+            Some(peer) => {
+                debug!("rtc warm pool hit");
+                peer
+            }
+            None => self.create_peer().await?,
+        };
+        if self.warm_pool_size() > 0 {
+            tokio::spawn(self.clone().refill());
+        }
+        Ok(peer)
+    }
+
+    // 补齐预热池到 warm_pool_size，同一时间只有一个补齐任务
+    pub async fn refill(self: Arc<Self>) {
+        loop {
+            if self.refilling.swap(true, Ordering::AcqRel) {
+                return;
+            }
+            let filled = self.fill_pool().await;
+            self.refilling.store(false, Ordering::Release);
+            // 补齐期间被取走的连接触发的补齐任务会因标记未清除而直接返回，清除后再检查一次
+            if !filled || self.pool_size() >= self.warm_pool_size() {
+                return;
+            }
+        }
+    }
+
+    // 创建失败时返回 false，等下一次取用再重试
+    async fn fill_pool(&self) -> bool {
+        while self.pool_size() < self.warm_pool_size() {
+            let generation = self.generation.load(Ordering::Acquire);
+            match self.create_peer().await {
+                Ok(peer) => {
+                    let mut pool = self.pool.lock().unwrap();
+                    if generation == self.generation.load(Ordering::Acquire) {
+                        pool.push_back(peer);
+                        continue;
+                    }
+                    drop(pool);
+                    close_peer(peer);
+                }
+                Err(e) => {
+                    warn!("Failed to pre-warm peer connection: {}", e);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    // 应用热更新后的 rtc 配置：编码变化时重建 API，丢弃按旧配置预热的连接并重新补齐
+    pub async fn reload(self: &Arc<Self>, mut cfg: RtcConfig) {
+        let current_codecs = self.cfg.lock().unwrap().codecs.clone();
+        let mut api = None;
+        if cfg.codecs != current_codecs {
+            match build_api(&cfg.codecs, self.setting_engine.clone()) {
+                Ok(new_api) => {
+                    info!(
+                        "rtc codecs changed, api rebuilt with {:?}",
+                        cfg.codecs.audio
+                    );
+                    api = Some(Arc::new(new_api));
+                }
+                Err(e) => {
+                    warn!("Failed to rebuild rtc api, keep previous codecs: {}", e);
+                    cfg.codecs = current_codecs;
+                }
+            }
+        }
+
+        let stale: Vec<WarmPeer> = {
+            let mut pool = self.pool.lock().unwrap();
+            let mut current = self.cfg.lock().unwrap();
+            if let Some(api) = api {
+                *self.api.lock().unwrap() = api;
+            }
+            *current = cfg;
+            self.generation.fetch_add(1, Ordering::AcqRel);
+            pool.drain(..).collect()
+        };
+        info!(
+            "rtc factory config reloaded, {} warm peers dropped, pool size {}",
+            stale.len(),
+            self.warm_pool_size()
+        );
+        for peer in stale {
+            close_peer(peer);
+        }
+        self.clone().refill().await;
+    }
+
+    // 配置重新加载后重建预热池，进程存活期间一直运行
+    pub async fn follow_config(self: Arc<Self>) {
+        let mut reloaded = config_reloaded();
+        while reloaded.changed().await.is_ok() {
+            let cfg = CONFIG.read().await.rtc.clone();
+            self.reload(cfg).await;
+        }
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.pool.lock().unwrap().len()
+    }
+
+    // 从收到 Offer 到发出 Answer 的耗时
+    pub fn record_answer(&self, elapsed: Duration) {
+        self.answer_stats
+            .lock()
+            .unwrap()
+            .record(elapsed, Instant::now());
+    }
+
+    pub fn answer_stats(&self) -> AnswerStats {
+        let mut stats = self.answer_stats.lock().unwrap();
+        stats.expire(Instant::now());
+        stats.clone()
+    }
+}
+
+fn close_peer(peer: WarmPeer) {
+    tokio::spawn(async move {
+        if let Err(e) = peer.peer_connection.close().await {
+            warn!("Failed to close warm peer connection: {}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_peer_offer_has_no_data_channel() {
+        let factory = RtcFactory::new(RtcConfig::default()).await.unwrap();
+        let peer = factory.acquire().await.unwrap().peer_connection;
+        peer.add_transceiver_from_kind(RTPCodecType::Audio, None)
+            .await
+            .unwrap();
+        let offer = peer.create_offer(None).await.unwrap();
+        assert!(offer.sdp.contains("m=audio"));
+        assert!(!offer.sdp.contains("m=application"));
+        peer.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reload_rebuilds_api_on_codec_change() {
+        let mut cfg = RtcConfig {
+            warm_pool_size: 1,
+            ..Default::default()
+        };
+        let factory = RtcFactory::new(cfg.clone()).await.unwrap();
+        factory.clone().refill().await;
+        assert_eq!(factory.pool_size(), 1);
+
+        cfg.codecs.audio = vec!["pcmu".to_string()];
+        factory.reload(cfg).await;
+        // 按旧编码预热的连接已被丢弃，新连接与其编码来自重建后的 API
+        let warm = factory.acquire().await.unwrap();
+        assert_eq!(warm.codecs.audio, vec!["pcmu".to_string()]);
+        let peer = warm.peer_connection;
+        peer.add_transceiver_from_kind(RTPCodecType::Audio, None)
+            .await
+            .unwrap();
+        let offer = peer.create_offer(None).await.unwrap();
+        assert!(offer.sdp.contains("PCMU/8000"));
+        assert!(!offer.sdp.contains("opus/48000"));
+        peer.close().await.unwrap();
+    }
+
+    #[test]
+    fn test_answer_stats() {
+        let start = Instant::now();
+        let mut stats = AnswerStats::default();
+        assert_eq!(stats.avg(), Duration::ZERO);
+        stats.record(Duration::from_millis(10), start);
+        stats.record(Duration::from_millis(30), start);
+        assert_eq!(stats.count(), 2);
+        assert_eq!(stats.avg(), Duration::from_millis(20));
+        assert_eq!(stats.max(), Duration::from_millis(30));
+
+        // 超出统计窗口的慢应答不再计入
+        let later = start + ANSWER_STATS_WINDOW + Duration::from_secs(1);
+        stats.record(Duration::from_millis(5), later);
+        assert_eq!(stats.count(), 1);
+        assert_eq!(stats.max(), Duration::from_millis(5));
+        stats.expire(later + ANSWER_STATS_WINDOW + Duration::from_secs(1));
+        assert_eq!(stats.max(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,188 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use webrtc::ice_transport::ice_candidate::{RTCIceCandidate, RTCIceCandidateInit};
+use webrtc::ice_transport::ice_candidate_type::RTCIceCandidateType;
+use webrtc::ice_transport::ice_protocol::RTCIceProtocol;
+
+use crate::{debug, info, warn};
+
+// webrtc-rs 的 ICE agent 只有 UDP，ICE-TCP passive 由这里桥接：
+// 在一个 TCP 端口上接受连接，按 RFC 4571 拆帧后从本机 UDP 套接字转发到共享 UDP mux，
+// agent 把每个 TCP 连接看成一个 peer-reflexive 的 UDP 对端
+
+const MAX_FRAME: usize = u16::MAX as usize;
+const ACCEPT_RETRY: Duration = Duration::from_millis(100);
+
+// RFC 6544 host 类型、passive 方向的本地优先级，低于同地址的 UDP 候选
+const TCP_PASSIVE_PRIORITY: u32 = (126 << 24) + (((4 << 13) + 8191) << 8);
+
+// RFC 4571：每个包前加 2 字节大端长度
+pub fn frame(packet: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(packet.len() + 2);
+    buf.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+    buf.extend_from_slice(packet);
+    buf
+}
+
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut Vec<u8>) -> io::Result<()> {
+    let len = reader.read_u16().await? as usize;
+    buf.resize(len, 0);
+    reader.read_exact(buf).await?;
+    Ok(())
+}
+
+// 监听 TCP 端口，每个连接转发到 target（共享 UDP mux 的本机地址），返回实际监听地址
+pub async fn serve(port: u16, target: SocketAddr) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    let local_addr = listener.local_addr()?;
+    info!("rtc ice-tcp passive listening on {}", local_addr);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    warn!("ice-tcp accept failed: {}", e);
+                    tokio::time::sleep(ACCEPT_RETRY).await;
+                    continue;
+                }
+            };
+            tokio::spawn(async move {
+                if let Err(e) = relay(stream, target).await {
+                    debug!("ice-tcp connection {} closed: {}", peer, e);
+                }
+            });
+        }
+    });
+    Ok(local_addr)
+}
+
+async fn relay(stream: TcpStream, target: SocketAddr) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let socket = Arc::new(UdpSocket::bind(("127.0.0.1", 0)).await?);
+    socket.connect(target).await?;
+    let (mut reader, writer) = stream.into_split();
+
+    let to_tcp = tokio::spawn(udp_to_tcp(socket.clone(), writer));
+
+    let mut buf = Vec::with_capacity(MAX_FRAME);
+    let result = loop {
+        if let Err(e) = read_frame(&mut reader, &mut buf).await {
+            break Err(e);
+        }
+        if let Err(e) = socket.send(&buf).await {
+            break Err(e);
+        }
+    };
+    to_tcp.abort();
+    result
+}
+
+// agent 发往该连接的包加帧后写回 TCP
+async fn udp_to_tcp(socket: Arc<UdpSocket>, mut writer: OwnedWriteHalf) -> io::Result<()> {
+    let mut buf = vec![0u8; MAX_FRAME];
+    loop {
+        let n = socket.recv(&mut buf).await?;
+        writer.write_all(&frame(&buf[..n])).await?;
+    }
+}
+
+// UDP host 候选对应的 TCP passive 候选，地址相同、端口为 ICE-TCP 端口；
+// 共享 mux 只绑定 IPv4，IPv6 候选不生成
+pub fn passive_candidate(candidate: &RTCIceCandidate, port: u16) -> Option<RTCIceCandidateInit> {
+    if candidate.typ != RTCIceCandidateType::Host
+        || candidate.protocol != RTCIceProtocol::Udp
+        || candidate.address.contains(':')
+    {
+        return None;
+    }
+    Some(RTCIceCandidateInit {
+        candidate: format!(
+            "candidate:t{} {} tcp {} {} {} typ host tcptype passive",
+            candidate.foundation,
+            candidate.component,
+            TCP_PASSIVE_PRIORITY + (256 - candidate.component as u32),
+            candidate.address,
+            port
+        ),
+        sdp_mid: Some(String::new()),
+        sdp_mline_index: Some(0),
+        username_fragment: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frame_roundtrip() {
+        let mut data = frame(b"stun");
+        data.extend(frame(b""));
+        let mut reader = &data[..];
+        let mut buf = Vec::new();
+        read_frame(&mut reader, &mut buf).await.unwrap();
+        assert_eq!(buf, b"stun");
+        read_frame(&mut reader, &mut buf).await.unwrap();
+        assert!(buf.is_empty());
+        assert!(read_frame(&mut reader, &mut buf).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_relay_tcp_to_udp() {
+        let agent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = serve(0, agent.local_addr().unwrap()).await.unwrap();
+        let mut client = TcpStream::connect(("127.0.0.1", addr.port()))
+            .await
+            .unwrap();
+
+        client.write_all(&frame(b"ping")).await.unwrap();
+        let mut buf = [0u8; 64];
+        let (n, from) = tokio::time::timeout(Duration::from_secs(1), agent.recv_from(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&buf[..n], b"ping");
+
+        agent.send_to(b"pong", from).await.unwrap();
+        let mut reply = Vec::new();
+        tokio::time::timeout(Duration::from_secs(1), read_frame(&mut client, &mut reply))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reply, b"pong");
+    }
+
+    #[test]
+    fn test_passive_candidate() {
+        let mut host = RTCIceCandidate {
+            foundation: "123".to_string(),
+            priority: 2130706431,
+            address: "203.0.113.10".to_string(),
+            protocol: RTCIceProtocol::Udp,
+            port: 3478,
+            typ: RTCIceCandidateType::Host,
+            component: 1,
+            ..Default::default()
+        };
+        let init = passive_candidate(&host, 3479).unwrap();
+        assert_eq!(
+            init.candidate,
+            "candidate:t123 1 tcp 2124414975 203.0.113.10 3479 typ host tcptype passive"
+        );
+        assert!(2124414975 < host.priority);
+
+        host.typ = RTCIceCandidateType::Srflx;
+        assert!(passive_candidate(&host, 3479).is_none());
+        host.typ = RTCIceCandidateType::Host;
+        host.address = "fe80::1".to_string();
+        assert!(passive_candidate(&host, 3479).is_none());
+    }
+}
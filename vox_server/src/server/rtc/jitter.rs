@@ -0,0 +1,342 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+
+// 到达时间偏离预期超过该值视为流重置，重新对齐时间基准
+const RESYNC_THRESHOLD: Duration = Duration::from_secs(1);
+// 缓冲包数上限，防止时间戳异常时无限增长
+const MAX_PACKETS: usize = 500;
+// 目标延迟取抖动估计的倍数
+const JITTER_MULTIPLIER: u32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct RtpPacket {
+    pub seq: u16,
+    pub timestamp: u32,
+    pub payload: Bytes,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct JitterStats {
+    pub received: u64,
+    pub released: u64,
+    pub reordered: u64, // 序号小于已收到的最大序号但仍赶上播放
+    pub duplicates: u64,
+    pub late: u64,     // 对应位置已播出，丢弃
+    pub skipped: u64,  // 等待超时未到的序号，交给解码器补偿
+    pub overflow: u64, // 超过最大延迟被提前放出的包
+    pub resyncs: u64,
+    pub delay_ms: u32,  // 当前目标延迟
+    pub jitter_ms: u32, // 到达间隔抖动估计（RFC 3550）
+}
+
+// 自适应抖动缓冲：按序号重排，按时间戳加目标延迟的时刻放出。
+// 不持有时钟，调用方传入当前时间，便于离线测试
+pub struct JitterBuffer {
+    min_delay: Duration,
+    max_delay: Duration,
+    clock_rate: u32,
+    delay: Duration,
+    packets: BTreeMap<u64, (Instant, RtpPacket)>,
+    // 下一个待放出的扩展序号
+    next_seq: Option<u64>,
+    highest_seq: Option<u64>,
+    // 时间基准：base_ts 的包在 base_at 到达时传输延迟最小
+    base: Option<(Instant, u32)>,
+    last_arrival: Option<(Instant, u32)>,
+    jitter: f64, // 秒
+    stats: JitterStats,
+}
+
+impl JitterBuffer {
+    pub fn new(target_delay: Duration, max_delay: Duration, clock_rate: u32) -> Self {
+        let min_delay = target_delay.min(max_delay);
+        Self {
+            min_delay,
+            max_delay,
+            clock_rate,
+            delay: min_delay,
+            packets: BTreeMap::new(),
+            next_seq: None,
+            highest_seq: None,
+            base: None,
+            last_arrival: None,
+            jitter: 0.0,
+            stats: JitterStats {
+                delay_ms: min_delay.as_millis() as u32,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn stats(&self) -> JitterStats {
+        self.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    // 16 位序号扩展为单调递增的 64 位序号
+    fn extend_seq(&self, seq: u16) -> u64 {
+        let Some(highest) = self.highest_seq else {
+            return seq as u64 + (1 << 16);
+        };
+        let diff = seq.wrapping_sub(highest as u16) as i16 as i64;
+        (highest as i64 + diff).max(0) as u64
+    }
+
+    fn expected_arrival(&self, timestamp: u32) -> Option<Instant> {
+        let (base_at, base_ts) = self.base?;
+        let ticks = timestamp.wrapping_sub(base_ts) as i32;
+        let offset = Duration::from_secs_f64(ticks.unsigned_abs() as f64 / self.clock_rate as f64);
+        if ticks >= 0 {
+            Some(base_at + offset)
+        } else {
+            base_at.checked_sub(offset)
+        }
+    }
+
+    fn update_jitter(&mut self, now: Instant, timestamp: u32) {
+        if let Some((last_at, last_ts)) = self.last_arrival {
+            let arrival = now.saturating_duration_since(last_at).as_secs_f64()
+                - last_at.saturating_duration_since(now).as_secs_f64();
+            let ticks = timestamp.wrapping_sub(last_ts) as i32;
+            let transit = ticks as f64 / self.clock_rate as f64;
+            let d = (arrival - transit).abs();
+            self.jitter += (d - self.jitter) / 16.0;
+        }
+        self.last_arrival = Some((now, timestamp));
+
+        let target = Duration::from_secs_f64(self.jitter) * JITTER_MULTIPLIER;
+        self.delay = target.clamp(self.min_delay, self.max_delay);
+        self.stats.delay_ms = self.delay.as_millis() as u32;
+        self.stats.jitter_ms = (self.jitter * 1000.0) as u32;
+    }
+
+    fn resync(&mut self, now: Instant, seq: u64, timestamp: u32) {
+        self.base = Some((now, timestamp));
+        self.next_seq = Some(seq);
+        self.highest_seq = Some(seq);
+        self.last_arrival = None;
+        self.stats.resyncs += 1;
+    }
+
+    pub fn push(&mut self, packet: RtpPacket, now: Instant) {
+        self.stats.received += 1;
+        let seq = self.extend_seq(packet.seq);
+
+        match self.expected_arrival(packet.timestamp) {
+            None => {
+                self.base = Some((now, packet.timestamp));
+                self.next_seq = Some(seq);
+            }
+            // 比预期早到，说明基准包本身有延迟，以传输最快的包为准
+            Some(expected) if now < expected => {
+                if expected - now > RESYNC_THRESHOLD {
+                    self.resync(now, seq, packet.timestamp);
+                } else {
+                    self.base = Some((now, packet.timestamp));
+                }
+            }
+            Some(expected) => {
+                if now - expected > RESYNC_THRESHOLD + self.max_delay {
+                    self.resync(now, seq, packet.timestamp);
+                }
+            }
+        }
+
+        if self.next_seq.is_some_and(|next| seq < next) {
+            self.stats.late += 1;
+            return;
+        }
+        if self.packets.contains_key(&seq) {
+            self.stats.duplicates += 1;
+            return;
+        }
+        if self.highest_seq.is_some_and(|highest| seq < highest) {
+            self.stats.reordered += 1;
+        } else {
+            self.highest_seq = Some(seq);
+        }
+        self.update_jitter(now, packet.timestamp);
+        self.packets.insert(seq, (now, packet));
+
+        // 超过容量时直接放弃最旧的包
+        while self.packets.len() > MAX_PACKETS {
+            if let Some((seq, _)) = self.packets.pop_first() {
+                self.next_seq = Some(seq + 1);
+                self.stats.overflow += 1;
+            }
+        }
+    }
+
+    // 队首包应放出的时刻
+    fn playout_at(&self, arrival: Instant, timestamp: u32) -> Instant {
+        let playout = self
+            .expected_arrival(timestamp)
+            .map(|expected| expected + self.delay)
+            .unwrap_or(arrival + self.delay);
+        // 在缓冲中停留不超过最大延迟
+        playout.min(arrival + self.max_delay)
+    }
+
+    // 下一次需要调用 pop 的时刻
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let (_, (arrival, packet)) = self.packets.first_key_value()?;
+        Some(self.playout_at(*arrival, packet.timestamp))
+    }
+
+    // 放出一个到期的包，期间缺失的序号计为跳过
+    pub fn pop(&mut self, now: Instant) -> Option<RtpPacket> {
+        let deadline = self.next_deadline()?;
+        if now < deadline {
+            return None;
+        }
+        let (seq, (arrival, packet)) = self.packets.pop_first()?;
+        // 因停留达到最大延迟而提前放出
+        let capped = self
+            .expected_arrival(packet.timestamp)
+            .is_some_and(|expected| expected + self.delay > arrival + self.max_delay);
+        if capped {
+            self.stats.overflow += 1;
+        }
+        if let Some(next) = self.next_seq {
+            self.stats.skipped += seq.saturating_sub(next);
+        }
+        self.next_seq = Some(seq + 1);
+        self.stats.released += 1;
+        Some(packet)
+    }
+
+    // 轨道结束时按序放出剩余的包
+    pub fn drain(&mut self) -> Vec<RtpPacket> {
+        let packets: Vec<RtpPacket> = std::mem::take(&mut self.packets)
+            .into_values()
+            .map(|(_, packet)| packet)
+            .collect();
+        self.stats.released += packets.len() as u64;
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn packet(seq: u16, timestamp: u32) -> RtpPacket {
+        RtpPacket {
+            seq,
+            timestamp,
+            payload: Bytes::from(vec![seq as u8]),
+        }
+    }
+
+    // 按 (到达时间 ms, 序号) 推入，每包 20ms，每 1ms 取一次，返回 (放出时间 ms, 序号)
+    fn run(buffer: &mut JitterBuffer, arrivals: &[(u64, u16)], until_ms: u64) -> Vec<(u64, u16)> {
+        let start = Instant::now();
+        let mut out = Vec::new();
+        let mut arrivals = arrivals.to_vec();
+        arrivals.sort_by_key(|(at, _)| *at);
+        let mut pending = arrivals.iter().peekable();
+        for t in 0..=until_ms {
+            let now = start + MS * t as u32;
+            while let Some((at, seq)) = pending.peek() {
+                if *at > t {
+                    break;
+                }
+                buffer.push(packet(*seq, *seq as u32 * 960), now);
+                pending.next();
+            }
+            while let Some(p) = buffer.pop(now) {
+                out.push((t, p.seq));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_reorders_within_delay() {
+        let mut buffer = JitterBuffer::new(MS * 60, MS * 200, 48000);
+        // 2 比 3 晚到 15ms
+        let arrivals = [(0, 1), (40, 3), (55, 2), (60, 4), (80, 5)];
+        let out = run(&mut buffer, &arrivals, 300);
+        let seqs: Vec<u16> = out.iter().map(|(_, seq)| *seq).collect();
+        assert_eq!(seqs, vec![1, 2, 3, 4, 5]);
+        // 按时间戳匀速放出：包 n 在 (n-1)*20 + 60ms
+        assert_eq!(out[0].0, 60);
+        assert_eq!(out[2].0, 100);
+
+        let stats = buffer.stats();
+        assert_eq!(stats.reordered, 1);
+        assert_eq!(stats.released, 5);
+        assert_eq!(stats.late, 0);
+    }
+
+    #[test]
+    fn test_late_and_duplicate_packets_dropped() {
+        let mut buffer = JitterBuffer::new(MS * 40, MS * 100, 48000);
+        // 3 在 2 播出之后才到，2 重复到达
+        let arrivals = [(0, 1), (20, 2), (20, 2), (40, 4), (130, 3), (140, 5)];
+        let out = run(&mut buffer, &arrivals, 400);
+        let seqs: Vec<u16> = out.iter().map(|(_, seq)| *seq).collect();
+        assert_eq!(seqs, vec![1, 2, 4, 5]);
+
+        let stats = buffer.stats();
+        assert_eq!(stats.duplicates, 1);
+        assert_eq!(stats.late, 1);
+        assert_eq!(stats.skipped, 1);
+    }
+
+    #[test]
+    fn test_sequence_wraparound() {
+        let mut buffer = JitterBuffer::new(MS * 40, MS * 100, 48000);
+        let start = Instant::now();
+        buffer.push(packet(65535, 0), start);
+        buffer.push(packet(1, 1920), start + MS * 5);
+        buffer.push(packet(0, 960), start + MS * 10);
+        let end = start + MS * 200;
+        let seqs: Vec<u16> = std::iter::from_fn(|| buffer.pop(end))
+            .map(|p| p.seq)
+            .collect();
+        assert_eq!(seqs, vec![65535, 0, 1]);
+    }
+
+    #[test]
+    fn test_adapts_delay_to_jitter() {
+        let mut buffer = JitterBuffer::new(MS * 20, MS * 200, 48000);
+        // 到达时间在 ±30ms 间交替抖动
+        let arrivals: Vec<(u64, u16)> = (1..=100u16)
+            .map(|seq| (seq as u64 * 20 + if seq % 2 == 0 { 30 } else { 0 }, seq))
+            .collect();
+        let out = run(&mut buffer, &arrivals, 3000);
+        assert_eq!(out.len(), 100);
+        assert!(out.windows(2).all(|w| w[0].1 + 1 == w[1].1));
+
+        let stats = buffer.stats();
+        assert!(stats.jitter_ms >= 20, "jitter {}", stats.jitter_ms);
+        assert!(stats.delay_ms > 20 && stats.delay_ms <= 200);
+        // 偶数包都晚于下一个奇数包到达，最后一个除外
+        assert_eq!(stats.reordered, 49);
+        assert_eq!(stats.skipped, 0);
+    }
+
+    #[test]
+    fn test_max_delay_bounds_hold_time() {
+        let mut buffer = JitterBuffer::new(MS * 40, MS * 80, 48000);
+        let start = Instant::now();
+        buffer.push(packet(1, 0), start);
+        // 时间戳跳到很远的未来时不会一直等待
+        buffer.push(packet(2, 48000 * 5), start + MS * 20);
+        assert_eq!(buffer.stats().resyncs, 1);
+        assert!(buffer.next_deadline().unwrap() <= start + MS * 100);
+        assert!(buffer.pop(start + MS * 200).is_some());
+    }
+}
@@ -0,0 +1,116 @@
+// 接收端按 RTP 序号与时间戳检测丢包，决定需要补偿的时长
+
+// 超过该间隔视为流重置（对端重启、长时间静音后恢复等），不做补偿
+const MAX_CONCEALED_PACKETS: u16 = 50;
+const MAX_CONCEALED_TICKS: u32 = 48000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqEvent {
+    First,
+    InOrder,
+    // lost 个包丢失，共 ticks 个 RTP 时钟周期
+    Gap { lost: u16, ticks: u32 },
+    // 乱序晚到或重复，对应时段已补偿过，直接丢弃
+    Late,
+    Reset,
+}
+
+#[derive(Debug, Default)]
+pub struct SeqTracker {
+    last: Option<(u16, u32)>,
+    // 上一个包覆盖的 RTP 时长，解码后更新
+    frame_ticks: u32,
+    lost: u64,
+    concealments: u64,
+    concealed_ticks: u64,
+}
+
+impl SeqTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_packet(&mut self, seq: u16, timestamp: u32) -> SeqEvent {
+        let Some((last_seq, last_ts)) = self.last else {
+            self.last = Some((seq, timestamp));
+            return SeqEvent::First;
+        };
+        let diff = seq.wrapping_sub(last_seq);
+        // 序号差超过一半视为回退
+        if diff == 0 || diff > u16::MAX / 2 {
+            return SeqEvent::Late;
+        }
+        self.last = Some((seq, timestamp));
+        if diff == 1 {
+            return SeqEvent::InOrder;
+        }
+
+        let lost = diff - 1;
+        let elapsed = timestamp.wrapping_sub(last_ts);
+        // 时间戳可信时用它计算缺失时长，否则按上一包的时长估算
+        let ticks = match elapsed.checked_sub(self.frame_ticks) {
+            Some(ticks) if ticks > 0 && elapsed < u32::MAX / 2 => ticks,
+            _ => lost as u32 * self.frame_ticks,
+        };
+        if lost > MAX_CONCEALED_PACKETS || ticks > MAX_CONCEALED_TICKS || ticks == 0 {
+            return SeqEvent::Reset;
+        }
+        self.lost += lost as u64;
+        self.concealments += 1;
+        self.concealed_ticks += ticks as u64;
+        SeqEvent::Gap { lost, ticks }
+    }
+
+    pub fn set_frame_ticks(&mut self, ticks: u32) {
+        self.frame_ticks = ticks;
+    }
+
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    pub fn concealments(&self) -> u64 {
+        self.concealments
+    }
+
+    pub fn concealed_ticks(&self) -> u64 {
+        self.concealed_ticks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_gaps() {
+        let mut tracker = SeqTracker::new();
+        assert_eq!(tracker.on_packet(65534, 1000), SeqEvent::First);
+        tracker.set_frame_ticks(960);
+        assert_eq!(tracker.on_packet(65535, 1960), SeqEvent::InOrder);
+
+        // 跨越序号回绕丢两个包
+        assert_eq!(
+            tracker.on_packet(2, 1960 + 960 * 3),
+            SeqEvent::Gap { lost: 2, ticks: 1920 }
+        );
+        // 丢掉的包晚到
+        assert_eq!(tracker.on_packet(1, 1960 + 960 * 2), SeqEvent::Late);
+        assert_eq!(tracker.on_packet(2, 1960 + 960 * 3), SeqEvent::Late);
+        assert_eq!(tracker.lost(), 2);
+        assert_eq!(tracker.concealments(), 1);
+        assert_eq!(tracker.concealed_ticks(), 1920);
+    }
+
+    #[test]
+    fn test_timestamp_fallback_and_reset() {
+        let mut tracker = SeqTracker::new();
+        tracker.on_packet(10, 0);
+        tracker.set_frame_ticks(960);
+        // 时间戳未前进时按帧长估算
+        assert_eq!(tracker.on_packet(12, 0), SeqEvent::Gap { lost: 1, ticks: 960 });
+        // 间隔过大不补偿
+        assert_eq!(tracker.on_packet(200, 960 * 190), SeqEvent::Reset);
+        assert_eq!(tracker.on_packet(201, 960 * 191), SeqEvent::InOrder);
+    }
+}
@@ -1,20 +1,28 @@
+pub mod audio_out;
 pub mod en_decoder;
+pub mod error;
+pub mod factory;
+pub mod ice_tcp;
+pub mod jitter;
+pub mod loss;
+pub mod network;
+pub mod quality;
 pub mod rtc_client;
 pub mod rtc_delegate;
 pub mod traits;
+pub mod trickle;
 
 use std::sync::Arc;
 use traits::WebRTCHandler;
 
 use webrtc::api::interceptor_registry::register_default_interceptors;
-use webrtc::api::media_engine::{self, MediaEngine, MIME_TYPE_OPUS};
 use webrtc::api::{APIBuilder, API};
 use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
 use webrtc::interceptor::registry::Registry;
 use webrtc::peer_connection::configuration::RTCConfiguration;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::RTCPeerConnection;
-use webrtc::rtp_transceiver::rtp_codec::{RTCRtpCodecCapability, RTPCodecType};
+use webrtc::rtp_transceiver::rtp_codec::RTPCodecType;
 use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
 use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
 use webrtc::track::track_remote::TrackRemote;
@@ -0,0 +1,160 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use tokio::net::UdpSocket;
+use tokio::sync::OnceCell;
+use webrtc::api::setting_engine::SettingEngine;
+use webrtc::ice::network_type::NetworkType;
+use webrtc::ice::udp_mux::{UDPMux, UDPMuxDefault, UDPMuxParams};
+use webrtc::ice::udp_network::{EphemeralUDP, UDPNetwork};
+use webrtc::ice_transport::ice_candidate_type::RTCIceCandidateType;
+use webrtc::ice_transport::ice_server::RTCIceServer;
+
+use super::ice_tcp;
+use crate::config::RtcConfig;
+use crate::info;
+
+// 进程内共享的 UDP mux，首次使用时绑定，之后端口不再随配置变化
+static UDP_MUX: OnceCell<Arc<UDPMuxDefault>> = OnceCell::const_new();
+// ICE-TCP passive 监听，与 UDP mux 一样只启动一次
+static ICE_TCP: OnceCell<SocketAddr> = OnceCell::const_new();
+
+fn millis(ms: Option<u64>) -> Option<Duration> {
+    ms.map(Duration::from_millis)
+}
+
+pub fn parse_network_type(name: &str) -> Result<NetworkType> {
+    match name {
+        "udp4" => Ok(NetworkType::Udp4),
+        "udp6" => Ok(NetworkType::Udp6),
+        // webrtc-rs 的 ICE agent 只有 UDP，ICE-TCP passive 通过 ice_tcp_port 桥接到 UDP mux
+        "tcp4" | "tcp6" => Err(anyhow!(
+            "network type {} is not supported by the ICE agent, use ice_tcp_port for ICE-TCP passive",
+            name
+        )),
+        other => Err(anyhow!("unknown network type {}", other)),
+    }
+}
+
+pub async fn bind_udp_mux(port: u16) -> Result<Arc<UDPMuxDefault>> {
+    let socket = UdpSocket::bind(("0.0.0.0", port)).await?;
+    info!("rtc udp mux listening on {}", socket.local_addr()?);
+    Ok(UDPMuxDefault::new(UDPMuxParams::new(socket)))
+}
+
+async fn shared_udp_mux(port: u16) -> Result<Arc<UDPMuxDefault>> {
+    let mux = UDP_MUX.get_or_try_init(|| bind_udp_mux(port)).await?;
+    Ok(mux.clone())
+}
+
+async fn shared_ice_tcp(port: u16, udp_mux_port: u16) -> Result<SocketAddr> {
+    let target = SocketAddr::from((Ipv4Addr::LOCALHOST, udp_mux_port));
+    let addr = ICE_TCP
+        .get_or_try_init(|| ice_tcp::serve(port, target))
+        .await?;
+    Ok(*addr)
+}
+
+// bot 创建 PeerConnection 时使用，配置了 udp_mux_port 时接入共享 mux，
+// 配置了 ice_tcp_port 时同时启动转发到该 mux 的 ICE-TCP 监听
+pub async fn setting_engine_for(cfg: &RtcConfig) -> Result<SettingEngine> {
+    let udp_mux: Option<Arc<dyn UDPMux + Send + Sync>> = if cfg.udp_mux_port != 0 {
+        Some(shared_udp_mux(cfg.udp_mux_port).await?)
+    } else {
+        None
+    };
+    if cfg.ice_tcp_port != 0 && cfg.udp_mux_port != 0 {
+        shared_ice_tcp(cfg.ice_tcp_port, cfg.udp_mux_port).await?;
+    }
+    build_setting_engine(cfg, udp_mux)
+}
+
+// 按配置生成 SettingEngine，每个 bot 的 PeerConnection 共用同一套网络设置
+pub fn build_setting_engine(
+    cfg: &RtcConfig,
+    udp_mux: Option<Arc<dyn UDPMux + Send + Sync>>,
+) -> Result<SettingEngine> {
+    let mut se = SettingEngine::default();
+
+    se.set_lite(cfg.ice_lite);
+    se.set_ice_timeouts(
+        millis(cfg.ice_disconnected_timeout_ms),
+        millis(cfg.ice_failed_timeout_ms),
+        millis(cfg.ice_keepalive_interval_ms),
+    );
+    se.set_host_acceptance_min_wait(millis(cfg.host_acceptance_min_wait_ms));
+    se.set_srflx_acceptance_min_wait(millis(cfg.srflx_acceptance_min_wait_ms));
+    se.set_relay_acceptance_min_wait(millis(cfg.relay_acceptance_min_wait_ms));
+
+    if !cfg.network_types.is_empty() {
+        let types = cfg
+            .network_types
+            .iter()
+            .map(|name| parse_network_type(name))
+            .collect::<Result<Vec<_>>>()?;
+        se.set_network_types(types);
+    }
+
+    if let Some(mux) = udp_mux {
+        // 共享 mux 只绑定 IPv4，所有本地候选共用同一个连接，
+        // IPv6 候选读到 IPv4 的包会按网络类型丢弃，导致 DTLS 握手卡住
+        se.set_network_types(vec![NetworkType::Udp4]);
+        se.set_udp_network(UDPNetwork::Muxed(mux));
+    } else if cfg.udp_port_min != 0 && cfg.udp_port_max != 0 {
+        let ephemeral = EphemeralUDP::new(cfg.udp_port_min, cfg.udp_port_max)?;
+        se.set_udp_network(UDPNetwork::Ephemeral(ephemeral));
+    }
+
+    if !cfg.nat_1to1_ips.is_empty() {
+        let candidate_type = match cfg.nat_1to1_candidate_type.as_str() {
+            "srflx" => RTCIceCandidateType::Srflx,
+            _ => RTCIceCandidateType::Host,
+        };
+        se.set_nat_1to1_ips(cfg.nat_1to1_ips.clone(), candidate_type);
+    }
+
+    Ok(se)
+}
+
+pub fn ice_servers(cfg: &RtcConfig) -> Vec<RTCIceServer> {
+    cfg.ice_servers
+        .iter()
+        .map(|server| RTCIceServer {
+            urls: server.urls.clone(),
+            username: server.username.clone(),
+            credential: server.credential.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IceServerConfig;
+
+    #[test]
+    fn test_build_from_config() {
+        let mut cfg = RtcConfig::default();
+        cfg.ice_servers.push(IceServerConfig {
+            urls: vec!["turn:turn.example.com:3478".to_string()],
+            username: "vox".to_string(),
+            credential: "secret".to_string(),
+        });
+        cfg.udp_port_min = 50000;
+        cfg.udp_port_max = 50100;
+        cfg.network_types = vec!["udp4".to_string(), "udp6".to_string()];
+        cfg.nat_1to1_ips = vec!["203.0.113.10".to_string()];
+        assert!(build_setting_engine(&cfg, None).is_ok());
+
+        let servers = ice_servers(&cfg);
+        assert_eq!(servers.len(), 1);
+        assert_eq!(servers[0].username, "vox");
+
+        cfg.network_types = vec!["sctp".to_string()];
+        assert!(build_setting_engine(&cfg, None).is_err());
+        cfg.network_types = vec!["tcp4".to_string()];
+        assert!(build_setting_engine(&cfg, None).is_err());
+    }
+}
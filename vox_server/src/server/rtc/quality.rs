@@ -0,0 +1,321 @@
+// 会话的媒体质量统计：接收任务更新丢包与补偿，RTCP 读取任务更新对端接收报告，
+// 定时任务从 get_stats 更新收发计数、码率与 RTT
+use std::fmt;
+use std::time::Instant;
+
+use webrtc::rtcp::packet::Packet;
+use webrtc::rtcp::receiver_report::ReceiverReport;
+use webrtc::rtcp::reception_report::ReceptionReport;
+use webrtc::rtcp::sender_report::SenderReport;
+use webrtc::rtcp::transport_feedbacks::transport_layer_nack::TransportLayerNack;
+use webrtc::stats::{StatsReport, StatsReportType};
+
+use super::jitter::JitterStats;
+
+// 由相邻两次采样的累计字节数计算码率
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RateMeter {
+    last: Option<(u64, Instant)>,
+}
+
+impl RateMeter {
+    pub fn update(&mut self, bytes: u64, now: Instant) -> Option<u64> {
+        let last = self.last.replace((bytes, now));
+        let (last_bytes, last_time) = last?;
+        let elapsed = now.duration_since(last_time).as_secs_f64();
+        if elapsed <= 0.0 || bytes < last_bytes {
+            return None;
+        }
+        Some(((bytes - last_bytes) as f64 * 8.0 / elapsed) as u64)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct QualityStats {
+    // 远端 -> bot
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    pub bitrate_in_bps: u64,
+    pub packets_lost: u64,
+    pub concealments: u64, // 解码器补偿次数
+    pub concealed_ms: u64,
+    pub nacks_sent: u64,
+    pub jitter: JitterStats,
+    // bot -> 远端
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub bitrate_out_bps: u64,
+    pub nacks_received: u64, // 对端请求重传的包数
+    // 对端的接收报告
+    pub remote_fraction_lost: f64,
+    pub remote_packets_lost: u64,
+    pub remote_jitter_ms: f64,
+    pub rtt_ms: Option<f64>,
+
+    rx_rate: RateMeter,
+    tx_rate: RateMeter,
+}
+
+impl QualityStats {
+    // fraction_lost 为 8 位定点小数，jitter 单位为 RTP 时钟
+    pub fn on_reception_report(
+        &mut self,
+        fraction_lost: u8,
+        total_lost: u32,
+        jitter: u32,
+        clock_rate: u32,
+    ) {
+        self.remote_fraction_lost = fraction_lost as f64 / 256.0;
+        self.remote_packets_lost = total_lost as u64;
+        self.remote_jitter_ms = jitter as f64 * 1000.0 / clock_rate as f64;
+    }
+
+    // 本地发送轨道收到的 RTCP
+    pub fn on_rtcp(&mut self, packet: &(dyn Packet + Send + Sync), clock_rate: u32) {
+        let any = packet.as_any();
+        let reports: &[ReceptionReport] = if let Some(rr) = any.downcast_ref::<ReceiverReport>() {
+            &rr.reports
+        } else if let Some(sr) = any.downcast_ref::<SenderReport>() {
+            &sr.reports
+        } else {
+            if let Some(nack) = any.downcast_ref::<TransportLayerNack>() {
+                self.nacks_received += nack
+                    .nacks
+                    .iter()
+                    .map(|pair| pair.packet_list().len() as u64)
+                    .sum::<u64>();
+            }
+            return;
+        };
+        for report in reports {
+            self.on_reception_report(
+                report.fraction_lost,
+                report.total_lost,
+                report.jitter,
+                clock_rate,
+            );
+        }
+    }
+
+    // 只统计音频流；RTT 优先取 RTCP 计算的值，没有时用 ICE 连通性检查的值
+    pub fn on_stats_report(&mut self, report: &StatsReport, now: Instant) {
+        let mut rtcp_rtt = None;
+        let mut ice_rtt = None;
+        for stats in report.reports.values() {
+            match stats {
+                StatsReportType::InboundRTP(inbound) if inbound.kind == "audio" => {
+                    self.packets_received = inbound.packets_received;
+                    self.bytes_received = inbound.bytes_received;
+                    self.nacks_sent = inbound.nack_count;
+                }
+                StatsReportType::OutboundRTP(outbound) if outbound.kind == "audio" => {
+                    self.packets_sent = outbound.packets_sent;
+                    self.bytes_sent = outbound.bytes_sent;
+                }
+                StatsReportType::RemoteInboundRTP(remote) if remote.kind == "audio" => {
+                    rtcp_rtt = remote.round_trip_time.or(rtcp_rtt);
+                }
+                StatsReportType::CandidatePair(pair)
+                    if pair.nominated && pair.current_round_trip_time > 0.0 =>
+                {
+                    ice_rtt = Some(pair.current_round_trip_time);
+                }
+                _ => {}
+            }
+        }
+        if let Some(rtt) = rtcp_rtt.or(ice_rtt) {
+            self.rtt_ms = Some(rtt * 1000.0);
+        }
+        if let Some(bps) = self.rx_rate.update(self.bytes_received, now) {
+            self.bitrate_in_bps = bps;
+        }
+        if let Some(bps) = self.tx_rate.update(self.bytes_sent, now) {
+            self.bitrate_out_bps = bps;
+        }
+    }
+}
+
+impl fmt::Display for QualityStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rx {} pkts {}kbps lost {} concealed {}x/{}ms jitter {}ms nack {}; \
+             tx {} pkts {}kbps nack {}; remote loss {:.1}% jitter {:.1}ms; rtt ",
+            self.packets_received,
+            self.bitrate_in_bps / 1000,
+            self.packets_lost,
+            self.concealments,
+            self.concealed_ms,
+            self.jitter.jitter_ms,
+            self.nacks_sent,
+            self.packets_sent,
+            self.bitrate_out_bps / 1000,
+            self.nacks_received,
+            self.remote_fraction_lost * 100.0,
+            self.remote_jitter_ms,
+        )?;
+        match self.rtt_ms {
+            Some(rtt) => write!(f, "{:.0}ms", rtt),
+            None => write!(f, "n/a"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use webrtc::ice::agent::agent_stats::CandidatePairStats;
+    use webrtc::rtcp::transport_feedbacks::transport_layer_nack::NackPair;
+    use webrtc::stats::{
+        ICECandidatePairStats, InboundRTPStats, OutboundRTPStats, RTCStatsType,
+        RemoteInboundRTPStats,
+    };
+
+    // 一次 get_stats 的结果：音频收发各一路、已提名的候选对，可选对端的接收报告
+    fn stats_report(bytes_received: u64, bytes_sent: u64, rtcp_rtt: Option<f64>) -> StatsReport {
+        let now = tokio::time::Instant::now();
+        let mut reports = HashMap::new();
+        reports.insert(
+            "inbound".to_string(),
+            StatsReportType::InboundRTP(InboundRTPStats {
+                timestamp: now,
+                stats_type: RTCStatsType::InboundRTP,
+                id: "inbound".to_string(),
+                ssrc: 1,
+                kind: "audio".to_string(),
+                packets_received: bytes_received / 100,
+                track_identifier: "remote".to_string(),
+                mid: "0".into(),
+                last_packet_received_timestamp: None,
+                header_bytes_received: 0,
+                bytes_received,
+                nack_count: 2,
+                fir_count: None,
+                pli_count: None,
+            }),
+        );
+        reports.insert(
+            "outbound".to_string(),
+            StatsReportType::OutboundRTP(OutboundRTPStats {
+                timestamp: now,
+                stats_type: RTCStatsType::OutboundRTP,
+                id: "outbound".to_string(),
+                ssrc: 2,
+                kind: "audio".to_string(),
+                packets_sent: bytes_sent / 100,
+                bytes_sent,
+                track_identifier: "audio".to_string(),
+                mid: "0".into(),
+                rid: None,
+                header_bytes_sent: 0,
+                nack_count: 0,
+                fir_count: None,
+                pli_count: None,
+            }),
+        );
+        if rtcp_rtt.is_some() {
+            reports.insert(
+                "remote-inbound".to_string(),
+                StatsReportType::RemoteInboundRTP(RemoteInboundRTPStats {
+                    timestamp: now,
+                    stats_type: RTCStatsType::RemoteInboundRTP,
+                    id: "remote-inbound".to_string(),
+                    ssrc: 2,
+                    kind: "audio".to_string(),
+                    packets_received: bytes_sent / 100,
+                    packets_lost: 0,
+                    local_id: "outbound".to_string(),
+                    round_trip_time: rtcp_rtt,
+                    total_round_trip_time: rtcp_rtt.unwrap_or_default(),
+                    fraction_lost: 0.0,
+                    round_trip_time_measurements: 1,
+                }),
+            );
+        }
+        reports.insert(
+            "pair".to_string(),
+            StatsReportType::CandidatePair(ICECandidatePairStats::from(CandidatePairStats {
+                nominated: true,
+                current_round_trip_time: 0.25,
+                ..Default::default()
+            })),
+        );
+        StatsReport { reports }
+    }
+
+    #[test]
+    fn test_rate_meter() {
+        let start = Instant::now();
+        let mut meter = RateMeter::default();
+        assert_eq!(meter.update(0, start), None);
+        assert_eq!(
+            meter.update(4000, start + Duration::from_secs(1)),
+            Some(32000)
+        );
+        assert_eq!(
+            meter.update(6000, start + Duration::from_secs(2)),
+            Some(16000)
+        );
+        // 计数回退（流重建）时不给出码率
+        assert_eq!(meter.update(100, start + Duration::from_secs(3)), None);
+    }
+
+    #[test]
+    fn test_reception_report_and_nack() {
+        let mut stats = QualityStats::default();
+        stats.on_reception_report(64, 12, 480, 48000);
+        assert_eq!(stats.remote_fraction_lost, 0.25);
+        assert_eq!(stats.remote_packets_lost, 12);
+        assert_eq!(stats.remote_jitter_ms, 10.0);
+
+        let rr = ReceiverReport {
+            reports: vec![ReceptionReport {
+                fraction_lost: 0,
+                total_lost: 13,
+                jitter: 960,
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        stats.on_rtcp(&rr, 48000);
+        assert_eq!(stats.remote_packets_lost, 13);
+        assert_eq!(stats.remote_jitter_ms, 20.0);
+
+        let nack = TransportLayerNack {
+            nacks: vec![NackPair {
+                packet_id: 100,
+                lost_packets: 0b101,
+            }],
+            ..Default::default()
+        };
+        stats.on_rtcp(&nack, 48000);
+        assert_eq!(stats.nacks_received, 3);
+        assert!(stats.to_string().ends_with("rtt n/a"));
+    }
+
+    #[test]
+    fn test_stats_report() {
+        let start = Instant::now();
+        let mut stats = QualityStats::default();
+        stats.on_stats_report(&stats_report(1000, 2000, Some(0.125)), start);
+        assert_eq!(stats.packets_received, 10);
+        assert_eq!(stats.bytes_received, 1000);
+        assert_eq!(stats.nacks_sent, 2);
+        assert_eq!(stats.packets_sent, 20);
+        assert_eq!(stats.bytes_sent, 2000);
+        // RTCP 计算的 RTT 优先于 ICE 的
+        assert_eq!(stats.rtt_ms, Some(125.0));
+        // 首次采样还没有码率
+        assert_eq!(stats.bitrate_in_bps, 0);
+        assert_eq!(stats.bitrate_out_bps, 0);
+
+        let later = start + Duration::from_secs(2);
+        stats.on_stats_report(&stats_report(5000, 4000, None), later);
+        assert_eq!(stats.bitrate_in_bps, 16000);
+        assert_eq!(stats.bitrate_out_bps, 8000);
+        // 没有对端接收报告时退回 ICE 的 RTT
+        assert_eq!(stats.rtt_ms, Some(250.0));
+    }
+}
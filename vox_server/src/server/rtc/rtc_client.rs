@@ -1,24 +1,43 @@
-use byteorder::{LittleEndian, ReadBytesExt};
-use bytes::Bytes;
 use en_decoder::{CodecType, VoxDecoder};
-use ogg::PacketReader;
 use std::{
-    fs::File,
-    io::{BufReader, Read as _},
-    sync::{Arc, Mutex},
-    time::Duration,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use slog::Logger;
+use tokio::{
+    sync::{mpsc, watch},
+    task::AbortHandle,
 };
-use tokio::sync::mpsc;
 use webrtc::{
-    data_channel::RTCDataChannel,
     ice_transport::ice_candidate::RTCIceCandidate,
     interceptor::report::receiver,
-    media::{audio::buffer::info, Sample},
-    peer_connection::sdp::session_description::RTCSessionDescription,
+    media::audio::buffer::info,
+    peer_connection::{
+        offer_answer_options::RTCOfferOptions,
+        sdp::{sdp_type::RTCSdpType, session_description::RTCSessionDescription},
+        signaling_state::RTCSignalingState,
+    },
+    rtp_transceiver::rtp_transceiver_direction::RTCRtpTransceiverDirection,
 };
 
 use crate::{
+    config::RtcConfig,
+    server::rtc::{
+        audio_out,
+        error::{RtcError, RtcResult},
+        factory::{self, RtcFactory},
+        ice_tcp,
+        jitter::{JitterBuffer, JitterStats, RtpPacket},
+        loss::{SeqEvent, SeqTracker},
+        quality::QualityStats,
+        trickle::{self, PendingCandidates, RemoteCandidate},
+    },
     msg_center::signaling_msgs::SignalingMessage,
+    prompt,
+    utils::audio::AudioConverter,
     server::{
         data,
         signal_cli::{
@@ -28,174 +47,368 @@ use crate::{
     },
 };
 
-use super::{en_decoder::create_encoder, *};
+use super::*;
+
+// 连接状态变化，由 Bot 决定是否结束会话
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcEvent {
+    PeerState(RTCPeerConnectionState),
+    IceState(RTCIceConnectionState),
+}
 
 pub struct RTCClient {
+    cfg: RtcConfig,
     peer_connection: Arc<RTCPeerConnection>,
-    api: API,
+    factory: Arc<RtcFactory>,
     track_id: String,
     rtp_sender: Option<Arc<RTCRtpSender>>,
     audio_track: Option<Arc<TrackLocalStaticSample>>,
+    // 本地轨道编码的 RTP 时钟，换轨时更新，RTCP 读取任务据此换算接收报告的抖动
+    send_clock_rate: Arc<AtomicU32>,
     remote_audio_tx: Option<mpsc::Sender<Vec<i16>>>,
+    // 处理模块需要的采样率，远端音频解码后转换为该采样率的单声道
+    remote_audio_rate: u32,
     local_audio_rx: Option<mpsc::Receiver<data::AudioData>>,
+    local_prompt_rx: Option<mpsc::Receiver<prompt::Frame>>,
     ws_tx: mpsc::Sender<SignalingMessage>,
     client_id: String,
     bot_id: String,
-    cached_candidates: Arc<Mutex<Vec<RTCIceCandidate>>>,
-    data_channel: Arc<RTCDataChannel>,
+    corr_id: String,
+    log: Logger,
+    // 本端候选按收集顺序进入队列，本轮协商的 Offer/Answer 发出后由转发任务依次发送，
+    // None 表示收集完毕
+    local_candidate_tx: mpsc::UnboundedSender<Option<RTCIceCandidate>>,
+    local_candidate_rx: Option<mpsc::UnboundedReceiver<Option<RTCIceCandidate>>>,
+    // 每轮协商设置本端描述前关闭，描述发给客户端后打开，候选不会先于描述到达
+    description_sent: Arc<watch::Sender<bool>>,
+    // 远端描述设置前收到的远端候选
+    pending_remote_candidates: PendingCandidates,
+    event_tx: mpsc::UnboundedSender<RtcEvent>,
+    event_rx: Option<mpsc::UnboundedReceiver<RtcEvent>>,
+    // 最近一次收到远端音频的时间
+    media_tx: Arc<watch::Sender<Option<Instant>>>,
+    media_rx: Option<watch::Receiver<Option<Instant>>>,
+    // 媒体质量统计，由接收任务、RTCP 读取任务和定时采集任务分别更新
+    quality: Arc<std::sync::Mutex<QualityStats>>,
+    // 本连接启动的后台任务，关闭时统一取消
+    tasks: Vec<AbortHandle>,
 }
 
 impl RTCClient {
     pub async fn new(
+        factory: Arc<RtcFactory>,
+        mut cfg: RtcConfig,
         client_id: String,
         bot_id: String,
+        corr_id: String,
+        log: Logger,
         ws_tx: mpsc::Sender<SignalingMessage>,
     ) -> Result<Self> {
-        let mut registry = Registry::new();
-        let mut media_engine = MediaEngine::default();
-        media_engine.register_default_codecs()?;
-        registry = register_default_interceptors(registry, &mut media_engine)?;
-        let api = APIBuilder::new()
-            .with_media_engine(media_engine)
-            .with_interceptor_registry(registry)
-            .build();
-        let config = RTCConfiguration::default();
-        let peer_connection = Arc::new(api.new_peer_connection(config).await?);
-        let data_channel = peer_connection
-            .create_data_channel("audio-file", None)
-            .await?;
+        let warm = factory.acquire().await?;
+        let peer_connection = warm.peer_connection;
+        // 本地轨道的编码以创建该连接的 API 为准，配置热更新期间两者可能不同
+        cfg.codecs = warm.codecs;
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let (media_tx, media_rx) = watch::channel(None);
+        let (local_candidate_tx, local_candidate_rx) = mpsc::unbounded_channel();
+        let (description_sent, _) = watch::channel(false);
 
         let mut client = Self {
+            cfg,
             peer_connection,
-            api,
+            factory,
             track_id: uuid::Uuid::new_v4().to_string(),
             rtp_sender: None,
             remote_audio_tx: None,
+            remote_audio_rate: 16000,
             ws_tx,
             client_id,
             bot_id,
-            cached_candidates: Arc::new(Mutex::new(Vec::new())),
+            corr_id,
+            log,
+            local_candidate_tx,
+            local_candidate_rx: Some(local_candidate_rx),
+            description_sent: Arc::new(description_sent),
+            pending_remote_candidates: PendingCandidates::default(),
             audio_track: None,
+            send_clock_rate: Arc::new(AtomicU32::new(0)),
             local_audio_rx: None,
-            data_channel,
+            local_prompt_rx: None,
+            event_tx,
+            event_rx: Some(event_rx),
+            media_tx: Arc::new(media_tx),
+            media_rx: Some(media_rx),
+            quality: Arc::new(std::sync::Mutex::new(QualityStats::default())),
+            tasks: Vec::new(),
         };
         Ok(client)
     }
 
+    pub fn take_event_rx(&mut self) -> Option<mpsc::UnboundedReceiver<RtcEvent>> {
+        self.event_rx.take()
+    }
+
+    pub fn jitter_stats(&self) -> JitterStats {
+        self.quality.lock().unwrap().jitter
+    }
+
+    pub fn quality(&self) -> QualityStats {
+        *self.quality.lock().unwrap()
+    }
+
+    // 质量统计的共享句柄，供 bot 任务之外随时读取快照
+    pub fn quality_handle(&self) -> Arc<std::sync::Mutex<QualityStats>> {
+        Arc::clone(&self.quality)
+    }
+
+    // 立即采集一次 get_stats，会话结束前用于生成最终报告
+    pub async fn refresh_quality(&self) {
+        Self::collect_quality(&self.peer_connection, &self.quality).await;
+    }
+
+    async fn collect_quality(
+        peer_connection: &RTCPeerConnection,
+        quality: &std::sync::Mutex<QualityStats>,
+    ) {
+        let report = peer_connection.get_stats().await;
+        quality
+            .lock()
+            .unwrap()
+            .on_stats_report(&report, Instant::now());
+    }
+
+    pub fn take_media_rx(&mut self) -> Option<watch::Receiver<Option<Instant>>> {
+        self.media_rx.take()
+    }
+
+    // 取消后台任务并关闭 PeerConnection，可重复调用
+    pub async fn close(&mut self) {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+        if self.peer_connection.connection_state() == RTCPeerConnectionState::Closed {
+            return;
+        }
+        if let Err(e) = self.peer_connection.close().await {
+            warn!(log: self.log, "close peer connection failed: {}", e);
+        }
+    }
+
+    // bot 主动发起协商，ice_restart 为 true 时生成新的 ICE 凭据
+    pub async fn send_offer(&mut self, ice_restart: bool) -> RtcResult<()> {
+        let options = RTCOfferOptions {
+            ice_restart,
+            ..Default::default()
+        };
+        let offer = self.peer_connection.create_offer(Some(options)).await?;
+        self.description_sent.send_replace(false);
+        self.peer_connection.set_local_description(offer).await?;
+        let local_description = self
+            .peer_connection
+            .local_description()
+            .await
+            .ok_or(RtcError::NotReady("local description"))?;
+        info!(log: self.log, "Bot sending offer, ice_restart: {}", ice_restart);
+        self.ws_tx
+            .send(SignalingMessage::Offer {
+                from: self.bot_id.clone(),
+                to: self.client_id.clone(),
+                sdp: serde_json::to_string(&local_description)
+                    .map_err(|e| RtcError::Internal(e.into()))?,
+                corr_id: Some(self.corr_id.clone()),
+            })
+            .await?;
+        // Offer 已发出，继续转发本端候选
+        self.description_sent.send_replace(true);
+        Ok(())
+    }
+
+    // 客户端对 bot 发起的 offer 的应答
+    pub async fn handle_answer(&mut self, answer_sdp: String) -> RtcResult<()> {
+        if self.peer_connection.signaling_state() != RTCSignalingState::HaveLocalOffer {
+            return Err(RtcError::UnexpectedState(format!(
+                "answer in signaling state {}",
+                self.peer_connection.signaling_state()
+            )));
+        }
+        let answer = Self::parse_description(&answer_sdp)?;
+        self.peer_connection
+            .set_remote_description(answer)
+            .await
+            .map_err(|e| RtcError::InvalidSdp(e.to_string()))?;
+        self.add_pending_remote_candidates().await;
+        self.start_audio_sender().await
+    }
+
+    // 通话中停止下行音频并重协商，Bot 不需要重建
+    pub async fn remove_local_audio(&mut self) -> RtcResult<()> {
+        self.set_local_audio_direction(RTCRtpTransceiverDirection::Recvonly)
+            .await
+    }
+
+    // 恢复下行音频并重协商，沿用原有的轨道和发送任务
+    pub async fn restore_local_audio(&mut self) -> RtcResult<()> {
+        self.set_local_audio_direction(RTCRtpTransceiverDirection::Sendrecv)
+            .await
+    }
+
+    // webrtc-rs 不能把 remove_track 移除的轨道加回原 transceiver，这里只修改方向，
+    // 协商完成后发送端按方向暂停或恢复
+    async fn set_local_audio_direction(
+        &mut self,
+        direction: RTCRtpTransceiverDirection,
+    ) -> RtcResult<()> {
+        let Some(sender) = self.rtp_sender.clone() else {
+            return Ok(());
+        };
+        for transceiver in self.peer_connection.get_transceivers().await {
+            if !Arc::ptr_eq(&transceiver.sender().await, &sender) {
+                continue;
+            }
+            if transceiver.direction() == direction {
+                return Ok(());
+            }
+            transceiver.set_direction(direction).await;
+            return self.send_offer(false).await;
+        }
+        Ok(())
+    }
+
     pub fn set_local_audio_rx(&mut self, local_audio_rx: mpsc::Receiver<data::AudioData>) {
         self.local_audio_rx = Some(local_audio_rx);
     }
 
-    pub async fn handle_offer(&mut self, offer_sdp: String) -> Result<String> {
+    pub fn set_local_prompt_rx(&mut self, local_prompt_rx: mpsc::Receiver<prompt::Frame>) {
+        self.local_prompt_rx = Some(local_prompt_rx);
+    }
+
+    // 首次协商与重协商（含客户端发起的 ICE restart）共用
+    pub async fn handle_offer(&mut self, offer_sdp: String) -> RtcResult<String> {
+        let started = Instant::now();
         // 设置远程描述(Offer)
-        let offer = match serde_json::from_str::<RTCSessionDescription>(&offer_sdp) {
-            Ok(s) => s,
-            Err(err) => panic!("{}", err),
-        };
+        let offer = Self::parse_description(&offer_sdp)?;
 
-        self.peer_connection.set_remote_description(offer).await?;
-        debug!("Bot set remote description ok");
+        // 与本端发起的 offer 冲突时 bot 让步，回滚本端 offer
+        if self.peer_connection.signaling_state() == RTCSignalingState::HaveLocalOffer {
+            warn!(log: self.log, "offer glare, rolling back local offer");
+            // parsed 字段私有，只能从默认值修改类型
+            let mut rollback = RTCSessionDescription::default();
+            rollback.sdp_type = RTCSdpType::Rollback;
+            self.peer_connection.set_local_description(rollback).await?;
+        }
+
+        self.peer_connection
+            .set_remote_description(offer)
+            .await
+            .map_err(|e| RtcError::InvalidSdp(e.to_string()))?;
+        debug!(log: self.log, "Bot set remote description ok");
+        self.add_pending_remote_candidates().await;
+        self.start_audio_sender().await?;
 
         //let mut gather_complete = self.peer_connection.gathering_complete_promise().await;
 
         // 创建Answer
         let answer = self.peer_connection.create_answer(None).await?;
-        info!("Bot creating answer, {:?}", answer);
+        info!(log: self.log, "Bot creating answer, {:?}", answer);
+        self.description_sent.send_replace(false);
         self.peer_connection
             .set_local_description(answer.clone())
             .await?;
 
         //let _ = gather_complete.recv().await;
 
-        let local_description = self.peer_connection.local_description().await.unwrap();
-        info!("Bot setting local description, {:?}", local_description);
+        let local_description = self
+            .peer_connection
+            .local_description()
+            .await
+            .ok_or(RtcError::NotReady("local description"))?;
+        info!(log: self.log, "Bot setting local description, {:?}", local_description);
         self.ws_tx
             .send(SignalingMessage::Answer {
                 from: self.bot_id.clone(),
                 to: self.client_id.clone(),
-                sdp: serde_json::to_string(&local_description).unwrap(),
+                sdp: serde_json::to_string(&local_description)
+                    .map_err(|e| RtcError::Internal(e.into()))?,
+                corr_id: Some(self.corr_id.clone()),
             })
             .await?;
+        let elapsed = started.elapsed();
+        self.factory.record_answer(elapsed);
+        info!(log: self.log, "Bot answered in {} ms", elapsed.as_millis());
 
-        // 在设置远程描述后发送缓存的候选项
-        let cached = {
-            let mut cache = self.cached_candidates.lock().unwrap();
-            std::mem::take(&mut *cache)
-        };
-
-        for candidate in cached {
-            let msg = SignalingMessage::IceCandidate {
-                from: self.bot_id.clone(),
-                to: self.client_id.clone(),
-                candidate: serde_json::to_string(&candidate.to_json().unwrap()).unwrap(),
-            };
-            self.ws_tx.send(msg).await?;
-        }
+        // Answer 已发出，开始转发本端候选
+        self.description_sent.send_replace(true);
 
         Ok(local_description.sdp)
     }
 
-    pub async fn setup_pc_handlers(&mut self) -> Result<()> {
-        let pc: Arc<RTCPeerConnection> = Arc::clone(&self.peer_connection);
-        let client_id = self.client_id.clone();
-        let bot_id = self.bot_id.clone();
-        let ws_tx = self.ws_tx.clone();
-        let cached_candidates: Arc<Mutex<Vec<RTCIceCandidate>>> =
-            Arc::clone(&self.cached_candidates);
+    fn parse_description(sdp: &str) -> RtcResult<RTCSessionDescription> {
+        serde_json::from_str::<RTCSessionDescription>(sdp)
+            .map_err(|e| RtcError::InvalidSdp(e.to_string()))
+    }
+
+    pub async fn setup_pc_handlers(&mut self) -> RtcResult<()> {
+        let log = self.log.clone();
+        let candidate_tx = self.local_candidate_tx.clone();
 
-        // ICE Candidate 处理
-        // 不做ice trickle
+        // ICE Candidate 处理，只入队，由 spawn_candidate_forwarder 的任务按顺序发送
         self.peer_connection.on_ice_candidate(Box::new(move |c| {
-            info!("rtc client received ice candidate, {:?}", c);
-            let pc2 = Arc::clone(&pc);
-            let client_id = client_id.clone();
-            let bot_id = bot_id.clone();
-            let ws_tx = ws_tx.clone();
-            let cached_candidates = Arc::clone(&cached_candidates);
-
-            Box::pin(async move {
-                if let Some(candidate) = c {
-                    if pc2.remote_description().await.is_none() {
-                        // 如果还没有远程描述，缓存候选项
-                        cached_candidates.lock().unwrap().push(candidate);
-                    } else {
-                        // 已有远程描述，直接发送
-                        let msg = SignalingMessage::IceCandidate {
-                            from: bot_id,
-                            to: client_id,
-                            candidate: serde_json::to_string(&candidate.to_json().unwrap())
-                                .unwrap(),
-                        };
-                        debug!("rtc client send ice candidate: {:?}", msg);
-                        if let Err(e) = ws_tx.send(msg).await {
-                            error!("Failed to send ICE candidate: {}", e);
-                        }
-                    }
-                }
-            })
+            info!(log: log, "rtc client gathered ice candidate, {:?}", c);
+            let _ = candidate_tx.send(c);
+            Box::pin(async {})
         }));
+        self.spawn_candidate_forwarder();
         // 监听音频轨道
 
-        let audio_tx = self.remote_audio_tx.take().unwrap();
+        let audio_tx = self
+            .remote_audio_tx
+            .take()
+            .ok_or(RtcError::NotReady("remote audio channel"))?;
+        let track_log = self.log.clone();
+        let track_cfg = self.cfg.clone();
+        let target_rate = self.remote_audio_rate;
+        let media_tx = Arc::clone(&self.media_tx);
+        let quality = Arc::clone(&self.quality);
         self.peer_connection
             .on_track(Box::new(move |track, _receiver, _transceiver| {
                 let audio_tx = audio_tx.clone();
+                let log = track_log.clone();
+                let cfg = track_cfg.clone();
+                let media_tx = Arc::clone(&media_tx);
+                let quality = Arc::clone(&quality);
                 Box::pin(async move {
-                    info!("Bot received track, {:?}", track);
+                    info!(log: log, "Bot received track, {:?}", track);
                     if track.kind() == RTPCodecType::Audio {
-                        Self::handle_track(track, audio_tx).await;
+                        Self::handle_track(
+                            track,
+                            cfg,
+                            target_rate,
+                            audio_tx,
+                            media_tx,
+                            quality,
+                            log,
+                        )
+                        .await;
+                    } else {
+                        // accept_video 时只协商不处理
+                        debug!(log: log, "ignore {} track", track.kind());
                     }
                 })
             }));
 
         // 连接状态变化处理
+        let state_log = self.log.clone();
+        let event_tx = self.event_tx.clone();
         self.peer_connection
             .on_peer_connection_state_change(Box::new(move |s: RTCPeerConnectionState| {
                 let state = s.clone();
+                let log = state_log.clone();
+                // Bot 已退出时接收端关闭，忽略发送失败
+                let _ = event_tx.send(RtcEvent::PeerState(state));
                 Box::pin(async move {
-                    info!("Peer Connection State has changed: {}", state);
+                    info!(log: log, "Peer Connection State has changed: {}", state);
                     if state == RTCPeerConnectionState::Failed {
-                        error!("Peer Connection has failed");
+                        error!(log: log, "Peer Connection has failed");
                     }
                 })
             }));
@@ -203,7 +416,7 @@ impl RTCClient {
         Ok(())
     }
 
-    pub fn setup_pc_other_handler(&mut self) -> Result<()> {
+    pub fn setup_pc_other_handler(&mut self) -> RtcResult<()> {
         self.peer_connection
             .on_data_channel(Box::new(move |channel| {
                 debug!("data channel opened");
@@ -212,244 +425,181 @@ impl RTCClient {
                 })
             }));
 
+        let ice_log = self.log.clone();
+        let event_tx = self.event_tx.clone();
         self.peer_connection
             .on_ice_connection_state_change(Box::new(
                 move |connection_state: RTCIceConnectionState| {
-                    debug!("Connection State has changed {connection_state}");
+                    let _ = event_tx.send(RtcEvent::IceState(connection_state));
+                    debug!(log: ice_log, "Connection State has changed {connection_state}");
                     match connection_state {
                         RTCIceConnectionState::Unspecified => {
-                            info!("rtc client ice connection unspecify");
+                            info!(log: ice_log, "rtc client ice connection unspecify");
                         }
                         RTCIceConnectionState::New => {
-                            info!("rtc client ice connection new");
+                            info!(log: ice_log, "rtc client ice connection new");
                         }
                         RTCIceConnectionState::Checking => {
-                            info!("rtc client ice connection checking");
+                            info!(log: ice_log, "rtc client ice connection checking");
                         }
                         RTCIceConnectionState::Connected => {
-                            info!("rtc client ice connection connected");
+                            info!(log: ice_log, "rtc client ice connection connected");
                         }
                         RTCIceConnectionState::Completed => {
-                            info!("rtc client ice connection completed");
+                            info!(log: ice_log, "rtc client ice connection completed");
                         }
                         RTCIceConnectionState::Disconnected => {
-                            info!("rtc client ice connection disconnected");
+                            info!(log: ice_log, "rtc client ice connection disconnected");
                         }
                         RTCIceConnectionState::Failed => {
-                            info!("rtc client ice connection failed");
+                            info!(log: ice_log, "rtc client ice connection failed");
                         }
                         RTCIceConnectionState::Closed => {
-                            info!("rtc client ice connection closed");
+                            info!(log: ice_log, "rtc client ice connection closed");
                         }
                     }
-                    if connection_state == RTCIceConnectionState::Connected {
-                        // 通知上层 notify_tx.send()
-                    }
                     Box::pin(async {})
                 },
             ));
 
-        // Set the handler for Peer connection state
-        // This will notify you when the peer has connected/disconnected
-        self.peer_connection
-            .on_peer_connection_state_change(Box::new(move |s: RTCPeerConnectionState| {
-                println!("Peer Connection State has changed: {s}");
-
-                if s == RTCPeerConnectionState::Failed {
-                    // Wait until PeerConnection has had no network activity for 30 seconds or another failure. It may be reconnected using an ICE Restart.
-                    // Use webrtc.PeerConnectionStateDisconnected if you are interested in detecting faster timeout.
-                    // Note that the PeerConnection may come back from PeerConnectionStateDisconnected.
-                    warn!("Peer Connection has gone to failed exiting");
-                    // 通知上层  notify_tx.send()
-                }
-
-                Box::pin(async {})
-            }));
-
-        let data_channel = self.data_channel.clone();
-        let d1 = data_channel.clone();
-        data_channel.on_open(Box::new(move || {
-            let d2 = d1.clone();
-            info!("data channel opened");
-            Box::pin(async move {
-                // read a file and send the whole file to remote peer
-                let file_path = "/Users/nixi/Project/assets/louader.opus";
-                let file = File::open(file_path).unwrap();
-                let mut reader = BufReader::new(file);
-                let mut buffer = vec![0; 4096];
-                loop {
-                    let bytes_read = reader.read(&mut buffer).unwrap();
-                    if bytes_read > 0 {
-                        let to_send = buffer[..bytes_read].to_vec();
-                        d2.send(&Bytes::from(to_send)).await.unwrap();
-                    }
-                }
-            })
-        }));
-        data_channel.on_buffered_amount_low(Box::new(move || {
-            info!("data channel buffered amount low");
-            Box::pin(async move {})
-        }));
-
         Ok(())
     }
 
-    async fn audio_track_rtcp_handler(sender: Arc<RTCRtpSender>) {
+    // 读取本地发送轨道的 RTCP（对端的接收报告、NACK 等），读取同时驱动拦截器处理
+    async fn audio_track_rtcp_handler(
+        sender: Arc<RTCRtpSender>,
+        clock_rate: Arc<AtomicU32>,
+        quality: Arc<std::sync::Mutex<QualityStats>>,
+        log: Logger,
+    ) {
         let mut buff = vec![0u8; 1500]; //  just the rtcp packet
         loop {
             match sender.read(&mut buff).await {
-                Ok((n, _)) => {
-                    if n.len() > 0 {
-                        // 在这里处理PCM音频数据
-                        info!("收到 {} 字节的音频数据", &n.len());
+                Ok((packets, _)) => {
+                    // 接收报告的抖动以发送编码的 RTP 时钟为单位
+                    let clock_rate = clock_rate.load(Ordering::Relaxed);
+                    let mut stats = quality.lock().unwrap();
+                    for packet in &packets {
+                        stats.on_rtcp(packet.as_ref(), clock_rate);
                     }
                 }
                 Err(err) => {
-                    error!("读取音频数据出错: {}", err);
+                    debug!(log: log, "rtcp reader stopped: {}", err);
                     break;
                 }
             }
         }
     }
 
-    async fn audio_send_handler(
-        mut receiver: mpsc::Receiver<data::AudioData>,
-        audio_track: Arc<TrackLocalStaticSample>,
-    ) -> Result<()> {
-        info!("start sleep for ten seconds");
-        tokio::time::sleep(Duration::from_secs(10)).await;
-        // 读取 Ogg Opus 文件
-        let file_path = "/Users/nixi/Project/assets/louader.opus";
-        let file = match File::open(file_path) {
-            Ok(f) => f,
-            Err(e) => {
-                error!("无法打开音频文件: {}", e);
-                return Err(anyhow::anyhow!("无法打开音频文件: {}", e));
-            }
-        };
-
-        let reader = BufReader::new(file);
-        let mut packet_reader = PacketReader::new(reader);
+    // 协商结果中优先级最高的音频编码，本地轨道按它发送
+    async fn negotiated_codec(sender: &RTCRtpSender) -> Option<CodecType> {
+        sender
+            .get_parameters()
+            .await
+            .rtp_parameters
+            .codecs
+            .iter()
+            .find_map(|codec| CodecType::from_mime_type(&codec.capability.mime_type))
+    }
 
-        // 跳过头部包
-        let mut header_packets = Vec::new();
-        for _ in 0..2 {
-            if let Some(packet) = packet_reader.read_packet().unwrap() {
-                header_packets.push(packet);
+    // 按 stats_interval_ms 定时采集 get_stats，关闭时随其他任务取消
+    fn spawn_quality_collector(&mut self) {
+        let peer_connection = Arc::clone(&self.peer_connection);
+        let quality = Arc::clone(&self.quality);
+        let interval = Duration::from_millis(self.cfg.stats_interval_ms);
+        let log = self.log.clone();
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                Self::collect_quality(&peer_connection, &quality).await;
+                debug!(log: log, "media quality: {}", *quality.lock().unwrap());
             }
-        }
-
-        // 读取所有音频包
-        let mut opus_packets = Vec::new();
-        while let Some(packet) = packet_reader.read_packet().unwrap() {
-            opus_packets.push(packet.data);
-        }
-
-        info!("已加载 {} 个 Opus 数据包", opus_packets.len());
-
-        // 从头部获取采样率和通道数
-        let mut id_header_data = &header_packets[0].data[..];
-        let _magic = id_header_data.read_u64::<LittleEndian>().unwrap();
-        let _version = id_header_data.read_u8().unwrap();
-        let _channels = id_header_data.read_u8().unwrap();
-        let _pre_skip = id_header_data.read_u16::<LittleEndian>().unwrap();
-        let _sample_rate = id_header_data.read_u32::<LittleEndian>().unwrap();
-
-        // 固定的发送间隔 (20ms)
-        let packet_duration = Duration::from_millis(20);
+        });
+        self.tasks.push(task.abort_handle());
+    }
 
-        // 循环发送数据包
-        let mut packet_index = 0;
+    // 等待 Answer 发出后按收集顺序发送本端候选，收集完毕时发送 end-of-candidates
+    fn spawn_candidate_forwarder(&mut self) {
+        let Some(mut candidate_rx) = self.local_candidate_rx.take() else {
+            return;
+        };
+        let mut description_sent = self.description_sent.subscribe();
+        let client_id = self.client_id.clone();
+        let bot_id = self.bot_id.clone();
+        let corr_id = self.corr_id.clone();
+        let log = self.log.clone();
+        let ws_tx = self.ws_tx.clone();
+        let ice_tcp_port = self.cfg.ice_tcp_port;
 
-        loop {
-            // match receiver.try_recv() {
-            //     Ok(audio_data) => {
-            //         // 如果有其他模块发送的数据，优先处理
-            //         let sample = Sample {
-            //             data: audio_data.data,
-            //             duration: audio_data.duration,
-            //             ..Default::default()
-            //         };
-            //         if let Err(e) = audio_track.write_sample(&sample).await {
-            //             error!("发送样本失败: {}", e);
-            //         }
-            //         continue;
-            //     }
-            //     Err(mpsc::error::TryRecvError::Empty) => {
-            //         // 队列为空，继续发送文件数据
-            //     }
-            //     Err(mpsc::error::TryRecvError::Disconnected) => {
-            //         warn!("音频数据通道已断开");
-            //         // 通道断开但我们仍然继续发送文件数据
-            //     }
-            // }
-
-            // 获取当前数据包
-            let opus_packet = &opus_packets[packet_index];
-
-            // 创建样本
-            let sample = Sample {
-                data: Bytes::from(opus_packet.clone()),
-                duration: packet_duration,
-                ..Default::default()
-            };
-
-            // 发送样本
-            if let Err(e) = audio_track.write_sample(&sample).await {
-                error!("发送样本失败: {}", e);
-                break;
+        let task = tokio::spawn(async move {
+            while let Some(candidate) = candidate_rx.recv().await {
+                // 每个候选都要等本轮的描述发出，bot 发起的重协商期间重新排队
+                if description_sent.wait_for(|sent| *sent).await.is_err() {
+                    return;
+                }
+                let mut candidates = vec![trickle::local_candidate_json(candidate.as_ref())];
+                // UDP host 候选之后附带同地址的 ICE-TCP passive 候选
+                if let Some(candidate) = candidate.as_ref().filter(|_| ice_tcp_port != 0) {
+                    if let Some(init) = ice_tcp::passive_candidate(candidate, ice_tcp_port) {
+                        candidates.push(serde_json::to_string(&init).map_err(Into::into));
+                    }
+                }
+                for candidate in candidates {
+                    let candidate = match candidate {
+                        Ok(json) => json,
+                        Err(e) => {
+                            error!(log: log, "Failed to serialize ICE candidate: {}", e);
+                            continue;
+                        }
+                    };
+                    let msg = SignalingMessage::IceCandidate {
+                        from: bot_id.clone(),
+                        to: client_id.clone(),
+                        candidate,
+                        corr_id: Some(corr_id.clone()),
+                    };
+                    debug!(log: log, "rtc client send ice candidate: {:?}", msg);
+                    if let Err(e) = ws_tx.send(msg).await {
+                        error!(log: log, "Failed to send ICE candidate: {}", e);
+                        return;
+                    }
+                }
             }
+        });
+        self.tasks.push(task.abort_handle());
+    }
 
-            // 更新索引，循环播放
-            packet_index = (packet_index + 1) % opus_packets.len();
-
-            // 等待固定时间间隔
-            tokio::time::sleep(packet_duration).await;
+    // 远端描述设置前收到的候选先排队，设置后由 handle_offer 统一添加
+    pub async fn add_ice_candidate(&mut self, candidate: String) -> RtcResult<()> {
+        let parsed = trickle::parse_remote_candidate(&candidate)
+            .map_err(|e| RtcError::InvalidCandidate(e.to_string()))?;
+        if parsed == RemoteCandidate::EndOfCandidates {
+            info!(log: self.log, "remote end of candidates");
         }
-
-        Ok(())
-
-        // let mut encoder = match create_encoder(CodecType::Opus, 48000, 1) {
-        //     Ok(p) => Box::pin(p),
-        //     Err(e) => {
-        //         error!("创建音频处理器失败: {}", e);
-        //         return Err(e);
-        //     }
-        // };
-
-        // loop {
-        //     let audio_data = receiver.recv().await;
-        //     if audio_data.is_none() {
-        //         error!("receive generated audio data error");
-        //         continue;
-        //     }
-
-        //     let audio_data = audio_data.unwrap();
-        //     let duration = audio_data.duration;
-        //     let audio_data = encoder.encode(&audio_data.data).await?;
-
-        //     let sample = Sample {
-        //         data: audio_data,
-        //         duration: duration,
-        //         ..Default::default()
-        //     };
-        //     audio_track.write_sample(&sample).await?;
-        // }
-        // Ok(())
-    }
-
-    pub async fn add_ice_candidate(&self, candidate: String) -> Result<()> {
+        if self.peer_connection.remote_description().await.is_none() {
+            debug!(log: self.log, "queue remote candidate before offer");
+            self.pending_remote_candidates.push(parsed);
+            return Ok(());
+        }
+        // end-of-candidates 以空候选交给 ICE agent
         self.peer_connection
-            .add_ice_candidate(webrtc::ice_transport::ice_candidate::RTCIceCandidateInit {
-                candidate,
-                ..Default::default()
-            })
+            .add_ice_candidate(parsed.into_init())
             .await
-            .map_err(|e| anyhow::anyhow!("Failed to add ICE candidate: {:?}", e))
+            .map_err(|e| RtcError::InvalidCandidate(e.to_string()))
+    }
+
+    async fn add_pending_remote_candidates(&mut self) {
+        for init in self.pending_remote_candidates.take() {
+            if let Err(e) = self.peer_connection.add_ice_candidate(init).await {
+                error!(log: self.log, "Failed to add queued ICE candidate: {:?}", e);
+            }
+        }
     }
 
-    pub(crate) fn set_remote_audio_tx(&mut self, audio_tx: mpsc::Sender<Vec<i16>>) -> () {
+    pub(crate) fn set_remote_audio_tx(&mut self, audio_tx: mpsc::Sender<Vec<i16>>, sample_rate: u32) {
         self.remote_audio_tx = Some(audio_tx);
+        self.remote_audio_rate = sample_rate;
     }
 
     // pub(crate) fn set_audio_rx(&mut self, audio_rx: mpsc::Receiver<Vec<i16>>) -> () {
@@ -458,64 +608,458 @@ impl RTCClient {
 }
 
 impl RTCClient {
-    // 处理远程音频轨道
-    pub async fn handle_track(track: Arc<TrackRemote>, audio_tx: mpsc::Sender<Vec<i16>>) {
-        debug!("handle_track start");
-        let mut decoder = match VoxDecoder::new(CodecType::Opus, 48000, 1) {
+    // 处理远程音频轨道：读取任务记录到达时间后交给抖动缓冲，按播放时刻取出、解码
+    pub async fn handle_track(
+        track: Arc<TrackRemote>,
+        cfg: RtcConfig,
+        target_rate: u32,
+        audio_tx: mpsc::Sender<Vec<i16>>,
+        media_tx: Arc<watch::Sender<Option<Instant>>>,
+        quality: Arc<std::sync::Mutex<QualityStats>>,
+        log: Logger,
+    ) {
+        let codec = track.codec();
+        debug!(log: log, "handle_track start, codec {}", codec.capability.mime_type);
+        let Some(codec_type) = CodecType::from_mime_type(&codec.capability.mime_type) else {
+            error!(log: log, "不支持的音频编码: {}", codec.capability.mime_type);
+            return;
+        };
+        // Opus 按轨道配置解码，G.711 固定为 8kHz 单声道
+        let (sample_rate, channels) =
+            codec_type.pcm_format(cfg.audio_sample_rate, cfg.audio_channels);
+        let mut decoder = match VoxDecoder::new(codec_type, sample_rate, channels) {
             Ok(p) => Box::pin(p),
             Err(e) => {
-                error!("创建音频处理器失败: {}", e);
+                error!(log: log, "创建音频处理器失败: {}", e);
                 return;
             }
         };
 
-        let mut buff = vec![0u8; 1920];
+        // 解码输出为轨道格式，处理模块需要单声道
+        let mut converter =
+            AudioConverter::new(decoder.sample_rate(), decoder.channels(), target_rate, 1);
+        let mut tracker = SeqTracker::new();
+        let mut jitter = JitterBuffer::new(
+            Duration::from_millis(cfg.jitter_target_ms),
+            Duration::from_millis(cfg.jitter_max_ms),
+            decoder.clock_rate(),
+        );
 
-        loop {
-            match track.read(&mut buff).await {
-                Ok((n, _)) => {
-                    if n.payload.len() > 0 {
-                        match decoder.decode(&n.payload).await {
-                            Ok(pcm_data) => {
-                                info!("收到 {} 字节的音频数据", &pcm_data.len());
-                                if let Err(e) = audio_tx.send(pcm_data).await {
-                                    error!("send audio data to bot failed: {}", e);
-                                    break;
-                                }
-                            }
-                            Err(e) => error!("decode error: {}", e),
+        let (packet_tx, mut packet_rx) = mpsc::unbounded_channel();
+        let reader_log = log.clone();
+        let reader = tokio::spawn(async move {
+            let mut buff = vec![0u8; 1920];
+            loop {
+                match track.read(&mut buff).await {
+                    Ok((packet, _)) => {
+                        if packet.payload.is_empty() {
+                            continue;
                         }
+                        media_tx.send_replace(Some(Instant::now()));
+                        let packet = RtpPacket {
+                            seq: packet.header.sequence_number,
+                            timestamp: packet.header.timestamp,
+                            payload: packet.payload,
+                        };
+                        if packet_tx.send((packet, Instant::now())).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        error!(log: reader_log, "读取音频数据出错: {}", err);
+                        break;
                     }
                 }
-                Err(err) => {
-                    error!("读取音频数据出错: {}", err);
+            }
+        });
+
+        let mut ended = false;
+        while !ended {
+            let deadline = jitter.next_deadline();
+            tokio::select! {
+                packet = packet_rx.recv() => match packet {
+                    Some((packet, arrival)) => jitter.push(packet, arrival),
+                    None => ended = true,
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now).into()),
+                    if deadline.is_some() => {}
+            }
+
+            let mut packets = Vec::new();
+            while let Some(packet) = jitter.pop(Instant::now()) {
+                packets.push(packet);
+            }
+            if ended {
+                packets.extend(jitter.drain());
+            }
+            for packet in packets {
+                let pcm_data =
+                    Self::decode_packet(&mut decoder, &mut tracker, &packet, &log).await;
+                let pcm_data = converter.process(&pcm_data);
+                if pcm_data.is_empty() {
+                    continue;
+                }
+                if let Err(e) = audio_tx.send(pcm_data).await {
+                    error!(log: log, "send audio data to bot failed: {}", e);
+                    ended = true;
                     break;
                 }
             }
+
+            let mut stats = quality.lock().unwrap();
+            stats.jitter = jitter.stats();
+            stats.packets_lost = tracker.lost();
+            stats.concealments = tracker.concealments();
+            stats.concealed_ms = tracker.concealed_ticks() * 1000 / decoder.clock_rate() as u64;
         }
+        reader.abort();
+
+        let stats = jitter.stats();
+        info!(
+            log: log,
+            "remote audio ended, lost {} packets, concealed {}ms, jitter {:?}",
+            tracker.lost(),
+            tracker.concealed_ticks() * 1000 / decoder.clock_rate() as u64,
+            stats
+        );
     }
 
-    pub async fn setup_media(&mut self) -> Result<()> {
-        let audio_track = Arc::new(TrackLocalStaticSample::new(
-            RTCRtpCodecCapability {
-                mime_type: MIME_TYPE_OPUS.to_owned(),
-                clock_rate: 48000,
-                channels: 1,
-                ..Default::default()
-            },
-            "audio".to_owned(),
-            "webrtc-rs".to_owned(),
-        ));
+    // 丢包时先补出缺失的时长，保证 PCM 时间轴连续
+    async fn decode_packet(
+        decoder: &mut VoxDecoder,
+        tracker: &mut SeqTracker,
+        packet: &RtpPacket,
+        log: &Logger,
+    ) -> Vec<i16> {
+        let mut pcm_data = Vec::new();
+        match tracker.on_packet(packet.seq, packet.timestamp) {
+            SeqEvent::Late => return pcm_data,
+            SeqEvent::Gap { lost, ticks } => {
+                let samples = (ticks as u64 * decoder.sample_rate() as u64
+                    / decoder.clock_rate() as u64) as usize;
+                debug!(
+                    log: log,
+                    "lost {} packets before seq {}, concealing {} samples",
+                    lost,
+                    packet.seq,
+                    samples
+                );
+                match decoder.conceal(&packet.payload, lost as usize, samples).await {
+                    Ok(pcm) => pcm_data = pcm,
+                    Err(e) => warn!(log: log, "conceal error: {}", e),
+                }
+            }
+            SeqEvent::Reset => debug!(log: log, "rtp stream reset at seq {}", packet.seq),
+            SeqEvent::First | SeqEvent::InOrder => {}
+        }
+
+        match decoder.decode(&packet.payload).await {
+            Ok(pcm) => {
+                let frames = pcm.len() / decoder.channels().max(1) as usize;
+                tracker.set_frame_ticks(
+                    (frames as u64 * decoder.clock_rate() as u64 / decoder.sample_rate() as u64)
+                        as u32,
+                );
+                pcm_data.extend(pcm);
+            }
+            Err(e) => error!(log: log, "decode error: {}", e),
+        }
+        pcm_data
+    }
+
+    // 创建本地轨道并加入连接，编码先取配置中的首选，协商后由 start_audio_sender 按结果调整
+    pub async fn setup_media(&mut self) -> RtcResult<()> {
+        let codec = self
+            .cfg
+            .codecs
+            .audio
+            .first()
+            .and_then(|name| CodecType::from_name(name))
+            .ok_or(RtcError::NotReady("audio codec"))?;
+        let audio_track = self.new_audio_track(codec);
+        self.send_clock_rate
+            .store(codec.clock_rate(), Ordering::Relaxed);
 
         let rtp_sender = self.peer_connection.add_track(audio_track.clone()).await?;
-        tokio::spawn(Self::audio_track_rtcp_handler(rtp_sender.clone()));
-        tokio::spawn(Self::audio_send_handler(
-            self.local_audio_rx.take().unwrap(),
-            audio_track.clone(),
+        let rtcp_task = tokio::spawn(Self::audio_track_rtcp_handler(
+            rtp_sender.clone(),
+            Arc::clone(&self.send_clock_rate),
+            Arc::clone(&self.quality),
+            self.log.clone(),
         ));
+        self.tasks.push(rtcp_task.abort_handle());
+        self.spawn_quality_collector();
 
         self.rtp_sender = Some(rtp_sender);
         self.audio_track = Some(audio_track);
         Ok(())
     }
+
+    fn new_audio_track(&self, codec: CodecType) -> Arc<TrackLocalStaticSample> {
+        let (capability, _) = factory::audio_codec_capability(codec, &self.cfg.codecs);
+        Arc::new(TrackLocalStaticSample::new(
+            capability,
+            "audio".to_owned(),
+            "webrtc-rs".to_owned(),
+        ))
+    }
+
+    // 设置远端描述后调用：按协商结果中优先级最高的音频编码替换本地轨道，再启动发送任务。
+    // 发送任务只启动一次，之后的重协商沿用已选定的编码
+    async fn start_audio_sender(&mut self) -> RtcResult<()> {
+        let Some(rtp_sender) = self.rtp_sender.clone() else {
+            return Ok(());
+        };
+        if self.local_audio_rx.is_none() {
+            return Ok(());
+        }
+        let Some(codec) = Self::negotiated_codec(&rtp_sender).await else {
+            return Err(RtcError::InvalidSdp("no supported audio codec".to_string()));
+        };
+        let current = self
+            .audio_track
+            .as_ref()
+            .and_then(|track| CodecType::from_mime_type(&track.codec().mime_type));
+        if current != Some(codec) {
+            let audio_track = self.new_audio_track(codec);
+            rtp_sender.replace_track(Some(audio_track.clone())).await?;
+            self.audio_track = Some(audio_track);
+            self.send_clock_rate
+                .store(codec.clock_rate(), Ordering::Relaxed);
+        }
+        let audio_track = self
+            .audio_track
+            .clone()
+            .ok_or(RtcError::NotReady("audio track"))?;
+        info!(log: self.log, "local audio codec {:?}", codec);
+
+        let send_task = tokio::spawn(audio_out::run_audio_sender(
+            self.local_audio_rx
+                .take()
+                .ok_or(RtcError::NotReady("local audio channel"))?,
+            self.local_prompt_rx
+                .take()
+                .ok_or(RtcError::NotReady("local prompt channel"))?,
+            audio_track,
+            self.cfg.audio_sample_rate,
+            self.cfg.audio_channels,
+            self.cfg.opus.clone(),
+            self.log.clone(),
+        ));
+        self.tasks.push(send_task.abort_handle());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn test_client() -> (RTCClient, mpsc::Receiver<SignalingMessage>) {
+        // 与 main 一致：rustls 同时启用了两种实现，DTLS 握手前要选定一个
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+        let cfg = RtcConfig::default();
+        let factory = RtcFactory::new(cfg.clone()).await.unwrap();
+        let (ws_tx, ws_rx) = mpsc::channel(64);
+        let log = Logger::root(slog::Discard, slog::o!());
+        let mut rtc = RTCClient::new(
+            factory,
+            cfg,
+            "client".to_string(),
+            "bot".to_string(),
+            "corr".to_string(),
+            log,
+            ws_tx,
+        )
+        .await
+        .unwrap();
+        let (_local_audio_tx, local_audio_rx) = mpsc::channel(8);
+        let (_local_prompt_tx, local_prompt_rx) = mpsc::channel(2);
+        rtc.set_local_audio_rx(local_audio_rx);
+        rtc.set_local_prompt_rx(local_prompt_rx);
+        let (remote_audio_tx, _remote_audio_rx) = mpsc::channel(8);
+        rtc.set_remote_audio_tx(remote_audio_tx, 16000);
+        rtc.setup_pc_handlers().await.unwrap();
+        rtc.setup_media().await.unwrap();
+        (rtc, ws_rx)
+    }
+
+    // 模拟客户端的 PeerConnection，按给定配置注册编码
+    async fn remote_peer(cfg: RtcConfig) -> Arc<RTCPeerConnection> {
+        RtcFactory::new(cfg)
+            .await
+            .unwrap()
+            .acquire()
+            .await
+            .unwrap()
+            .peer_connection
+    }
+
+    // 客户端发起协商，返回 bot 的 Answer SDP
+    async fn offer_to(rtc: &mut RTCClient, remote: &RTCPeerConnection) -> String {
+        let offer = remote.create_offer(None).await.unwrap();
+        remote.set_local_description(offer.clone()).await.unwrap();
+        rtc.handle_offer(serde_json::to_string(&offer).unwrap())
+            .await
+            .unwrap()
+    }
+
+    // 跳过候选等其他消息，取下一条 bot 发出的 Offer
+    async fn next_offer(ws_rx: &mut mpsc::Receiver<SignalingMessage>) -> RTCSessionDescription {
+        loop {
+            if let SignalingMessage::Offer { sdp, .. } = ws_rx.recv().await.unwrap() {
+                return serde_json::from_str(&sdp).unwrap();
+            }
+        }
+    }
+
+    // 客户端应答 bot 发起的 offer，返回应答 JSON
+    async fn answer_json(peer: &RTCPeerConnection, offer: RTCSessionDescription) -> String {
+        peer.set_remote_description(offer).await.unwrap();
+        let answer = peer.create_answer(None).await.unwrap();
+        peer.set_local_description(answer.clone()).await.unwrap();
+        serde_json::to_string(&answer).unwrap()
+    }
+
+    // audio m 段的方向属性，数据通道的 m=application 段不计
+    fn audio_direction(sdp: &str) -> &str {
+        sdp.split("m=")
+            .filter(|section| section.starts_with("audio"))
+            .flat_map(|section| section.lines())
+            .filter_map(|line| line.strip_prefix("a="))
+            .find(|attr| matches!(*attr, "sendrecv" | "sendonly" | "recvonly" | "inactive"))
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn test_remove_and_restore_local_audio_renegotiates() {
+        let (mut rtc, mut ws_rx) = test_client().await;
+        let remote = remote_peer(RtcConfig::default()).await;
+        remote
+            .add_transceiver_from_kind(RTPCodecType::Audio, None)
+            .await
+            .unwrap();
+
+        // 客户端发起首次协商
+        let answer_sdp = offer_to(&mut rtc, &remote).await;
+        assert_eq!(audio_direction(&answer_sdp), "sendrecv");
+        remote
+            .set_remote_description(RTCSessionDescription::answer(answer_sdp).unwrap())
+            .await
+            .unwrap();
+
+        // 移除下行音频后 bot 发起重协商，不再发送
+        rtc.remove_local_audio().await.unwrap();
+        let offer = next_offer(&mut ws_rx).await;
+        assert_eq!(audio_direction(&offer.sdp), "recvonly");
+        let answer = answer_json(&remote, offer).await;
+        rtc.handle_answer(answer).await.unwrap();
+        assert_eq!(rtc.peer_connection.signaling_state(), RTCSignalingState::Stable);
+
+        // 恢复后再次重协商，重新发送
+        rtc.restore_local_audio().await.unwrap();
+        let offer = next_offer(&mut ws_rx).await;
+        assert_eq!(audio_direction(&offer.sdp), "sendrecv");
+        let answer = answer_json(&remote, offer).await;
+        rtc.handle_answer(answer).await.unwrap();
+        assert_eq!(rtc.peer_connection.signaling_state(), RTCSignalingState::Stable);
+
+        rtc.close().await;
+        remote.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_answer_is_not_fatal() {
+        let (mut rtc, mut ws_rx) = test_client().await;
+        let remote = remote_peer(RtcConfig::default()).await;
+
+        rtc.send_offer(false).await.unwrap();
+        let answer = answer_json(&remote, next_offer(&mut ws_rx).await).await;
+        rtc.handle_answer(answer.clone()).await.unwrap();
+
+        // 重复的 Answer 被忽略，Bot 据 is_fatal 继续会话
+        let err = rtc.handle_answer(answer).await.unwrap_err();
+        assert!(matches!(err, RtcError::UnexpectedState(_)));
+        assert!(!err.is_fatal());
+        assert_eq!(rtc.peer_connection.signaling_state(), RTCSignalingState::Stable);
+
+        rtc.close().await;
+        remote.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_local_track_follows_negotiated_codec() {
+        let (mut rtc, _ws_rx) = test_client().await;
+        let mut cfg = RtcConfig::default();
+        cfg.codecs.audio = vec!["pcmu".to_string()];
+        let remote = remote_peer(cfg).await;
+        remote
+            .add_transceiver_from_kind(RTPCodecType::Audio, None)
+            .await
+            .unwrap();
+
+        // 客户端只支持 PCMU，bot 换成 PCMU 轨道后仍然双向发送
+        let answer_sdp = offer_to(&mut rtc, &remote).await;
+        assert!(answer_sdp.contains("PCMU/8000"));
+        assert!(!answer_sdp.contains("opus"));
+        assert_eq!(audio_direction(&answer_sdp), "sendrecv");
+        let track = rtc.audio_track.clone().unwrap();
+        assert_eq!(
+            CodecType::from_mime_type(&track.codec().mime_type),
+            Some(CodecType::Pcmu)
+        );
+
+        rtc.close().await;
+        remote.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_video_rejected_when_not_accepted() {
+        let (mut rtc, _ws_rx) = test_client().await;
+        let mut cfg = RtcConfig::default();
+        cfg.codecs.accept_video = true;
+        let remote = remote_peer(cfg).await;
+        for kind in [RTPCodecType::Audio, RTPCodecType::Video] {
+            remote.add_transceiver_from_kind(kind, None).await.unwrap();
+        }
+
+        // 默认配置不接收视频，视频 m-line 端口为 0，音频正常协商
+        let answer_sdp = offer_to(&mut rtc, &remote).await;
+        assert!(answer_sdp.contains("m=video 0 "));
+        assert!(!answer_sdp.contains("m=audio 0 "));
+        assert_eq!(audio_direction(&answer_sdp), "sendrecv");
+
+        rtc.close().await;
+        remote.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_candidates_wait_for_bot_offer() {
+        let (mut rtc, mut ws_rx) = test_client().await;
+        let remote = remote_peer(RtcConfig::default()).await;
+        remote
+            .add_transceiver_from_kind(RTPCodecType::Audio, None)
+            .await
+            .unwrap();
+        offer_to(&mut rtc, &remote).await;
+
+        // 等首轮候选收集完毕（空候选）
+        loop {
+            if let SignalingMessage::IceCandidate { candidate, .. } = ws_rx.recv().await.unwrap() {
+                if candidate.contains(r#""candidate":"""#) {
+                    break;
+                }
+            }
+        }
+
+        // ICE restart 重新收集的候选不会先于 Offer 发出
+        rtc.send_offer(true).await.unwrap();
+        assert!(matches!(
+            ws_rx.recv().await.unwrap(),
+            SignalingMessage::Offer { .. }
+        ));
+
+        rtc.close().await;
+        remote.close().await.unwrap();
+    }
 }
@@ -1,7 +1,9 @@
 use super::{rtc_client::RTCClient, *};
 use crate::{
     audio_processor::biz_processor::{AsrProcessor, AudioBizProcessor, VadProcessor},
+    prompt::Frame,
     server::data,
+    utils::audio::AudioConverter,
 };
 use anyhow::Result;
 use std::sync::Arc;
@@ -13,6 +15,8 @@ pub struct RTCDelegate {
     //remote_audio_rx: mpsc::Receiver<Vec<i16>>,
     // 从处理模块接收要发送到本地轨道的音频数据
     local_audio_tx: mpsc::Sender<data::AudioData>,
+    // 提示音帧，发送任务优先发送，播放期间上面的 PCM 暂停
+    local_prompt_tx: mpsc::Sender<Frame>,
 }
 
 impl RTCDelegate {
@@ -21,20 +25,25 @@ impl RTCDelegate {
         Self,
         //        mpsc::Sender<Vec<i16>>,
         mpsc::Receiver<data::AudioData>,
+        mpsc::Receiver<Frame>,
     ) {
         // 创建用于远程音频数据的通道
         //      let (remote_audio_tx, remote_audio_rx) = mpsc::channel::<Vec<i16>>(100);
 
         // 创建用于本地音频数据的通道
         let (local_audio_tx, local_audio_rx) = mpsc::channel::<data::AudioData>(100);
+        // 容量决定提示音停止/暂停的延迟，保持很小
+        let (local_prompt_tx, local_prompt_rx) = mpsc::channel::<Frame>(2);
 
         (
             Self {
                 //            remote_audio_rx,
                 local_audio_tx,
+                local_prompt_tx,
             },
             //      remote_audio_tx,
             local_audio_rx,
+            local_prompt_rx,
         )
     }
 
@@ -43,6 +52,50 @@ impl RTCDelegate {
         self.local_audio_tx.clone()
     }
 
+    /// 获取提示音帧的发送器
+    pub fn get_local_prompt_tx(&self) -> mpsc::Sender<Frame> {
+        self.local_prompt_tx.clone()
+    }
+
+    /// 获取按指定格式写入的发送器，转换为轨道格式后转发到本地轨道，
+    /// 所有发送器释放后把重采样器中剩余的采样一并送出
+    pub fn get_converting_audio_tx(
+        &self,
+        from_rate: u32,
+        from_channels: u16,
+        to_rate: u32,
+        to_channels: u16,
+    ) -> mpsc::Sender<data::AudioData> {
+        let (tx, mut rx) = mpsc::channel::<data::AudioData>(100);
+        let local_audio_tx = self.local_audio_tx.clone();
+        let mut converter = AudioConverter::new(from_rate, from_channels, to_rate, to_channels);
+        tokio::spawn(async move {
+            while let Some(audio) = rx.recv().await {
+                let data = converter.process(&audio.data);
+                if data.is_empty() {
+                    continue;
+                }
+                let audio = data::AudioData {
+                    data,
+                    duration: audio.duration,
+                };
+                if local_audio_tx.send(audio).await.is_err() {
+                    return;
+                }
+            }
+            let data = converter.flush();
+            if !data.is_empty() {
+                let frames = (data.len() / to_channels.max(1) as usize) as u64;
+                let audio = data::AudioData {
+                    data,
+                    duration: std::time::Duration::from_micros(frames * 1_000_000 / to_rate as u64),
+                };
+                let _ = local_audio_tx.send(audio).await;
+            }
+        });
+        tx
+    }
+
     /// 发送音频数据到本地轨道
     pub async fn send_audio_to_local_track(&self, audio_data: data::AudioData) -> Result<()> {
         self.local_audio_tx.send(audio_data).await?;
@@ -0,0 +1,135 @@
+use anyhow::Result;
+use webrtc::ice_transport::ice_candidate::{RTCIceCandidate, RTCIceCandidateInit};
+
+// 信令中 IceCandidate.candidate 的内容
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteCandidate {
+    Candidate(RTCIceCandidateInit),
+    EndOfCandidates,
+}
+
+// 浏览器发送 JSON 序列化的 RTCIceCandidate（含 sdpMid/sdpMLineIndex）；
+// 旧客户端只发送 candidate 行。空字符串、null 或 candidate 为空表示对端收集完毕
+pub fn parse_remote_candidate(raw: &str) -> Result<RemoteCandidate> {
+    let raw = raw.trim();
+    if raw.is_empty() || raw == "null" {
+        return Ok(RemoteCandidate::EndOfCandidates);
+    }
+    if !raw.starts_with('{') {
+        return Ok(RemoteCandidate::Candidate(RTCIceCandidateInit {
+            candidate: raw.trim_start_matches("a=").to_string(),
+            ..Default::default()
+        }));
+    }
+    let init: RTCIceCandidateInit = serde_json::from_str(raw)?;
+    if init.candidate.is_empty() {
+        return Ok(RemoteCandidate::EndOfCandidates);
+    }
+    Ok(RemoteCandidate::Candidate(init))
+}
+
+impl RemoteCandidate {
+    // 交给 add_ice_candidate 的参数，end-of-candidates 为空候选
+    pub fn into_init(self) -> RTCIceCandidateInit {
+        match self {
+            RemoteCandidate::Candidate(init) => init,
+            RemoteCandidate::EndOfCandidates => RTCIceCandidateInit::default(),
+        }
+    }
+}
+
+// 远端描述设置前收到的候选按到达顺序排队，end-of-candidates 排在最后，重复的只保留一个
+#[derive(Debug, Default)]
+pub struct PendingCandidates {
+    queue: Vec<RemoteCandidate>,
+}
+
+impl PendingCandidates {
+    pub fn push(&mut self, candidate: RemoteCandidate) {
+        let ended = self.queue.last() == Some(&RemoteCandidate::EndOfCandidates);
+        match candidate {
+            RemoteCandidate::EndOfCandidates if ended => {}
+            // 乱序到达的候选仍排在 end-of-candidates 之前
+            RemoteCandidate::Candidate(_) if ended => {
+                self.queue.insert(self.queue.len() - 1, candidate)
+            }
+            _ => self.queue.push(candidate),
+        }
+    }
+
+    pub fn take(&mut self) -> Vec<RTCIceCandidateInit> {
+        std::mem::take(&mut self.queue)
+            .into_iter()
+            .map(RemoteCandidate::into_init)
+            .collect()
+    }
+}
+
+// 发给客户端的候选，None 表示本端收集完毕
+pub fn local_candidate_json(candidate: Option<&RTCIceCandidate>) -> Result<String> {
+    let init = match candidate {
+        Some(candidate) => candidate.to_json()?,
+        None => RTCIceCandidateInit::default(),
+    };
+    Ok(serde_json::to_string(&init)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "candidate:1 1 udp 2130706431 192.168.1.2 50000 typ host";
+
+    #[test]
+    fn test_parse_browser_candidate() {
+        let raw = format!(
+            r#"{{"candidate":"{}","sdpMid":"0","sdpMLineIndex":0,"usernameFragment":"abcd"}}"#,
+            LINE
+        );
+        let RemoteCandidate::Candidate(init) = parse_remote_candidate(&raw).unwrap() else {
+            panic!("expected candidate");
+        };
+        assert_eq!(init.candidate, LINE);
+        assert_eq!(init.sdp_mid.as_deref(), Some("0"));
+        assert_eq!(init.sdp_mline_index, Some(0));
+        assert_eq!(init.username_fragment.as_deref(), Some("abcd"));
+    }
+
+    #[test]
+    fn test_parse_legacy_and_end_of_candidates() {
+        let RemoteCandidate::Candidate(init) = parse_remote_candidate(LINE).unwrap() else {
+            panic!("expected candidate");
+        };
+        assert_eq!(init.candidate, LINE);
+        assert_eq!(init.sdp_mid, None);
+
+        for raw in ["", "null", r#"{"candidate":"","sdpMid":"0"}"#] {
+            assert_eq!(
+                parse_remote_candidate(raw).unwrap(),
+                RemoteCandidate::EndOfCandidates
+            );
+        }
+        assert!(parse_remote_candidate("{not json").is_err());
+
+        let end = local_candidate_json(None).unwrap();
+        assert_eq!(
+            parse_remote_candidate(&end).unwrap(),
+            RemoteCandidate::EndOfCandidates
+        );
+    }
+
+    #[test]
+    fn test_end_of_candidates_queued_before_offer() {
+        let mut pending = PendingCandidates::default();
+        pending.push(parse_remote_candidate(LINE).unwrap());
+        pending.push(parse_remote_candidate("").unwrap());
+        pending.push(parse_remote_candidate("null").unwrap());
+
+        let inits = pending.take();
+        assert_eq!(inits.len(), 2);
+        assert_eq!(inits[0].candidate, LINE);
+        // 设置远端描述后以空候选通知 ICE agent 对端收集完毕
+        assert!(inits[1].candidate.is_empty());
+        assert!(pending.take().is_empty());
+    }
+}
@@ -15,8 +15,12 @@ use crate::config::CONFIG;
 use super::*;
 
 // lazy static a server id
+// 可通过 VOX_SERVER_ID 固定，进程内保持不变，重连后以同一 id 重新注册
 lazy_static::lazy_static! {
-   pub static ref SERVER_ID: String = xid::new().to_string();
+   pub static ref SERVER_ID: String = std::env::var("VOX_SERVER_ID")
+       .ok()
+       .filter(|id| !id.is_empty())
+       .unwrap_or_else(|| xid::new().to_string());
 }
 
 pub enum BotEvent {
@@ -1,20 +1,26 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
 
 use super::*;
-use futures_util::{SinkExt, StreamExt};
+use futures_util::{stream::SplitSink, SinkExt, StreamExt};
+use rand::Rng;
 use serde_json::json;
 use signal_cli::{
     msgs::{CallingPayload, CandidatePayload, ServerEvent, ServerMsg},
     SERVER_ID,
 };
 use tokio::{net::TcpStream, sync::mpsc};
-use tokio_tungstenite::connect_async;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 use tungstenite::client::IntoClientRequest;
 use tungstenite::protocol::Message;
 
 use url::Url;
 
-use crate::{bot::bot::Bot, config::CONFIG, msg_center::signaling_msgs::SignalingMessage};
+use crate::{
+    bot::bot::Bot,
+    config::{OfflinePolicy, CONFIG},
+    msg_center::signaling_msgs::SignalingMessage,
+};
 
 use super::rtc::traits::WebRTCHandler;
 
@@ -97,71 +103,234 @@ async fn run_websocket_client() {
     }
 }
 
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// 重连退避：指数增长，取上限的一半再加随机抖动，避免多个实例同时重连
+struct Backoff {
+    initial: Duration,
+    max: Duration,
+    attempt: u32,
+}
+
+impl Backoff {
+    fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max: max.max(initial),
+            attempt: 0,
+        }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let factor = 1u32 << self.attempt.min(16);
+        let cap = self.initial.saturating_mul(factor).min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+        let half = cap / 2;
+        half + jitter(cap - half)
+    }
+
+    fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+// [0, max] 内的随机时长
+fn jitter(max: Duration) -> Duration {
+    let millis = max.as_millis() as u64;
+    Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+}
+
+// 断线期间待发送的消息
+struct Outbox {
+    policy: OfflinePolicy,
+    capacity: usize,
+    queue: VecDeque<SignalingMessage>,
+    dropped: usize,
+}
+
+impl Outbox {
+    fn new(policy: OfflinePolicy, capacity: usize) -> Self {
+        Self {
+            policy,
+            capacity,
+            queue: VecDeque::new(),
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, msg: SignalingMessage) {
+        if self.policy == OfflinePolicy::Drop || self.capacity == 0 {
+            self.dropped += 1;
+            debug!("signaling offline, drop message: {:?}", msg);
+            return;
+        }
+        if self.queue.len() >= self.capacity {
+            self.queue.pop_front();
+            self.dropped += 1;
+        }
+        self.queue.push_back(msg);
+    }
+
+    // 发送失败的消息放回队首，保持顺序
+    fn push_front(&mut self, msg: SignalingMessage) {
+        if self.policy == OfflinePolicy::Buffer && self.capacity > 0 {
+            self.queue.push_front(msg);
+            // 超出容量时丢弃队尾较新的消息
+            self.dropped += self.queue.len().saturating_sub(self.capacity);
+            self.queue.truncate(self.capacity);
+        }
+    }
+
+    fn take_dropped(&mut self) -> usize {
+        std::mem::take(&mut self.dropped)
+    }
+}
+
+enum SessionEnd {
+    Disconnected, // 连接断开，需要重连
+    BusClosed,    // MessageBus 已退出，不再重连
+}
+
 // 从websocket接收消息，发送给messagebus，并从messagebus接收消息，发送给websocket
+// 连接断开后按退避策略重连并重新注册，MessageBus 及其中的 Bot 不受影响
 pub async fn run_signaling_client(
     bus_tx: mpsc::Sender<SignalingMessage>,
     mut ws_rx: mpsc::Receiver<SignalingMessage>,
 ) {
-    let url = Url::parse(&CONFIG.read().await.server.signaling_server).unwrap();
-    let url_str = url.as_str().into_client_request().unwrap();
+    let reconnect = CONFIG.read().await.server.reconnect.clone();
+    let mut backoff = Backoff::new(
+        Duration::from_millis(reconnect.initial_backoff_ms),
+        Duration::from_millis(reconnect.max_backoff_ms),
+    );
+    let mut outbox = Outbox::new(reconnect.offline_policy, reconnect.offline_buffer_size);
 
-    // 连接到 WebSocket 服务器
-    let (ws_stream, _) = connect_async(url_str).await.expect("Failed to connect");
-    info!("Connected to the server");
+    loop {
+        let url = CONFIG.read().await.server.signaling_server.clone();
+        match connect(&url).await {
+            Ok(ws_stream) => {
+                info!("Connected to the server {}, server id {}", url, *SERVER_ID);
+                backoff.reset();
+                match run_session(ws_stream, &bus_tx, &mut ws_rx, &mut outbox).await {
+                    SessionEnd::Disconnected => warn!("WebSocket connection closed"),
+                    SessionEnd::BusClosed => break,
+                }
+            }
+            Err(e) => error!("Failed to connect to signaling server {}: {}", url, e),
+        }
 
+        let delay = backoff.next_delay();
+        info!("reconnect to signaling server in {:?}", delay);
+        // 等待期间继续消费 ws_rx，避免 bot 发送阻塞
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => break,
+                msg = ws_rx.recv() => match msg {
+                    Some(msg) => outbox.push(msg),
+                    None => {
+                        warn!("message bus closed, stop signaling client");
+                        return;
+                    }
+                },
+            }
+        }
+    }
+
+    warn!("message bus closed, stop signaling client");
+}
+
+async fn connect(url: &str) -> Result<WsStream> {
+    let request = Url::parse(url)?.as_str().into_client_request()?;
+    let (ws_stream, _) = connect_async(request).await?;
+    Ok(ws_stream)
+}
+
+async fn run_session(
+    ws_stream: WsStream,
+    bus_tx: &mpsc::Sender<SignalingMessage>,
+    ws_rx: &mut mpsc::Receiver<SignalingMessage>,
+    outbox: &mut Outbox,
+) -> SessionEnd {
     let (mut write, mut read) = ws_stream.split();
 
-    // 注册为 RTC 服务器
+    // 注册为 RTC 服务器，每次重连都使用同一个 server id
     let register_msg = SignalingMessage::ServerRegister {
         server_id: SERVER_ID.to_string(),
     };
+    if let Err(e) = send_msg(&mut write, &register_msg).await {
+        error!("Failed to send register message: {}", e);
+        return SessionEnd::Disconnected;
+    }
 
-    write
-        .send(Message::Text(serde_json::to_string(&register_msg).unwrap()))
-        .await
-        .expect("Failed to send register message");
+    let dropped = outbox.take_dropped();
+    if dropped > 0 {
+        warn!("{} messages dropped while signaling was offline", dropped);
+    }
+    // 补发断线期间缓存的消息
+    while let Some(msg) = outbox.queue.pop_front() {
+        if let Err(e) = send_msg(&mut write, &msg).await {
+            error!("Failed to flush buffered message: {}", e);
+            outbox.push_front(msg);
+            return SessionEnd::Disconnected;
+        }
+    }
 
     // 使用 tokio::select! 同时处理 WebSocket 接收和 ws_rx 接收的消息
     loop {
         tokio::select! {
             // 处理从 WebSocket 接收的消息
-            Some(msg) = read.next() => {
+            msg = read.next() => {
                 match msg {
-                    Ok(Message::Text(text)) => {
+                    Some(Ok(Message::Text(text))) => {
                         match serde_json::from_str::<SignalingMessage>(&text) {
                             Ok(msg) => {
                                 if let Err(e) = bus_tx.send(msg).await {
                                     error!("Failed to send message to bus: {}", e);
+                                    return SessionEnd::BusClosed;
                                 }
                             }
                             Err(e) => error!("Failed to parse signaling message: {}", e),
                         }
                     }
-                    Err(e) => {
-                        error!("WebSocket error: {}", e);
-                        break;
+                    Some(Ok(Message::Close(frame))) => {
+                        warn!("WebSocket closed by server: {:?}", frame);
+                        return SessionEnd::Disconnected;
                     }
-                    _ => {
+                    Some(Ok(Message::Ping(_))) | Some(Ok(Message::Pong(_))) => {}
+                    Some(Ok(_)) => {
                         error!("Received unexpected message type");
                     }
+                    Some(Err(e)) => {
+                        error!("WebSocket error: {}", e);
+                        return SessionEnd::Disconnected;
+                    }
+                    None => return SessionEnd::Disconnected,
                 }
             }
             // 处理从 ws_rx 接收的消息并发送到 WebSocket
-            Some(msg) = ws_rx.recv() => {
+            msg = ws_rx.recv() => {
+                let Some(msg) = msg else {
+                    return SessionEnd::BusClosed;
+                };
                 debug!("Sending message through WebSocket: {:?}", msg);
-                match serde_json::to_string(&msg) {
-                    Ok(text) => {
-                        if let Err(e) = write.send(Message::Text(text)).await {
-                            error!("Failed to send message through WebSocket: {}", e);
-                        }
-                    }
-                    Err(e) => error!("Failed to serialize message: {}", e),
+                if let Err(e) = send_msg(&mut write, &msg).await {
+                    error!("Failed to send message through WebSocket: {}", e);
+                    outbox.push(msg);
+                    return SessionEnd::Disconnected;
                 }
             }
         }
     }
+}
 
-    error!("WebSocket connection closed");
+async fn send_msg(
+    write: &mut SplitSink<WsStream, Message>,
+    msg: &SignalingMessage,
+) -> Result<()> {
+    let text = serde_json::to_string(msg)?;
+    write.send(Message::Text(text)).await?;
+    Ok(())
 }
 
 // async fn handle_server_message(ws_stream: &mut tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>, msg: &str) {
@@ -228,3 +397,73 @@ pub async fn run_signaling_client(
 async fn main() {
     run_websocket_client().await;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(n: usize) -> SignalingMessage {
+        SignalingMessage::IceCandidate {
+            from: "bot".to_string(),
+            to: "client".to_string(),
+            candidate: n.to_string(),
+            corr_id: None,
+        }
+    }
+
+    #[test]
+    fn test_backoff_grows_within_bounds() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(1000));
+        let mut last_cap = Duration::ZERO;
+        for attempt in 0..10u32 {
+            let cap = Duration::from_millis((100u64 << attempt).min(1000));
+            let delay = backoff.next_delay();
+            assert!(delay >= cap / 2 && delay <= cap, "attempt {}: {:?}", attempt, delay);
+            assert!(cap >= last_cap);
+            last_cap = cap;
+        }
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_outbox_buffer_drops_oldest() {
+        let mut outbox = Outbox::new(OfflinePolicy::Buffer, 2);
+        for n in 0..3 {
+            outbox.push(candidate(n));
+        }
+        assert_eq!(outbox.take_dropped(), 1);
+        let kept: Vec<_> = outbox
+            .queue
+            .iter()
+            .map(|msg| match msg {
+                SignalingMessage::IceCandidate { candidate, .. } => candidate.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(kept, vec!["1", "2"]);
+    }
+
+    #[test]
+    fn test_outbox_push_front_counts_overflow() {
+        let mut outbox = Outbox::new(OfflinePolicy::Buffer, 2);
+        outbox.push(candidate(0));
+        outbox.push(candidate(1));
+        outbox.push_front(candidate(2));
+        assert_eq!(outbox.queue.len(), 2);
+        assert_eq!(outbox.take_dropped(), 1);
+        assert!(matches!(
+            outbox.queue.front(),
+            Some(SignalingMessage::IceCandidate { candidate, .. }) if candidate == "2"
+        ));
+    }
+
+    #[test]
+    fn test_outbox_drop_policy() {
+        let mut outbox = Outbox::new(OfflinePolicy::Drop, 16);
+        outbox.push(candidate(0));
+        outbox.push_front(candidate(1));
+        assert!(outbox.queue.is_empty());
+        assert_eq!(outbox.take_dropped(), 1);
+    }
+}
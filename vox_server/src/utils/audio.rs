@@ -0,0 +1,472 @@
+use std::f64::consts::PI;
+use std::time::Duration;
+
+// 每个输出采样在输入侧覆盖的滤波器长度（采样数），降采样时按比例加长
+const TAPS_PER_INPUT: usize = 64;
+// 截止频率相对于较低一侧奈奎斯特频率的比例
+const CUTOFF: f64 = 0.92;
+// Kaiser 窗参数，约 80dB 阻带衰减
+const KAISER_BETA: f64 = 8.0;
+
+pub fn i16_to_f32(pcm: &[i16]) -> Vec<f32> {
+    pcm.iter().map(|s| *s as f32 / 32768.0).collect()
+}
+
+pub fn f32_to_i16(pcm: &[f32]) -> Vec<i16> {
+    pcm.iter().map(|s| sample_to_i16(*s * 32768.0)).collect()
+}
+
+fn sample_to_i16(s: f32) -> i16 {
+    s.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+// 多声道取平均下混为单声道
+pub fn downmix_to_mono(pcm: &[i16], channels: u16) -> Vec<i16> {
+    let channels = channels.max(1) as usize;
+    if channels == 1 {
+        return pcm.to_vec();
+    }
+    pcm.chunks_exact(channels)
+        .map(|frame| {
+            let sum: i32 = frame.iter().map(|s| *s as i32).sum();
+            (sum / channels as i32) as i16
+        })
+        .collect()
+}
+
+// 单声道复制到每个声道
+pub fn upmix_mono(pcm: &[i16], channels: u16) -> Vec<i16> {
+    let channels = channels.max(1) as usize;
+    if channels == 1 {
+        return pcm.to_vec();
+    }
+    pcm.iter()
+        .flat_map(|s| std::iter::repeat_n(*s, channels))
+        .collect()
+}
+
+// 声道数转换，不同时先下混再复制
+pub fn remix(pcm: &[i16], from: u16, to: u16) -> Vec<i16> {
+    if from == to {
+        return pcm.to_vec();
+    }
+    upmix_mono(&downmix_to_mono(pcm, from), to)
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// 零阶修正贝塞尔函数，用于 Kaiser 窗
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..50 {
+        term *= half / k as f64;
+        sum += term * term;
+        if term * term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+// 流式多相重采样器（Kaiser 窗 sinc 低通），输入输出为交织 PCM。
+// 已补偿滤波器群延迟，输出与输入时间对齐；末尾调用 flush 取出剩余采样
+pub struct Resampler {
+    from: u32,
+    to: u32,
+    channels: usize,
+    up: u64,
+    down: u64,
+    taps: usize,
+    // phases[p][j] 对应 x[n - j]
+    phases: Vec<Vec<f32>>,
+    // 每个声道的输入缓存，buf[0] 对应输入序号 base
+    bufs: Vec<Vec<f32>>,
+    base: i64,
+    // 下一个输出在上采样域中的位置
+    next_pos: u64,
+    delay: u64,
+    input_frames: u64,
+    output_frames: u64,
+}
+
+impl Resampler {
+    pub fn new(from: u32, to: u32, channels: u16) -> Self {
+        let g = gcd(from as u64, to as u64).max(1);
+        let up = to as u64 / g;
+        let down = from as u64 / g;
+        let taps = TAPS_PER_INPUT * (from as usize).div_ceil(to as usize).max(1);
+        let len = taps * up as usize;
+        // 奇数长度使群延迟为整数，多出的一个系数置零
+        let odd = len - (1 - len % 2);
+
+        // 在上采样域设计原型低通
+        let fc = CUTOFF * from.min(to) as f64 / 2.0 / (from as f64 * up as f64);
+        let center = (odd - 1) / 2;
+        let norm = bessel_i0(KAISER_BETA);
+        let proto: Vec<f64> = (0..len)
+            .map(|i| {
+                if i >= odd {
+                    return 0.0;
+                }
+                let t = i as f64 - center as f64;
+                let sinc = if t == 0.0 {
+                    2.0 * fc
+                } else {
+                    (2.0 * PI * fc * t).sin() / (PI * t)
+                };
+                let r = 2.0 * t / (odd - 1) as f64;
+                sinc * bessel_i0(KAISER_BETA * (1.0 - r * r).max(0.0).sqrt()) / norm
+            })
+            .collect();
+
+        // 拆成多相，每相单独归一化保证直流增益为 1
+        let phases = (0..up as usize)
+            .map(|p| {
+                let phase: Vec<f64> = (0..taps).map(|j| proto[p + j * up as usize]).collect();
+                let sum: f64 = phase.iter().sum();
+                phase.iter().map(|h| (h / sum) as f32).collect()
+            })
+            .collect();
+
+        let channels = channels.max(1) as usize;
+        Self {
+            from,
+            to,
+            channels,
+            up,
+            down,
+            taps,
+            phases,
+            bufs: vec![vec![0.0; taps - 1]; channels],
+            base: -(taps as i64 - 1),
+            next_pos: center as u64,
+            delay: center as u64,
+            input_frames: 0,
+            output_frames: 0,
+        }
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.from == self.to
+    }
+
+    pub fn process(&mut self, pcm: &[i16]) -> Vec<i16> {
+        if self.is_passthrough() {
+            return pcm.to_vec();
+        }
+        for frame in pcm.chunks_exact(self.channels) {
+            for (ch, s) in frame.iter().enumerate() {
+                self.bufs[ch].push(*s as f32);
+            }
+        }
+        self.input_frames += (pcm.len() / self.channels) as u64;
+        self.drain(self.input_frames)
+    }
+
+    // 补零取出尾部，输出总数与输入时长对应，之后可继续使用
+    pub fn flush(&mut self) -> Vec<i16> {
+        if self.is_passthrough() {
+            return Vec::new();
+        }
+        let expected = self.input_frames * self.to as u64 / self.from as u64;
+        let mut out = Vec::new();
+        while self.output_frames < expected {
+            for buf in self.bufs.iter_mut() {
+                buf.resize(buf.len() + self.taps, 0.0);
+            }
+            let available = (self.base + self.bufs[0].len() as i64) as u64;
+            out.extend(self.drain(available));
+        }
+        let extra = (self.output_frames - expected) as usize * self.channels;
+        out.truncate(out.len() - extra);
+        self.reset();
+        out
+    }
+
+    pub fn reset(&mut self) {
+        for buf in self.bufs.iter_mut() {
+            buf.clear();
+            buf.resize(self.taps - 1, 0.0);
+        }
+        self.base = -(self.taps as i64 - 1);
+        self.next_pos = self.delay;
+        self.input_frames = 0;
+        self.output_frames = 0;
+    }
+
+    // 生成所有依赖的输入序号小于 available 的输出
+    fn drain(&mut self, available: u64) -> Vec<i16> {
+        let mut out = Vec::new();
+        loop {
+            let n = (self.next_pos / self.up) as i64;
+            if n >= available as i64 {
+                break;
+            }
+            let phase = &self.phases[(self.next_pos % self.up) as usize];
+            let idx = (n - self.base) as usize;
+            for buf in &self.bufs {
+                let mut acc = 0.0f32;
+                for (j, h) in phase.iter().enumerate() {
+                    acc += h * buf[idx - j];
+                }
+                out.push(sample_to_i16(acc));
+            }
+            self.next_pos += self.down;
+            self.output_frames += 1;
+        }
+
+        // 丢弃后续输出不再需要的历史
+        let keep_from = (self.next_pos / self.up) as i64 - (self.taps as i64 - 1);
+        if keep_from > self.base {
+            let drop = ((keep_from - self.base) as usize).min(self.bufs[0].len());
+            for buf in self.bufs.iter_mut() {
+                buf.drain(..drop);
+            }
+            self.base += drop as i64;
+        }
+        out
+    }
+}
+
+// 采样率与声道数转换，声道减少时先下混以减少重采样计算
+pub struct AudioConverter {
+    from_channels: u16,
+    to_channels: u16,
+    resampler: Resampler,
+}
+
+impl AudioConverter {
+    pub fn new(from_rate: u32, from_channels: u16, to_rate: u32, to_channels: u16) -> Self {
+        let channels = from_channels.min(to_channels).max(1);
+        Self {
+            from_channels,
+            to_channels,
+            resampler: Resampler::new(from_rate, to_rate, channels),
+        }
+    }
+
+    pub fn is_passthrough(&self) -> bool {
+        self.from_channels == self.to_channels && self.resampler.is_passthrough()
+    }
+
+    pub fn process(&mut self, pcm: &[i16]) -> Vec<i16> {
+        if self.is_passthrough() {
+            return pcm.to_vec();
+        }
+        let mid = self.from_channels.min(self.to_channels).max(1);
+        let pcm = remix(pcm, self.from_channels, mid);
+        let pcm = self.resampler.process(&pcm);
+        remix(&pcm, mid, self.to_channels)
+    }
+
+    pub fn flush(&mut self) -> Vec<i16> {
+        let mid = self.from_channels.min(self.to_channels).max(1);
+        let pcm = self.resampler.flush();
+        remix(&pcm, mid, self.to_channels)
+    }
+}
+
+// 一次性转换整段音频
+pub fn convert(
+    pcm: &[i16],
+    from_rate: u32,
+    from_channels: u16,
+    to_rate: u32,
+    to_channels: u16,
+) -> Vec<i16> {
+    let mut converter = AudioConverter::new(from_rate, from_channels, to_rate, to_channels);
+    let mut out = converter.process(pcm);
+    out.extend(converter.flush());
+    out
+}
+
+// 把任意长度的 PCM 切成固定长度的帧，不足一帧的部分留到下次
+pub struct Rechunker {
+    frame_len: usize,
+    buf: Vec<i16>,
+}
+
+impl Rechunker {
+    pub fn new(frame_len: usize) -> Self {
+        Self {
+            frame_len,
+            buf: Vec::new(),
+        }
+    }
+
+    // 指定时长的帧（交织后的采样数）
+    pub fn for_duration(sample_rate: u32, channels: u16, duration: Duration) -> Self {
+        let per_channel = sample_rate as usize * duration.as_millis() as usize / 1000;
+        Self::new(per_channel * channels as usize)
+    }
+
+    pub fn push(&mut self, pcm: &[i16]) {
+        self.buf.extend_from_slice(pcm);
+    }
+
+    pub fn pop_frame(&mut self) -> Option<Vec<i16>> {
+        if self.buf.len() < self.frame_len {
+            return None;
+        }
+        let rest = self.buf.split_off(self.frame_len);
+        Some(std::mem::replace(&mut self.buf, rest))
+    }
+
+    // 结束时把剩余部分补零成完整一帧
+    pub fn flush(&mut self) -> Option<Vec<i16>> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        let mut frame = std::mem::take(&mut self.buf);
+        frame.resize(self.frame_len, 0);
+        Some(frame)
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    pub fn buffered_frames(&self) -> usize {
+        self.buf.len() / self.frame_len
+    }
+
+    pub fn frame_len(&self) -> usize {
+        self.frame_len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(freq: f64, rate: u32, frames: usize, amp: f64) -> Vec<i16> {
+        (0..frames)
+            .map(|i| (amp * (2.0 * PI * freq * i as f64 / rate as f64).sin()).round() as i16)
+            .collect()
+    }
+
+    // 跳过首尾各 skip 个采样，计算相对理想正弦的信噪比
+    fn snr_db(out: &[i16], freq: f64, rate: u32, amp: f64, skip: usize) -> f64 {
+        let ideal = sine(freq, rate, out.len(), amp);
+        let (mut signal, mut noise) = (0.0, 0.0);
+        for i in skip..out.len() - skip {
+            signal += (ideal[i] as f64).powi(2);
+            noise += (out[i] as f64 - ideal[i] as f64).powi(2);
+        }
+        10.0 * (signal / noise.max(1e-9)).log10()
+    }
+
+    fn rms(pcm: &[i16]) -> f64 {
+        (pcm.iter().map(|s| (*s as f64).powi(2)).sum::<f64>() / pcm.len() as f64).sqrt()
+    }
+
+    #[test]
+    fn test_resample_sine_accuracy() {
+        for (from, to) in [
+            (48000, 16000),
+            (16000, 48000),
+            (24000, 48000),
+            (48000, 24000),
+            (48000, 8000),
+            (8000, 16000),
+            (24000, 16000),
+        ] {
+            let input = sine(1000.0, from, from as usize / 2, 10000.0);
+            let out = convert(&input, from, 1, to, 1);
+            assert_eq!(out.len(), to as usize / 2, "{} -> {}", from, to);
+            let snr = snr_db(&out, 1000.0, to, 10000.0, to as usize / 50);
+            assert!(snr > 60.0, "{} -> {} snr {:.1}dB", from, to, snr);
+        }
+    }
+
+    #[test]
+    fn test_resample_rejects_aliasing() {
+        // 10kHz 超出 16kHz 的奈奎斯特频率，应被滤除
+        let input = sine(10000.0, 48000, 24000, 10000.0);
+        let out = convert(&input, 48000, 1, 16000, 1);
+        let tail = &out[800..out.len() - 800];
+        assert!(rms(tail) < 10.0, "alias rms {}", rms(tail));
+    }
+
+    #[test]
+    fn test_streaming_matches_one_shot() {
+        let input: Vec<i16> = sine(440.0, 48000, 4800, 8000.0)
+            .into_iter()
+            .flat_map(|s| [s, s / 2])
+            .collect();
+        let expected = convert(&input, 48000, 2, 16000, 2);
+
+        let mut resampler = Resampler::new(48000, 16000, 2);
+        let mut out = Vec::new();
+        // 不规则分块，包括奇数帧
+        for chunk in input.chunks(2 * 137) {
+            out.extend(resampler.process(chunk));
+        }
+        out.extend(resampler.flush());
+        assert_eq!(out, expected);
+        assert_eq!(out.len(), 1600 * 2);
+    }
+
+    #[test]
+    fn test_converter_channels() {
+        let stereo: Vec<i16> = [100i16, 300].repeat(480);
+        let mut converter = AudioConverter::new(48000, 2, 48000, 1);
+        assert_eq!(converter.process(&stereo), vec![200; 480]);
+
+        let mut converter = AudioConverter::new(16000, 1, 48000, 2);
+        let mut out = converter.process(&vec![1000; 1600]);
+        out.extend(converter.flush());
+        assert_eq!(out.len(), 4800 * 2);
+        // 稳态直流不变，两个声道一致
+        assert!(out[2000..8000].iter().all(|s| *s == 1000));
+    }
+
+    #[test]
+    fn test_sample_format_conversion() {
+        let pcm = vec![i16::MIN, -16384, 0, 16384, i16::MAX];
+        let float = i16_to_f32(&pcm);
+        assert_eq!(float[0], -1.0);
+        assert_eq!(float[3], 0.5);
+        assert_eq!(f32_to_i16(&float), pcm);
+        assert_eq!(f32_to_i16(&[1.5, -1.5]), vec![i16::MAX, i16::MIN]);
+
+        assert_eq!(downmix_to_mono(&[100, 300, -100, -300], 2), vec![200, -200]);
+        assert_eq!(upmix_mono(&[1, 2], 2), vec![1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn test_rechunk_exact_frames() {
+        let mut chunker = Rechunker::for_duration(48000, 1, Duration::from_millis(20));
+        assert_eq!(chunker.frame_len(), 960);
+
+        chunker.push(&vec![1i16; 500]);
+        assert!(chunker.pop_frame().is_none());
+        chunker.push(&vec![2i16; 1500]);
+        assert_eq!(chunker.buffered_frames(), 2);
+
+        let first = chunker.pop_frame().unwrap();
+        assert_eq!(first.len(), 960);
+        assert_eq!(first[499], 1);
+        assert_eq!(first[500], 2);
+        assert_eq!(chunker.pop_frame().unwrap().len(), 960);
+        assert!(chunker.pop_frame().is_none());
+
+        // 剩余 80 个采样补零
+        let last = chunker.flush().unwrap();
+        assert_eq!(last.len(), 960);
+        assert_eq!(last[79], 2);
+        assert_eq!(last[80], 0);
+        assert!(chunker.flush().is_none());
+
+        let stereo = Rechunker::for_duration(16000, 2, Duration::from_millis(32));
+        assert_eq!(stereo.frame_len(), 1024);
+    }
+}
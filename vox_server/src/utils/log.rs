@@ -1,18 +1,73 @@
-use once_cell::sync::Lazy;
-use slog::{o, Drain, Logger};
+use once_cell::sync::{Lazy, OnceCell};
+use slog::{o, Drain, Level, LevelFilter, Logger};
 
+use std::fs::{self, File, OpenOptions};
+use std::path::Path;
+use std::str::FromStr;
 use std::sync::Mutex;
 
-pub static GLOBAL_LOGGER: Lazy<Logger> = Lazy::new(|| {
+use crate::config::LogConfig;
+
+static LOG_CONFIG: OnceCell<LogConfig> = OnceCell::new();
+
+pub static GLOBAL_LOGGER: Lazy<Logger> = Lazy::new(|| build_logger(LOG_CONFIG.get()));
+
+// 启动时在第一次打日志前调用，之后的调用无效
+pub fn init(cfg: &LogConfig) -> bool {
+    LOG_CONFIG.set(cfg.clone()).is_ok()
+}
+
+// 终端输出，配置了 path 时同时以 json 写入 path/vox_server.log
+fn build_logger(cfg: Option<&LogConfig>) -> Logger {
+    let level = cfg
+        .and_then(|c| Level::from_str(&c.level).ok())
+        .unwrap_or(Level::Info);
     let decorator = slog_term::TermDecorator::new().build();
-    let drain = slog_term::FullFormat::new(decorator).build().fuse();
-    let drain = Mutex::new(drain).fuse();
-    Logger::root(drain, o!())
-});
+    let term = slog_term::FullFormat::new(decorator).build().fuse();
+
+    match cfg.and_then(|c| open_log_file(&c.path)) {
+        Some(file) => {
+            let json = slog_json::Json::default(file).fuse();
+            let drain = Mutex::new(slog::Duplicate::new(term, json).fuse()).fuse();
+            Logger::root(LevelFilter::new(drain, level).fuse(), o!())
+        }
+        None => {
+            let drain = Mutex::new(term).fuse();
+            Logger::root(LevelFilter::new(drain, level).fuse(), o!())
+        }
+    }
+}
+
+fn open_log_file(dir: &str) -> Option<File> {
+    if dir.is_empty() {
+        return None;
+    }
+    let opened = fs::create_dir_all(dir).and_then(|_| {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Path::new(dir).join("vox_server.log"))
+    });
+    match opened {
+        Ok(file) => Some(file),
+        Err(e) => {
+            eprintln!("failed to open log file in {}: {}", dir, e);
+            None
+        }
+    }
+}
+
+// 以关联ID创建子logger，同一次呼叫的日志都带上 corr_id 与 client_id
+pub fn session_logger(corr_id: &str, client_id: &str) -> Logger {
+    GLOBAL_LOGGER.new(o!("corr_id" => corr_id.to_string(), "client_id" => client_id.to_string()))
+}
 
-// 定义自己的宏
+// 定义自己的宏，`info!(log: logger, ...)` 形式使用指定的logger
 #[macro_export]
 macro_rules! info {
+    (log: $logger:expr, $($arg:tt)+) => {
+        slog::info!($logger, $($arg)+; "file" => file!(), "line" => line!())
+    };
     ($($arg:tt)+) => {
         slog::info!($crate::utils::log::GLOBAL_LOGGER, $($arg)+; "file" => file!(), "line" => line!())
     }
@@ -20,6 +75,9 @@ macro_rules! info {
 
 #[macro_export]
 macro_rules! debug {
+    (log: $logger:expr, $($arg:tt)+) => {
+        slog::debug!($logger, $($arg)+; "file" => file!(), "line" => line!())
+    };
     ($($arg:tt)+) => {
         slog::debug!($crate::utils::log::GLOBAL_LOGGER, $($arg)+; "file" => file!(), "line" => line!())
     }
@@ -27,6 +85,9 @@ macro_rules! debug {
 
 #[macro_export]
 macro_rules! error {
+    (log: $logger:expr, $($arg:tt)+) => {
+        slog::error!($logger, $($arg)+; "file" => file!(), "line" => line!())
+    };
     ($($arg:tt)+) => {
         slog::error!($crate::utils::log::GLOBAL_LOGGER, $($arg)+; "file" => file!(), "line" => line!())
     }
@@ -34,6 +95,9 @@ macro_rules! error {
 
 #[macro_export]
 macro_rules! warn {
+    (log: $logger:expr, $($arg:tt)+) => {
+        slog::warn!($logger, $($arg)+; "file" => file!(), "line" => line!())
+    };
     ($($arg:tt)+) => {
         slog::warn!($crate::utils::log::GLOBAL_LOGGER, $($arg)+; "file" => file!(), "line" => line!())
     }
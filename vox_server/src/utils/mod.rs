@@ -1,2 +1,3 @@
+pub mod audio;
 pub mod config;
 pub mod log;
@@ -0,0 +1,231 @@
+use std::collections::hash_map::{Entry, HashMap};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use vox_verse::config::RtcConfig;
+use vox_verse::server::rtc::{ice_tcp, network};
+use webrtc::api::interceptor_registry::register_default_interceptors;
+use webrtc::api::media_engine::MediaEngine;
+use webrtc::api::setting_engine::SettingEngine;
+use webrtc::api::APIBuilder;
+use webrtc::ice::udp_mux::UDPMux;
+use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
+use webrtc::interceptor::registry::Registry;
+use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
+use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::RTCPeerConnection;
+
+async fn new_peer(mut se: SettingEngine) -> Arc<RTCPeerConnection> {
+    // 与 main 一致：rustls 同时启用了两种实现，DTLS 握手前要选定一个
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+    // 测试环境可能只有回环网卡
+    se.set_include_loopback_candidate(true);
+    let mut media_engine = MediaEngine::default();
+    media_engine.register_default_codecs().unwrap();
+    let registry = register_default_interceptors(Registry::new(), &mut media_engine).unwrap();
+    let api = APIBuilder::new()
+        .with_media_engine(media_engine)
+        .with_interceptor_registry(registry)
+        .with_setting_engine(se)
+        .build();
+    Arc::new(
+        api.new_peer_connection(RTCConfiguration::default())
+            .await
+            .unwrap(),
+    )
+}
+
+async fn wait_connected(pc: &Arc<RTCPeerConnection>) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    pc.on_peer_connection_state_change(Box::new(move |state| {
+        let _ = tx.send(state);
+        Box::pin(async {})
+    }));
+    if pc.connection_state() == RTCPeerConnectionState::Connected {
+        return;
+    }
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while let Some(state) = rx.recv().await {
+            assert_ne!(state, RTCPeerConnectionState::Failed);
+            if state == RTCPeerConnectionState::Connected {
+                return;
+            }
+        }
+    })
+    .await
+    .expect("peer connection not connected in time");
+}
+
+fn candidate_ports(desc: &RTCSessionDescription) -> Vec<u16> {
+    desc.sdp
+        .lines()
+        .filter(|line| line.starts_with("a=candidate:"))
+        .filter_map(|line| line.split_whitespace().nth(5))
+        .map(|port| port.parse().unwrap())
+        .collect()
+}
+
+// 去掉描述中的候选，对端只能通过之后单独添加的候选或 peer-reflexive 地址连通
+fn without_candidates(desc: RTCSessionDescription) -> RTCSessionDescription {
+    let sdp = desc
+        .sdp
+        .lines()
+        .filter(|line| !line.starts_with("a=candidate:"))
+        .map(|line| format!("{}\r\n", line))
+        .collect();
+    match desc.sdp_type {
+        RTCSdpType::Offer => RTCSessionDescription::offer(sdp).unwrap(),
+        _ => RTCSessionDescription::answer(sdp).unwrap(),
+    }
+}
+
+// 完成一次非 trickle 的 offer/answer，返回 bot 端的 answer；strip 为 true 时双方都收不到对方的候选
+async fn negotiate(
+    client: &Arc<RTCPeerConnection>,
+    bot: &Arc<RTCPeerConnection>,
+    strip: bool,
+) -> RTCSessionDescription {
+    let strip = |desc| {
+        if strip {
+            without_candidates(desc)
+        } else {
+            desc
+        }
+    };
+
+    client.create_data_channel("probe", None).await.unwrap();
+    let offer = client.create_offer(None).await.unwrap();
+    let mut gathered = client.gathering_complete_promise().await;
+    client.set_local_description(offer).await.unwrap();
+    let _ = gathered.recv().await;
+
+    bot.set_remote_description(strip(client.local_description().await.unwrap()))
+        .await
+        .unwrap();
+    let answer = bot.create_answer(None).await.unwrap();
+    let mut gathered = bot.gathering_complete_promise().await;
+    bot.set_local_description(answer).await.unwrap();
+    let _ = gathered.recv().await;
+
+    let answer = bot.local_description().await.unwrap();
+    client
+        .set_remote_description(strip(answer.clone()))
+        .await
+        .unwrap();
+    answer
+}
+
+// 模拟浏览器的 ICE-TCP active 端：客户端发到这个 UDP 端口的包按源地址各开一条 TCP 连接，
+// 以 RFC 4571 分帧发给 bot 的 ICE-TCP 端口，返回的包再发回对应的源地址
+async fn tcp_active_proxy(tcp_addr: SocketAddr) -> u16 {
+    let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let port = socket.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        let mut conns: HashMap<SocketAddr, OwnedWriteHalf> = HashMap::new();
+        let mut buf = vec![0u8; 1500];
+        while let Ok((n, from)) = socket.recv_from(&mut buf).await {
+            let writer = match conns.entry(from) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    entry.insert(connect_tcp(tcp_addr, socket.clone(), from).await)
+                }
+            };
+            let _ = writer.write_all(&ice_tcp::frame(&buf[..n])).await;
+        }
+    });
+    port
+}
+
+// 一条 ICE-TCP 连接，收到的包拆帧后发回 UDP 源地址
+async fn connect_tcp(
+    tcp_addr: SocketAddr,
+    socket: Arc<UdpSocket>,
+    from: SocketAddr,
+) -> OwnedWriteHalf {
+    let (mut reader, writer) = TcpStream::connect(tcp_addr).await.unwrap().into_split();
+    tokio::spawn(async move {
+        let mut packet = Vec::new();
+        while ice_tcp::read_frame(&mut reader, &mut packet).await.is_ok() {
+            let _ = socket.send_to(&packet, from).await;
+        }
+    });
+    writer
+}
+
+fn free_udp_port() -> u16 {
+    std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[tokio::test]
+async fn test_bots_share_one_udp_port() {
+    let port = free_udp_port();
+    let mux = network::bind_udp_mux(port).await.unwrap();
+    let cfg = RtcConfig::default();
+
+    let mut pairs = Vec::new();
+    for _ in 0..2 {
+        let udp_mux: Arc<dyn UDPMux + Send + Sync> = mux.clone();
+        let bot_se = network::build_setting_engine(&cfg, Some(udp_mux)).unwrap();
+        let bot = new_peer(bot_se).await;
+        let client = new_peer(SettingEngine::default()).await;
+
+        let ports = candidate_ports(&negotiate(&client, &bot, false).await);
+        assert!(!ports.is_empty());
+        assert!(ports.iter().all(|p| *p == port), "ports {:?}", ports);
+        pairs.push((client, bot));
+    }
+
+    for (client, bot) in &pairs {
+        wait_connected(bot).await;
+        wait_connected(client).await;
+    }
+    for (client, bot) in pairs {
+        client.close().await.unwrap();
+        bot.close().await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_client_over_ice_tcp() {
+    let port = free_udp_port();
+    let mux = network::bind_udp_mux(port).await.unwrap();
+    let tcp_addr = ice_tcp::serve(0, SocketAddr::from((Ipv4Addr::LOCALHOST, port)))
+        .await
+        .unwrap();
+
+    let bot_se = network::build_setting_engine(&RtcConfig::default(), Some(mux)).unwrap();
+    let bot = new_peer(bot_se).await;
+    let client = new_peer(SettingEngine::default()).await;
+
+    // 双方的 UDP 候选都不交换，客户端唯一的远端候选是 ICE-TCP 转发端，
+    // bot 只能从 TCP 连接转发来的包得到 peer-reflexive 候选
+    negotiate(&client, &bot, true).await;
+    let proxy_port =
+        tcp_active_proxy(SocketAddr::from((Ipv4Addr::LOCALHOST, tcp_addr.port()))).await;
+    client
+        .add_ice_candidate(RTCIceCandidateInit {
+            candidate: format!(
+                "candidate:1 1 udp 2130706431 127.0.0.1 {} typ host",
+                proxy_port
+            ),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    wait_connected(&bot).await;
+    wait_connected(&client).await;
+    client.close().await.unwrap();
+    bot.close().await.unwrap();
+}